@@ -116,6 +116,7 @@ fn gfx_main(glfw: glfw::Glfw,
         levels: 1,
         kind: gfx::tex::Texture2D,
         format: gfx::tex::RGBA8,
+        swizzle: gfx::tex::IDENTITY_SWIZZLE,
     };
     let image_info = texture_info.to_image_info();
     let texture = device.create_texture(texture_info).unwrap();
@@ -93,12 +93,19 @@ pub trait CommandBuffer {
     fn bind_target_surface(&mut self, target::Access, target::Target, back::Surface);
     /// Bind a level of the texture to the specified target slot
     fn bind_target_texture(&mut self, target::Access, target::Target, back::Texture,
-                           target::Level, Option<target::Layer>);
+                           tex::TextureKind, target::Level, Option<target::Layer>);
     fn bind_uniform_block(&mut self, back::Program, ::UniformBufferSlot,
                           ::UniformBlockIndex, back::Buffer);
     fn bind_uniform(&mut self, shade::Location, shade::UniformValue);
     fn bind_texture(&mut self, ::TextureSlot, tex::TextureKind, back::Texture,
                     Option<::SamplerHandle>);
+    fn bind_storage_block(&mut self, back::Program, ::StorageBufferSlot,
+                          ::StorageBlockIndex, back::Buffer);
+    /// Bind a level of the texture (or a single layer of it) as an image
+    fn bind_image(&mut self, ::ImageSlot, back::Texture, target::Level,
+                  Option<target::Layer>, tex::ImageAccess, tex::Format);
+    /// Make the incoherent shader writes visible to the given accesses
+    fn memory_barrier(&mut self, ::Barrier);
     fn set_primitive(&mut self, ::state::Primitive);
     fn set_viewport(&mut self, target::Rect);
     fn set_multi_sample(&mut self, Option<::state::MultiSample>);
@@ -107,6 +114,8 @@ pub trait CommandBuffer {
                          Option<::state::Stencil>, ::state::CullMode);
     fn set_blend(&mut self, Option<::state::Blend>);
     fn set_color_mask(&mut self, ::state::ColorMask);
+    /// Enable or disable the sRGB encoding of the values written to sRGB planes
+    fn set_frame_buffer_srgb(&mut self, bool);
     fn update_buffer(&mut self, back::Buffer, DataPointer, uint);
     fn update_texture(&mut self, tex::TextureKind, back::Texture,
                       tex::ImageInfo, DataPointer);
@@ -30,6 +30,8 @@ extern crate libc;
 pub use self::gl_device as back;
 
 use std::mem;
+use std::sync::Arc;
+use std::sync::atomic::AtomicUint;
 
 pub mod attrib;
 pub mod draw;
@@ -54,6 +56,12 @@ pub type AttributeSlot = u8;
 pub type UniformBufferSlot = u8;
 /// Slot a texture can be bound to.
 pub type TextureSlot = u8;
+/// Index of a shader storage block.
+pub type StorageBlockIndex = u8;
+/// Slot for a shader storage buffer object.
+pub type StorageBufferSlot = u8;
+/// Slot a texture level can be bound to as an image.
+pub type ImageSlot = u8;
 
 /// Specifies the access allowed to a buffer mapping.
 pub enum MapAccess {
@@ -224,6 +232,16 @@ pub fn make_fake_buffer<T>() -> BufferHandle<T> {
     BufferHandle::from_raw(Handle(0, info))
 }
 
+/// A helper method to test batches without GL context
+pub fn make_fake_program(name: back::Program, info: shade::ProgramInfo) -> ProgramHandle {
+    Handle(name, info)
+}
+
+/// A helper method to test render targets without GL context
+pub fn make_fake_texture(name: back::Texture, info: tex::TextureInfo) -> TextureHandle {
+    Handle(name, info)
+}
+
 /// Return the framebuffer handle for the screen.
 pub fn get_main_frame_buffer() -> FrameBufferHandle {
     Handle(0, ())
@@ -247,6 +265,8 @@ pub struct Capabilities {
     pub max_draw_buffers : uint,
     pub max_texture_size : uint,
     pub max_vertex_attributes: uint,
+    pub max_texture_buffer_size: uint,
+    pub max_anisotropy: uint,
     pub uniform_block_supported: bool,
     pub array_buffer_supported: bool,
     pub sampler_objects_supported: bool,
@@ -256,6 +276,15 @@ pub struct Capabilities {
     pub render_targets_supported: bool,
     pub vertex_base_supported: bool,
     pub instance_base_supported: bool,
+    pub srgb_frame_buffer_supported: bool,
+    pub texture_swizzle_supported: bool,
+    pub texture_view_supported: bool,
+    pub texture_buffer_supported: bool,
+    pub texture_cube_array_supported: bool,
+    pub image_load_store_supported: bool,
+    pub storage_buffer_supported: bool,
+    pub program_binary_supported: bool,
+    pub spirv_supported: bool,
 }
 
 /// Describes what geometric primitives are created from vertex data.
@@ -312,6 +341,31 @@ pub struct BufferInfo {
     pub size: uint,
 }
 
+bitflags!(
+    /// Kinds of accesses that need to see the results of the incoherent
+    /// shader writes (to images and storage buffers) before a memory barrier.
+    #[allow(missing_docs)]
+    flags Barrier: u32 {
+        const BARRIER_VERTEX_ATTRIB  = 0x001,
+        const BARRIER_INDEX          = 0x002,
+        const BARRIER_UNIFORM        = 0x004,
+        const BARRIER_TEXTURE_FETCH  = 0x008,
+        const BARRIER_IMAGE_ACCESS   = 0x010,
+        const BARRIER_COMMAND        = 0x020,
+        const BARRIER_BUFFER_UPDATE  = 0x040,
+        const BARRIER_TEXTURE_UPDATE = 0x080,
+        const BARRIER_FRAME_BUFFER   = 0x100,
+        const BARRIER_STORAGE        = 0x200,
+        const BARRIER_ALL            = 0x3FF
+    }
+)
+
+impl std::fmt::Show for Barrier {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Barrier({})", self.bits())
+    }
+}
+
 /// Serialized device command.
 /// While this is supposed to be an internal detail of a device,
 /// this particular representation may be used by different backends,
@@ -330,10 +384,16 @@ pub enum Command {
     BindTargetSurface(target::Access, target::Target, back::Surface),
     /// Bind a level of the texture to the specified target slot
     BindTargetTexture(target::Access, target::Target, back::Texture,
-                      target::Level, Option<target::Layer>),
+                      tex::TextureKind, target::Level, Option<target::Layer>),
     BindUniformBlock(back::Program, UniformBufferSlot, UniformBlockIndex, back::Buffer),
     BindUniform(shade::Location, shade::UniformValue),
     BindTexture(TextureSlot, tex::TextureKind, back::Texture, Option<SamplerHandle>),
+    BindStorageBlock(back::Program, StorageBufferSlot, StorageBlockIndex, back::Buffer),
+    /// Bind a level of the texture (or a single layer of it) as an image
+    BindImage(ImageSlot, back::Texture, target::Level, Option<target::Layer>,
+              tex::ImageAccess, tex::Format),
+    /// Make the incoherent shader writes visible to the given accesses
+    MemoryBarrier(Barrier),
     SetPrimitiveState(state::Primitive),
     SetViewport(target::Rect),
     SetMultiSampleState(Option<state::MultiSample>),
@@ -341,6 +401,8 @@ pub enum Command {
     SetDepthStencilState(Option<state::Depth>, Option<state::Stencil>, state::CullMode),
     SetBlendState(Option<state::Blend>),
     SetColorMask(state::ColorMask),
+    /// Enable or disable the sRGB encoding of the values written to sRGB planes
+    SetFrameBufferSrgb(bool),
     UpdateBuffer(back::Buffer, draw::DataPointer, uint),
     UpdateTexture(tex::TextureKind, back::Texture, tex::ImageInfo, draw::DataPointer),
     // drawing
@@ -357,6 +419,9 @@ pub enum Command {
 pub trait Device<C: draw::CommandBuffer> {
     /// Returns the capabilities available to the specific API implementation
     fn get_capabilities<'a>(&'a self) -> &'a Capabilities;
+    /// Returns the number of programs and textures deleted so far, which
+    /// tells the caches keyed on their names that the names may be reused
+    fn get_deletions(&self) -> Arc<AtomicUint>;
     /// Reset all the states to disabled/default
     fn reset_state(&mut self);
     /// Submit a command buffer for execution
@@ -374,10 +439,29 @@ pub trait Device<C: draw::CommandBuffer> {
     fn create_array_buffer(&mut self) -> Result<ArrayBufferHandle, ()>;
     fn create_shader(&mut self, stage: shade::Stage, code: shade::ShaderSource) ->
                      Result<ShaderHandle, shade::CreateShaderError>;
-    fn create_program(&mut self, shaders: &[ShaderHandle]) -> Result<ProgramHandle, ()>;
+    /// Link the shaders into a program, returning the parsed link log on failure.
+    fn create_program(&mut self, shaders: &[ShaderHandle]) -> Result<ProgramHandle, shade::ShaderLog>;
+    /// Load a program from a binary previously returned by `get_program_binary`.
+    /// Fails if the driver rejects the binary, in which case the program has
+    /// to be linked from its shaders again.
+    fn create_program_binary(&mut self, binary: &shade::ProgramBinary)
+                             -> Result<ProgramHandle, shade::ShaderLog>;
     fn create_frame_buffer(&mut self) -> FrameBufferHandle;
     fn create_surface(&mut self, info: tex::SurfaceInfo) -> Result<SurfaceHandle, tex::SurfaceError>;
     fn create_texture(&mut self, info: tex::TextureInfo) -> Result<TextureHandle, tex::TextureError>;
+    /// Create a view into the storage of an existing texture
+    fn create_texture_view(&mut self, tex: &TextureHandle, view: tex::ViewInfo)
+                           -> Result<TextureHandle, tex::TextureError>;
+    /// Create a buffer texture, exposing the contents of a buffer to the
+    /// shaders as texels of the given format
+    fn create_texture_buffer_raw(&mut self, buf: BufferHandle<()>, format: tex::Format)
+                                 -> Result<TextureHandle, tex::TextureError>;
+    fn create_texture_buffer<T>(&mut self, buf: BufferHandle<T>, format: tex::Format)
+                                -> Result<TextureHandle, tex::TextureError> {
+        self.create_texture_buffer_raw(buf.cast(), format)
+    }
+    /// Create a sampler, or share the one created for an equal description.
+    /// Every handle returned needs to be deleted to release the sampler.
     fn create_sampler(&mut self, info: tex::SamplerInfo) -> SamplerHandle;
 
     // resource deletion
@@ -417,6 +501,10 @@ pub trait Device<C: draw::CommandBuffer> {
         with_slice(data, |s| self.update_texture_raw(tex, img, s))
     }
     fn generate_mipmap(&mut self, tex: &TextureHandle);
+
+    /// Retrieve the binary of a linked program, to be cached and loaded later
+    /// with `create_program_binary`. Returns `None` if not supported.
+    fn get_program_binary(&mut self, prog: &ProgramHandle) -> Option<shade::ProgramBinary>;
 }
 
 #[cfg(test)]
@@ -64,6 +64,18 @@ pub enum SamplerType {
     SamplerCube(IsShadow),
 }
 
+impl SamplerType {
+    /// Whether the sampler compares a reference value against a depth texture.
+    pub fn is_shadow(&self) -> bool {
+        match *self {
+            Sampler1D(_, Shadow) => true,
+            Sampler2D(_, Shadow, _, _) => true,
+            SamplerCube(Shadow) => true,
+            _ => false,
+        }
+    }
+}
+
 /// Base type of this shader parameter.
 #[allow(missing_docs)]
 #[deriving(Clone, PartialEq, Show)]
@@ -104,22 +116,59 @@ pub type Location = uint;
 
 // unable to derive anything for fixed arrays
 /// A value that can be uploaded to the device as a uniform.
+///
+/// Matrices are stored in column-major order, so `ValueF32Matrix2x3` holds
+/// two columns of three rows, like a GLSL `mat2x3`. Arrays are uploaded one
+/// element at a time, since each of them has its own location.
 #[allow(missing_docs)]
 pub enum UniformValue {
     ValueI32(i32),
+    ValueU32(u32),
     ValueF32(f32),
+    ValueF64(f64),
+    ValueBool(bool),
 
     ValueI32Vector2([i32, ..2]),
     ValueI32Vector3([i32, ..3]),
     ValueI32Vector4([i32, ..4]),
 
+    ValueU32Vector2([u32, ..2]),
+    ValueU32Vector3([u32, ..3]),
+    ValueU32Vector4([u32, ..4]),
+
     ValueF32Vector2([f32, ..2]),
     ValueF32Vector3([f32, ..3]),
     ValueF32Vector4([f32, ..4]),
 
+    ValueF64Vector2([f64, ..2]),
+    ValueF64Vector3([f64, ..3]),
+    ValueF64Vector4([f64, ..4]),
+
+    ValueBoolVector2([bool, ..2]),
+    ValueBoolVector3([bool, ..3]),
+    ValueBoolVector4([bool, ..4]),
+
     ValueF32Matrix2([[f32, ..2], ..2]),
     ValueF32Matrix3([[f32, ..3], ..3]),
     ValueF32Matrix4([[f32, ..4], ..4]),
+
+    ValueF32Matrix2x3([[f32, ..3], ..2]),
+    ValueF32Matrix2x4([[f32, ..4], ..2]),
+    ValueF32Matrix3x2([[f32, ..2], ..3]),
+    ValueF32Matrix3x4([[f32, ..4], ..3]),
+    ValueF32Matrix4x2([[f32, ..2], ..4]),
+    ValueF32Matrix4x3([[f32, ..3], ..4]),
+
+    ValueF64Matrix2([[f64, ..2], ..2]),
+    ValueF64Matrix3([[f64, ..3], ..3]),
+    ValueF64Matrix4([[f64, ..4], ..4]),
+
+    ValueF64Matrix2x3([[f64, ..3], ..2]),
+    ValueF64Matrix2x4([[f64, ..4], ..2]),
+    ValueF64Matrix3x2([[f64, ..2], ..3]),
+    ValueF64Matrix3x4([[f64, ..4], ..3]),
+    ValueF64Matrix4x2([[f64, ..2], ..4]),
+    ValueF64Matrix4x3([[f64, ..3], ..4]),
 }
 
 impl UniformValue {
@@ -127,20 +176,53 @@ impl UniformValue {
     pub fn is_same_type(&self, other: &UniformValue) -> bool {
         match (*self, *other) {
             (ValueI32(_), ValueI32(_)) => true,
+            (ValueU32(_), ValueU32(_)) => true,
             (ValueF32(_), ValueF32(_)) => true,
+            (ValueF64(_), ValueF64(_)) => true,
+            (ValueBool(_), ValueBool(_)) => true,
 
             (ValueI32Vector2(_), ValueI32Vector2(_)) => true,
             (ValueI32Vector3(_), ValueI32Vector3(_)) => true,
             (ValueI32Vector4(_), ValueI32Vector4(_)) => true,
 
+            (ValueU32Vector2(_), ValueU32Vector2(_)) => true,
+            (ValueU32Vector3(_), ValueU32Vector3(_)) => true,
+            (ValueU32Vector4(_), ValueU32Vector4(_)) => true,
+
             (ValueF32Vector2(_), ValueF32Vector2(_)) => true,
             (ValueF32Vector3(_), ValueF32Vector3(_)) => true,
             (ValueF32Vector4(_), ValueF32Vector4(_)) => true,
 
+            (ValueF64Vector2(_), ValueF64Vector2(_)) => true,
+            (ValueF64Vector3(_), ValueF64Vector3(_)) => true,
+            (ValueF64Vector4(_), ValueF64Vector4(_)) => true,
+
+            (ValueBoolVector2(_), ValueBoolVector2(_)) => true,
+            (ValueBoolVector3(_), ValueBoolVector3(_)) => true,
+            (ValueBoolVector4(_), ValueBoolVector4(_)) => true,
+
             (ValueF32Matrix2(_), ValueF32Matrix2(_)) => true,
             (ValueF32Matrix3(_), ValueF32Matrix3(_)) => true,
             (ValueF32Matrix4(_), ValueF32Matrix4(_)) => true,
 
+            (ValueF32Matrix2x3(_), ValueF32Matrix2x3(_)) => true,
+            (ValueF32Matrix2x4(_), ValueF32Matrix2x4(_)) => true,
+            (ValueF32Matrix3x2(_), ValueF32Matrix3x2(_)) => true,
+            (ValueF32Matrix3x4(_), ValueF32Matrix3x4(_)) => true,
+            (ValueF32Matrix4x2(_), ValueF32Matrix4x2(_)) => true,
+            (ValueF32Matrix4x3(_), ValueF32Matrix4x3(_)) => true,
+
+            (ValueF64Matrix2(_), ValueF64Matrix2(_)) => true,
+            (ValueF64Matrix3(_), ValueF64Matrix3(_)) => true,
+            (ValueF64Matrix4(_), ValueF64Matrix4(_)) => true,
+
+            (ValueF64Matrix2x3(_), ValueF64Matrix2x3(_)) => true,
+            (ValueF64Matrix2x4(_), ValueF64Matrix2x4(_)) => true,
+            (ValueF64Matrix3x2(_), ValueF64Matrix3x2(_)) => true,
+            (ValueF64Matrix3x4(_), ValueF64Matrix3x4(_)) => true,
+            (ValueF64Matrix4x2(_), ValueF64Matrix4x2(_)) => true,
+            (ValueF64Matrix4x3(_), ValueF64Matrix4x3(_)) => true,
+
             _ => false,
         }
     }
@@ -150,19 +232,127 @@ impl Clone for UniformValue {
     fn clone(&self) -> UniformValue {
         match *self {
             ValueI32(val)      => ValueI32(val),
+            ValueU32(val)      => ValueU32(val),
             ValueF32(val)      => ValueF32(val),
+            ValueF64(val)      => ValueF64(val),
+            ValueBool(val)     => ValueBool(val),
 
             ValueI32Vector2(v) => ValueI32Vector2(v),
             ValueI32Vector3(v) => ValueI32Vector3(v),
             ValueI32Vector4(v) => ValueI32Vector4(v),
 
+            ValueU32Vector2(v) => ValueU32Vector2(v),
+            ValueU32Vector3(v) => ValueU32Vector3(v),
+            ValueU32Vector4(v) => ValueU32Vector4(v),
+
             ValueF32Vector2(v) => ValueF32Vector2(v),
             ValueF32Vector3(v) => ValueF32Vector3(v),
             ValueF32Vector4(v) => ValueF32Vector4(v),
 
+            ValueF64Vector2(v) => ValueF64Vector2(v),
+            ValueF64Vector3(v) => ValueF64Vector3(v),
+            ValueF64Vector4(v) => ValueF64Vector4(v),
+
+            ValueBoolVector2(v) => ValueBoolVector2(v),
+            ValueBoolVector3(v) => ValueBoolVector3(v),
+            ValueBoolVector4(v) => ValueBoolVector4(v),
+
             ValueF32Matrix2(m) => ValueF32Matrix2(m),
             ValueF32Matrix3(m) => ValueF32Matrix3(m),
             ValueF32Matrix4(m) => ValueF32Matrix4(m),
+
+            ValueF32Matrix2x3(m) => ValueF32Matrix2x3(m),
+            ValueF32Matrix2x4(m) => ValueF32Matrix2x4(m),
+            ValueF32Matrix3x2(m) => ValueF32Matrix3x2(m),
+            ValueF32Matrix3x4(m) => ValueF32Matrix3x4(m),
+            ValueF32Matrix4x2(m) => ValueF32Matrix4x2(m),
+            ValueF32Matrix4x3(m) => ValueF32Matrix4x3(m),
+
+            ValueF64Matrix2(m) => ValueF64Matrix2(m),
+            ValueF64Matrix3(m) => ValueF64Matrix3(m),
+            ValueF64Matrix4(m) => ValueF64Matrix4(m),
+
+            ValueF64Matrix2x3(m) => ValueF64Matrix2x3(m),
+            ValueF64Matrix2x4(m) => ValueF64Matrix2x4(m),
+            ValueF64Matrix3x2(m) => ValueF64Matrix3x2(m),
+            ValueF64Matrix3x4(m) => ValueF64Matrix3x4(m),
+            ValueF64Matrix4x2(m) => ValueF64Matrix4x2(m),
+            ValueF64Matrix4x3(m) => ValueF64Matrix4x3(m),
+        }
+    }
+}
+
+// compared by value, for the renderer to skip redundant uploads
+impl PartialEq for UniformValue {
+    fn eq(&self, other: &UniformValue) -> bool {
+        match (*self, *other) {
+            (ValueI32(a), ValueI32(b)) => a == b,
+            (ValueU32(a), ValueU32(b)) => a == b,
+            (ValueF32(a), ValueF32(b)) => a == b,
+            (ValueF64(a), ValueF64(b)) => a == b,
+            (ValueBool(a), ValueBool(b)) => a == b,
+
+            (ValueI32Vector2(a), ValueI32Vector2(b)) => a.as_slice() == b.as_slice(),
+            (ValueI32Vector3(a), ValueI32Vector3(b)) => a.as_slice() == b.as_slice(),
+            (ValueI32Vector4(a), ValueI32Vector4(b)) => a.as_slice() == b.as_slice(),
+
+            (ValueU32Vector2(a), ValueU32Vector2(b)) => a.as_slice() == b.as_slice(),
+            (ValueU32Vector3(a), ValueU32Vector3(b)) => a.as_slice() == b.as_slice(),
+            (ValueU32Vector4(a), ValueU32Vector4(b)) => a.as_slice() == b.as_slice(),
+
+            (ValueF32Vector2(a), ValueF32Vector2(b)) => a.as_slice() == b.as_slice(),
+            (ValueF32Vector3(a), ValueF32Vector3(b)) => a.as_slice() == b.as_slice(),
+            (ValueF32Vector4(a), ValueF32Vector4(b)) => a.as_slice() == b.as_slice(),
+
+            (ValueF64Vector2(a), ValueF64Vector2(b)) => a.as_slice() == b.as_slice(),
+            (ValueF64Vector3(a), ValueF64Vector3(b)) => a.as_slice() == b.as_slice(),
+            (ValueF64Vector4(a), ValueF64Vector4(b)) => a.as_slice() == b.as_slice(),
+
+            (ValueBoolVector2(a), ValueBoolVector2(b)) => a.as_slice() == b.as_slice(),
+            (ValueBoolVector3(a), ValueBoolVector3(b)) => a.as_slice() == b.as_slice(),
+            (ValueBoolVector4(a), ValueBoolVector4(b)) => a.as_slice() == b.as_slice(),
+
+            (ValueF32Matrix2(a), ValueF32Matrix2(b)) =>
+                a.iter().zip(b.iter()).all(|(x, y)| x.as_slice() == y.as_slice()),
+            (ValueF32Matrix3(a), ValueF32Matrix3(b)) =>
+                a.iter().zip(b.iter()).all(|(x, y)| x.as_slice() == y.as_slice()),
+            (ValueF32Matrix4(a), ValueF32Matrix4(b)) =>
+                a.iter().zip(b.iter()).all(|(x, y)| x.as_slice() == y.as_slice()),
+
+            (ValueF32Matrix2x3(a), ValueF32Matrix2x3(b)) =>
+                a.iter().zip(b.iter()).all(|(x, y)| x.as_slice() == y.as_slice()),
+            (ValueF32Matrix2x4(a), ValueF32Matrix2x4(b)) =>
+                a.iter().zip(b.iter()).all(|(x, y)| x.as_slice() == y.as_slice()),
+            (ValueF32Matrix3x2(a), ValueF32Matrix3x2(b)) =>
+                a.iter().zip(b.iter()).all(|(x, y)| x.as_slice() == y.as_slice()),
+            (ValueF32Matrix3x4(a), ValueF32Matrix3x4(b)) =>
+                a.iter().zip(b.iter()).all(|(x, y)| x.as_slice() == y.as_slice()),
+            (ValueF32Matrix4x2(a), ValueF32Matrix4x2(b)) =>
+                a.iter().zip(b.iter()).all(|(x, y)| x.as_slice() == y.as_slice()),
+            (ValueF32Matrix4x3(a), ValueF32Matrix4x3(b)) =>
+                a.iter().zip(b.iter()).all(|(x, y)| x.as_slice() == y.as_slice()),
+
+            (ValueF64Matrix2(a), ValueF64Matrix2(b)) =>
+                a.iter().zip(b.iter()).all(|(x, y)| x.as_slice() == y.as_slice()),
+            (ValueF64Matrix3(a), ValueF64Matrix3(b)) =>
+                a.iter().zip(b.iter()).all(|(x, y)| x.as_slice() == y.as_slice()),
+            (ValueF64Matrix4(a), ValueF64Matrix4(b)) =>
+                a.iter().zip(b.iter()).all(|(x, y)| x.as_slice() == y.as_slice()),
+
+            (ValueF64Matrix2x3(a), ValueF64Matrix2x3(b)) =>
+                a.iter().zip(b.iter()).all(|(x, y)| x.as_slice() == y.as_slice()),
+            (ValueF64Matrix2x4(a), ValueF64Matrix2x4(b)) =>
+                a.iter().zip(b.iter()).all(|(x, y)| x.as_slice() == y.as_slice()),
+            (ValueF64Matrix3x2(a), ValueF64Matrix3x2(b)) =>
+                a.iter().zip(b.iter()).all(|(x, y)| x.as_slice() == y.as_slice()),
+            (ValueF64Matrix3x4(a), ValueF64Matrix3x4(b)) =>
+                a.iter().zip(b.iter()).all(|(x, y)| x.as_slice() == y.as_slice()),
+            (ValueF64Matrix4x2(a), ValueF64Matrix4x2(b)) =>
+                a.iter().zip(b.iter()).all(|(x, y)| x.as_slice() == y.as_slice()),
+            (ValueF64Matrix4x3(a), ValueF64Matrix4x3(b)) =>
+                a.iter().zip(b.iter()).all(|(x, y)| x.as_slice() == y.as_slice()),
+
+            _ => false,
         }
     }
 }
@@ -171,16 +361,31 @@ impl fmt::Show for UniformValue {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             ValueI32(x)            => write!(f, "ValueI32({})", x),
+            ValueU32(x)            => write!(f, "ValueU32({})", x),
             ValueF32(x)            => write!(f, "ValueF32({})", x),
+            ValueF64(x)            => write!(f, "ValueF64({})", x),
+            ValueBool(x)           => write!(f, "ValueBool({})", x),
 
             ValueI32Vector2(ref v) => write!(f, "ValueI32Vector2({})", v.as_slice()),
             ValueI32Vector3(ref v) => write!(f, "ValueI32Vector3({})", v.as_slice()),
             ValueI32Vector4(ref v) => write!(f, "ValueI32Vector4({})", v.as_slice()),
 
+            ValueU32Vector2(ref v) => write!(f, "ValueU32Vector2({})", v.as_slice()),
+            ValueU32Vector3(ref v) => write!(f, "ValueU32Vector3({})", v.as_slice()),
+            ValueU32Vector4(ref v) => write!(f, "ValueU32Vector4({})", v.as_slice()),
+
             ValueF32Vector2(ref v) => write!(f, "ValueF32Vector2({})", v.as_slice()),
             ValueF32Vector3(ref v) => write!(f, "ValueF32Vector3({})", v.as_slice()),
             ValueF32Vector4(ref v) => write!(f, "ValueF32Vector4({})", v.as_slice()),
 
+            ValueF64Vector2(ref v) => write!(f, "ValueF64Vector2({})", v.as_slice()),
+            ValueF64Vector3(ref v) => write!(f, "ValueF64Vector3({})", v.as_slice()),
+            ValueF64Vector4(ref v) => write!(f, "ValueF64Vector4({})", v.as_slice()),
+
+            ValueBoolVector2(ref v) => write!(f, "ValueBoolVector2({})", v.as_slice()),
+            ValueBoolVector3(ref v) => write!(f, "ValueBoolVector3({})", v.as_slice()),
+            ValueBoolVector4(ref v) => write!(f, "ValueBoolVector4({})", v.as_slice()),
+
             ValueF32Matrix2(ref m) => {
                 try!(write!(f, "ValueF32Matrix2("));
                 for v in m.iter() {
@@ -202,6 +407,111 @@ impl fmt::Show for UniformValue {
                 }
                 write!(f, ")")
             },
+            ValueF32Matrix2x3(ref m) => {
+                try!(write!(f, "ValueF32Matrix2x3("));
+                for v in m.iter() {
+                    try!(write!(f, "{}", v.as_slice()));
+                }
+                write!(f, ")")
+            },
+            ValueF32Matrix2x4(ref m) => {
+                try!(write!(f, "ValueF32Matrix2x4("));
+                for v in m.iter() {
+                    try!(write!(f, "{}", v.as_slice()));
+                }
+                write!(f, ")")
+            },
+            ValueF32Matrix3x2(ref m) => {
+                try!(write!(f, "ValueF32Matrix3x2("));
+                for v in m.iter() {
+                    try!(write!(f, "{}", v.as_slice()));
+                }
+                write!(f, ")")
+            },
+            ValueF32Matrix3x4(ref m) => {
+                try!(write!(f, "ValueF32Matrix3x4("));
+                for v in m.iter() {
+                    try!(write!(f, "{}", v.as_slice()));
+                }
+                write!(f, ")")
+            },
+            ValueF32Matrix4x2(ref m) => {
+                try!(write!(f, "ValueF32Matrix4x2("));
+                for v in m.iter() {
+                    try!(write!(f, "{}", v.as_slice()));
+                }
+                write!(f, ")")
+            },
+            ValueF32Matrix4x3(ref m) => {
+                try!(write!(f, "ValueF32Matrix4x3("));
+                for v in m.iter() {
+                    try!(write!(f, "{}", v.as_slice()));
+                }
+                write!(f, ")")
+            },
+            ValueF64Matrix2(ref m) => {
+                try!(write!(f, "ValueF64Matrix2("));
+                for v in m.iter() {
+                    try!(write!(f, "{}", v.as_slice()));
+                }
+                write!(f, ")")
+            },
+            ValueF64Matrix3(ref m) => {
+                try!(write!(f, "ValueF64Matrix3("));
+                for v in m.iter() {
+                    try!(write!(f, "{}", v.as_slice()));
+                }
+                write!(f, ")")
+            },
+            ValueF64Matrix4(ref m) => {
+                try!(write!(f, "ValueF64Matrix4("));
+                for v in m.iter() {
+                    try!(write!(f, "{}", v.as_slice()));
+                }
+                write!(f, ")")
+            },
+            ValueF64Matrix2x3(ref m) => {
+                try!(write!(f, "ValueF64Matrix2x3("));
+                for v in m.iter() {
+                    try!(write!(f, "{}", v.as_slice()));
+                }
+                write!(f, ")")
+            },
+            ValueF64Matrix2x4(ref m) => {
+                try!(write!(f, "ValueF64Matrix2x4("));
+                for v in m.iter() {
+                    try!(write!(f, "{}", v.as_slice()));
+                }
+                write!(f, ")")
+            },
+            ValueF64Matrix3x2(ref m) => {
+                try!(write!(f, "ValueF64Matrix3x2("));
+                for v in m.iter() {
+                    try!(write!(f, "{}", v.as_slice()));
+                }
+                write!(f, ")")
+            },
+            ValueF64Matrix3x4(ref m) => {
+                try!(write!(f, "ValueF64Matrix3x4("));
+                for v in m.iter() {
+                    try!(write!(f, "{}", v.as_slice()));
+                }
+                write!(f, ")")
+            },
+            ValueF64Matrix4x2(ref m) => {
+                try!(write!(f, "ValueF64Matrix4x2("));
+                for v in m.iter() {
+                    try!(write!(f, "{}", v.as_slice()));
+                }
+                write!(f, ")")
+            },
+            ValueF64Matrix4x3(ref m) => {
+                try!(write!(f, "ValueF64Matrix4x3("));
+                for v in m.iter() {
+                    try!(write!(f, "{}", v.as_slice()));
+                }
+                write!(f, ")")
+            },
         }
     }
 }
@@ -222,13 +532,17 @@ pub struct Attribute {
 }
 
 /// Uniform, a type of shader parameter representing data passed to the program.
+///
+/// Arrays are described by one variable per element, named `name[i]`, and
+/// the members of structures by one variable per member, such as
+/// `light[3].color`.
 #[deriving(Clone, PartialEq, Show)]
 pub struct UniformVar {
     /// Name of this uniform.
     pub name: String,
     /// Location of this uniform in the program.
     pub location: Location,
-    /// Number of elements this uniform represents.
+    /// Number of elements this uniform represents, 1 for array elements.
     pub count: uint,
     /// Type that this uniform is composed of
     pub base_type: BaseType,
@@ -236,6 +550,28 @@ pub struct UniformVar {
     pub container: ContainerType,
 }
 
+/// A member of a uniform block, as laid out in the buffer.
+///
+/// Arrays are described by a single member, without the `[0]` suffix.
+#[deriving(Clone, PartialEq, Show)]
+pub struct BlockMember {
+    /// Name of this member.
+    pub name: String,
+    /// Offset (in bytes) of this member from the start of the block.
+    pub offset: uint,
+    /// Type that this member is composed of.
+    pub base_type: BaseType,
+    /// "Scalarness" of this member.
+    pub container: ContainerType,
+    /// Number of array elements, 1 for non-arrays.
+    pub count: uint,
+    /// Distance (in bytes) between array elements, 0 for non-arrays.
+    pub array_stride: uint,
+    /// Distance (in bytes) between the columns (or rows, if row major) of a
+    /// matrix, 0 for non-matrices.
+    pub matrix_stride: uint,
+}
+
 /// A uniform block.
 #[deriving(Clone, PartialEq, Show)]
 pub struct BlockVar {
@@ -245,6 +581,8 @@ pub struct BlockVar {
     pub size: uint,
     /// What program stage this uniform block can be used in, as a bitflag.
     pub usage: u8,
+    /// Active members of this uniform block, in no particular order.
+    pub members: Vec<BlockMember>,
 }
 
 /// Sampler, a type of shader parameter representing a texture that can be sampled.
@@ -260,6 +598,32 @@ pub struct SamplerVar {
     pub sampler_type: SamplerType,
 }
 
+/// Image, a type of shader parameter representing a texture level that can be
+/// loaded from and stored to directly.
+#[deriving(Clone, PartialEq, Show)]
+pub struct ImageVar {
+    /// Name of this image variable.
+    pub name: String,
+    /// Location of this image in the program.
+    pub location: Location,
+    /// Base type for the image.
+    pub base_type: BaseType,
+    /// Type of the texture accessed, never a shadow one.
+    pub image_type: SamplerType,
+}
+
+/// A shader storage block.
+#[deriving(Clone, PartialEq, Show)]
+pub struct StorageBlockVar {
+    /// Name of this storage block.
+    pub name: String,
+    /// Size (in bytes) of this storage block's data, not including the
+    /// variable-sized array at its end.
+    pub size: uint,
+    /// What program stage this storage block can be used in, as a bitflag.
+    pub usage: u8,
+}
+
 /// Metadata about a program.
 #[deriving(Clone, PartialEq, Show)]
 pub struct ProgramInfo {
@@ -271,6 +635,10 @@ pub struct ProgramInfo {
     pub blocks: Vec<BlockVar>,
     /// Samplers in the program
     pub textures: Vec<SamplerVar>,
+    /// Images in the program
+    pub images: Vec<ImageVar>,
+    /// Shader storage blocks in the program
+    pub storage_blocks: Vec<StorageBlockVar>,
 }
 
 /// Error type for trying to store a UniformValue in a UniformVar.
@@ -287,14 +655,20 @@ pub enum CompatibilityError {
 
 impl UniformVar {
     /// Whether a value is compatible with this variable. That is, whether the value can be stored
-    /// in this variable.
+    /// in this variable. Matrices are compatible regardless of their layout, since the values
+    /// are always provided in column-major order.
     pub fn is_compatible(&self, value: &UniformValue) -> Result<(), CompatibilityError> {
-        if self.count != 1 {
-            return Err(ErrorArraySize)
-        }
         match (self.base_type, self.container, *value) {
             (BaseI32, Single, ValueI32(_)) => Ok(()),
+            (BaseU32, Single, ValueU32(_)) => Ok(()),
             (BaseF32, Single, ValueF32(_)) => Ok(()),
+            (BaseF64, Single, ValueF64(_)) => Ok(()),
+            (BaseBool, Single, ValueBool(_)) => Ok(()),
+
+            // booleans can also be set from integers and floats
+            (BaseBool, Single, ValueI32(_)) => Ok(()),
+            (BaseBool, Single, ValueU32(_)) => Ok(()),
+            (BaseBool, Single, ValueF32(_)) => Ok(()),
 
             (BaseF32, Vector(2), ValueF32Vector2(_)) => Ok(()),
             (BaseF32, Vector(3), ValueF32Vector3(_)) => Ok(()),
@@ -304,33 +678,246 @@ impl UniformVar {
             (BaseI32, Vector(3), ValueI32Vector3(_)) => Ok(()),
             (BaseI32, Vector(4), ValueI32Vector4(_)) => Ok(()),
 
+            (BaseU32, Vector(2), ValueU32Vector2(_)) => Ok(()),
+            (BaseU32, Vector(3), ValueU32Vector3(_)) => Ok(()),
+            (BaseU32, Vector(4), ValueU32Vector4(_)) => Ok(()),
+
+            (BaseF64, Vector(2), ValueF64Vector2(_)) => Ok(()),
+            (BaseF64, Vector(3), ValueF64Vector3(_)) => Ok(()),
+            (BaseF64, Vector(4), ValueF64Vector4(_)) => Ok(()),
+
+            (BaseBool, Vector(2), ValueBoolVector2(_)) => Ok(()),
+            (BaseBool, Vector(3), ValueBoolVector3(_)) => Ok(()),
+            (BaseBool, Vector(4), ValueBoolVector4(_)) => Ok(()),
+
+            (BaseBool, Vector(2), ValueI32Vector2(_)) => Ok(()),
+            (BaseBool, Vector(3), ValueI32Vector3(_)) => Ok(()),
+            (BaseBool, Vector(4), ValueI32Vector4(_)) => Ok(()),
+            (BaseBool, Vector(2), ValueU32Vector2(_)) => Ok(()),
+            (BaseBool, Vector(3), ValueU32Vector3(_)) => Ok(()),
+            (BaseBool, Vector(4), ValueU32Vector4(_)) => Ok(()),
+            (BaseBool, Vector(2), ValueF32Vector2(_)) => Ok(()),
+            (BaseBool, Vector(3), ValueF32Vector3(_)) => Ok(()),
+            (BaseBool, Vector(4), ValueF32Vector4(_)) => Ok(()),
+
             (BaseF32, Matrix(_, 2,2), ValueF32Matrix2(_)) => Ok(()),
             (BaseF32, Matrix(_, 3,3), ValueF32Matrix3(_)) => Ok(()),
             (BaseF32, Matrix(_, 4,4), ValueF32Matrix4(_)) => Ok(()),
 
+            (BaseF32, Matrix(_, 2,3), ValueF32Matrix2x3(_)) => Ok(()),
+            (BaseF32, Matrix(_, 2,4), ValueF32Matrix2x4(_)) => Ok(()),
+            (BaseF32, Matrix(_, 3,2), ValueF32Matrix3x2(_)) => Ok(()),
+            (BaseF32, Matrix(_, 3,4), ValueF32Matrix3x4(_)) => Ok(()),
+            (BaseF32, Matrix(_, 4,2), ValueF32Matrix4x2(_)) => Ok(()),
+            (BaseF32, Matrix(_, 4,3), ValueF32Matrix4x3(_)) => Ok(()),
+
+            (BaseF64, Matrix(_, 2,2), ValueF64Matrix2(_)) => Ok(()),
+            (BaseF64, Matrix(_, 3,3), ValueF64Matrix3(_)) => Ok(()),
+            (BaseF64, Matrix(_, 4,4), ValueF64Matrix4(_)) => Ok(()),
+
+            (BaseF64, Matrix(_, 2,3), ValueF64Matrix2x3(_)) => Ok(()),
+            (BaseF64, Matrix(_, 2,4), ValueF64Matrix2x4(_)) => Ok(()),
+            (BaseF64, Matrix(_, 3,2), ValueF64Matrix3x2(_)) => Ok(()),
+            (BaseF64, Matrix(_, 3,4), ValueF64Matrix3x4(_)) => Ok(()),
+            (BaseF64, Matrix(_, 4,2), ValueF64Matrix4x2(_)) => Ok(()),
+            (BaseF64, Matrix(_, 4,3), ValueF64Matrix4x3(_)) => Ok(()),
+
             _ => Err(ErrorBaseType)
         }
     }
 }
 
 /// A type storing shader source for different graphics APIs and versions.
+///
+/// The device picks the highest GLSL version it supports for its profile
+/// (desktop or ES), and only falls back to the SPIR-V binary if none match,
+/// since the names of the parameters are not guaranteed to survive in it.
 #[allow(missing_docs)]
-#[deriving(Clone, PartialEq, Show)]
+#[deriving(Clone, Default, PartialEq, Hash, Show)]
 pub struct ShaderSource<'a> {
+    pub glsl_100_es: Option<&'a [u8]>,
+    pub glsl_300_es: Option<&'a [u8]>,
     pub glsl_120: Option<&'a [u8]>,
     pub glsl_130: Option<&'a [u8]>,
     pub glsl_140: Option<&'a [u8]>,
     pub glsl_150: Option<&'a [u8]>,
+    pub glsl_330: Option<&'a [u8]>,
+    pub glsl_400: Option<&'a [u8]>,
+    pub glsl_410: Option<&'a [u8]>,
+    pub glsl_420: Option<&'a [u8]>,
+    pub glsl_430: Option<&'a [u8]>,
+    pub glsl_440: Option<&'a [u8]>,
+    pub glsl_450: Option<&'a [u8]>,
+    pub spirv: Option<&'a [u8]>,
     // TODO: hlsl_sm_N...
 }
 
+/// The severity of a diagnostic message.
+#[allow(missing_docs)]
+#[deriving(Clone, PartialEq, Show)]
+pub enum DiagnosticLevel {
+    LevelError,
+    LevelWarning,
+    LevelNote,
+}
+
+/// A single message of a compile or link log.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Diagnostic {
+    /// Severity of the message.
+    pub level: DiagnosticLevel,
+    /// Index of the source string, if reported.
+    pub source: Option<uint>,
+    /// Line number, starting from 1, if reported.
+    pub line: Option<uint>,
+    /// Column number, if reported.
+    pub column: Option<uint>,
+    /// The message itself, without the location and severity.
+    pub message: String,
+}
+
+/// The info log of a shader compilation or program link, along with the
+/// diagnostics parsed from it.
+#[deriving(Clone, PartialEq, Show)]
+pub struct ShaderLog {
+    /// The raw text, as returned by the driver.
+    pub text: String,
+    /// The messages recognized in the text.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+static LEVELS: [(&'static str, DiagnosticLevel), ..6] = [
+    ("error", LevelError), ("ERROR", LevelError),
+    ("warning", LevelWarning), ("WARNING", LevelWarning),
+    ("note", LevelNote), ("NOTE", LevelNote),
+];
+
+/// Split a leading `level:` or `level CODE:` off the message.
+fn split_level<'a>(s: &'a str) -> Option<(DiagnosticLevel, &'a str)> {
+    for &(word, level) in LEVELS.iter() {
+        if !s.starts_with(word) {
+            continue
+        }
+        let rest = s.slice_from(word.len());
+        let colon = match rest.find(':') {
+            Some(i) => i,
+            None => continue,
+        };
+        let code = rest.slice_to(colon).trim();
+        if code.contains_char(' ') || (!code.is_empty() && !rest.starts_with(" ")) {
+            continue
+        }
+        return Some((level, rest.slice_from(colon + 1).trim()))
+    }
+    None
+}
+
+fn split_number<'a>(s: &'a str) -> Option<(uint, &'a str)> {
+    let n = s.find(|c: char| c < '0' || c > '9').unwrap_or(s.len());
+    from_str(s.slice_to(n)).map(|v| (v, s.slice_from(n)))
+}
+
+fn split_char<'a>(s: &'a str, c: char) -> Option<&'a str> {
+    let s = s.trim_left();
+    if s.chars().next() == Some(c) {
+        Some(s.slice_from(1))
+    } else {
+        None
+    }
+}
+
+/// Split `N)` followed by a colon, returning the number.
+fn split_parens<'a>(s: &'a str) -> Option<(uint, &'a str)> {
+    split_number(s).and_then(|(n, rest)| {
+        split_char(rest, ')').and_then(|r| split_char(r, ':')).map(|r| (n, r))
+    })
+}
+
+/// Split the location off a log line, in either of the `S(L) :` (NVIDIA),
+/// `S:L(C):` (Mesa) and `S:L:` (AMD, Apple, ANGLE) forms.
+fn split_location<'a>(s: &'a str) -> Option<(uint, uint, Option<uint>, &'a str)> {
+    let (source, rest) = match split_number(s) {
+        Some(v) => v,
+        None => return None,
+    };
+    match split_char(rest, '(') {
+        Some(rest) => return split_parens(rest).map(|(line, r)| (source, line, None, r)),
+        None => (),
+    }
+    let (line, rest) = match split_char(rest, ':').and_then(|r| split_number(r)) {
+        Some(v) => v,
+        None => return None,
+    };
+    match split_char(rest, '(') {
+        Some(rest) => split_parens(rest).map(|(column, r)| (source, line, Some(column), r)),
+        None => split_char(rest, ':').map(|r| (source, line, None, r)),
+    }
+}
+
+fn parse_diagnostic(line: &str) -> Option<Diagnostic> {
+    let line = line.trim();
+    match split_level(line) {
+        // `LEVEL: S:L: message` or an unlocated `level: message`
+        Some((level, rest)) => {
+            let (source, line, column, message) = match split_location(rest) {
+                Some((s, l, c, m)) => (Some(s), Some(l), c, m),
+                None => (None, None, None, rest),
+            };
+            Some(Diagnostic {
+                level: level,
+                source: source,
+                line: line,
+                column: column,
+                message: message.trim().to_string(),
+            })
+        },
+        // `S(L) : level CODE: message` or `S:L(C): level: message`
+        None => split_location(line).map(|(source, line, column, rest)| {
+            let (level, message) = split_level(rest.trim()).unwrap_or((LevelError, rest));
+            Diagnostic {
+                level: level,
+                source: Some(source),
+                line: Some(line),
+                column: column,
+                message: message.trim().to_string(),
+            }
+        }),
+    }
+}
+
+impl ShaderLog {
+    /// Parse the messages of an info log. Lines that are not recognized as
+    /// messages, such as headers, are only kept in the raw text.
+    pub fn parse(text: &str) -> ShaderLog {
+        ShaderLog {
+            text: text.to_string(),
+            diagnostics: text.lines_any().filter_map(|l| parse_diagnostic(l)).collect(),
+        }
+    }
+
+    /// Return true if any of the messages is an error.
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.level == LevelError)
+    }
+}
+
+/// A linked program in the driver-specific binary form, which can only be
+/// loaded back by the same driver.
+#[deriving(Clone, PartialEq, Show)]
+pub struct ProgramBinary {
+    /// Driver-specific format identifier.
+    pub format: u32,
+    /// The binary data.
+    pub data: Vec<u8>,
+}
+
 /// An error type for creating programs.
 #[deriving(Clone, PartialEq, Show)]
 pub enum CreateShaderError {
     /// The device does not support any of the shaders supplied.
     NoSupportedShaderProvided,
     /// The shader failed to compile.
-    ShaderCompilationFailed
+    ShaderCompilationFailed(ShaderLog),
 }
 
 /// Shader model supported by the device, corresponds to the HLSL shader models.
@@ -358,3 +945,79 @@ impl ShaderModel {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Diagnostic, ShaderLog, LevelError, LevelWarning};
+    use super::{UniformVar, BaseType, ContainerType};
+    use super::{BaseBool, BaseU32, BaseF64, Single, Vector, Matrix, ColumnMajor};
+    use super::{ValueBool, ValueI32, ValueU32, ValueF32, ValueF64Vector3, ValueF64Matrix2x3};
+
+    fn located(level: super::DiagnosticLevel, line: uint, column: Option<uint>,
+               message: &str) -> Diagnostic {
+        Diagnostic {
+            level: level,
+            source: Some(0),
+            line: Some(line),
+            column: column,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_log() {
+        let log = ShaderLog::parse("0(12) : error C0000: syntax error, unexpected '}'\n\
+                                    0:3(10): warning: `x' used uninitialized\n\
+                                    ERROR: 0:7: 'foo' : undeclared identifier\n\
+                                    Fragment info\n\
+                                    error: linking failed");
+        assert_eq!(log.diagnostics, vec![
+            located(LevelError, 12, None, "syntax error, unexpected '}'"),
+            located(LevelWarning, 3, Some(10), "`x' used uninitialized"),
+            located(LevelError, 7, None, "'foo' : undeclared identifier"),
+            Diagnostic {
+                level: LevelError,
+                source: None,
+                line: None,
+                column: None,
+                message: "linking failed".to_string(),
+            },
+        ]);
+        assert!(log.has_errors());
+        assert!(!ShaderLog::parse("0:1(1): warning: unused").has_errors());
+    }
+
+    fn var(base: BaseType, container: ContainerType) -> UniformVar {
+        UniformVar {
+            name: "u".to_string(),
+            location: 0,
+            count: 1,
+            base_type: base,
+            container: container,
+        }
+    }
+
+    #[test]
+    fn test_is_compatible() {
+        assert!(var(BaseU32, Single).is_compatible(&ValueU32(1)).is_ok());
+        assert!(var(BaseU32, Single).is_compatible(&ValueI32(1)).is_err());
+        assert!(var(BaseBool, Single).is_compatible(&ValueBool(true)).is_ok());
+        assert!(var(BaseBool, Single).is_compatible(&ValueF32(1.0)).is_ok());
+        assert!(var(BaseF64, Vector(3)).is_compatible(&ValueF64Vector3([0.0, ..3])).is_ok());
+        let mat2x3 = var(BaseF64, Matrix(ColumnMajor, 2, 3));
+        assert!(mat2x3.is_compatible(&ValueF64Matrix2x3([[0.0, ..3], ..2])).is_ok());
+        assert!(var(BaseF64, Matrix(ColumnMajor, 3, 2))
+                    .is_compatible(&ValueF64Matrix2x3([[0.0, ..3], ..2])).is_err());
+    }
+
+    #[test]
+    fn test_uniform_eq() {
+        assert!(ValueF32Vector3([1.0, 2.0, 3.0]) == ValueF32Vector3([1.0, 2.0, 3.0]));
+        assert!(ValueF32Vector3([1.0, 2.0, 3.0]) != ValueF32Vector3([1.0, 2.0, 4.0]));
+        assert!(ValueI32(1) != ValueU32(1));
+        let mut m = [[0.0f32, ..4], ..4];
+        assert!(ValueF32Matrix4(m) == ValueF32Matrix4(m));
+        m[3][2] = 1.0;
+        assert!(ValueF32Matrix4(m) != ValueF32Matrix4([[0.0, ..4], ..4]));
+    }
+}
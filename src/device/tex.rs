@@ -44,6 +44,23 @@ pub enum TextureError {
     InvalidTextureInfo(TextureInfo),
     /// The given data has a different size than the target texture slice.
     IncorrectTextureSize(uint),
+    /// The device doesn't support swizzling texture components.
+    UnsupportedTextureSwizzle,
+    /// The device doesn't support creating texture views.
+    UnsupportedTextureView,
+    /// The given ViewInfo is out of range or incompatible with the texture.
+    InvalidTextureView(ViewInfo),
+    /// The ImageInfo is missing a face for a cube texture, or has one for
+    /// a texture of another kind.
+    InvalidCubeFace,
+    /// The device doesn't support buffer textures, or the operation is not
+    /// available for them.
+    UnsupportedTextureBuffer,
+    /// The buffer size is not a non-zero multiple of the texel size,
+    /// or holds more texels than a texture can address.
+    InvalidTextureBuffer(uint),
+    /// The device doesn't support cube array textures.
+    UnsupportedTextureCubeArray,
 }
 
 impl fmt::Show for TextureError {
@@ -72,6 +89,38 @@ impl fmt::Show for TextureError {
                     expected size {}",
                     expected
                 ),
+            &UnsupportedTextureSwizzle =>
+                write!(f, "Texture component swizzling is not supported by the device"),
+
+            &UnsupportedTextureView =>
+                write!(f, "Texture views are not supported by the device"),
+
+            &InvalidTextureView(view) =>
+                write!(
+                    f,
+                    "Invalid ViewInfo (the format, kind, levels and layers \
+                    must be compatible with the texture): {}\n",
+                    view
+                ),
+            &InvalidCubeFace =>
+                write!(
+                    f,
+                    "Only ImageInfo of cube textures (and all of them) \
+                    need to select a face"
+                ),
+            &UnsupportedTextureBuffer =>
+                write!(f, "Buffer textures are not supported by the device or operation"),
+
+            &InvalidTextureBuffer(size) =>
+                write!(
+                    f,
+                    "Invalid buffer size for a buffer texture (must be a \
+                    non-zero multiple of the texel size, within the device \
+                    limit): {}",
+                    size
+                ),
+            &UnsupportedTextureCubeArray =>
+                write!(f, "Cube array textures are not supported by the device"),
         }
     }
 }
@@ -108,6 +157,7 @@ pub enum Components {
 
 /// Describes the layout of each texel within a surface/texture.
 #[deriving(Eq, Ord, PartialEq, PartialOrd, Hash, Clone, Show)]
+#[allow(non_camel_case_types)]
 pub enum Format {
     /// Floating point.
     Float(Components, ::attrib::FloatSize),
@@ -130,9 +180,23 @@ pub enum Format {
     /// This s an RGB format of type floating-point. The 3 color values have
     /// 9 bits of precision, and they share a single exponent.
     RGB9E5,
+    /// 8 bits each for RGB, encoded in the sRGB color space.
+    SRGB8,
+    /// 8 bits each for RGB encoded in the sRGB color space, and a linear
+    /// 8-bit alpha.
+    SRGB8_A8,
+    /// 16 bits for depth
+    DEPTH16,
+    /// 24 bits for depth
+    DEPTH24,
+    /// 32-bit floating-point depth
+    DEPTH32F,
     /// 24 bits for depth, 8 for stencil
     DEPTH24STENCIL8,
-    // TODO: sRGB
+    /// 32-bit floating-point depth, 8 bits for stencil
+    DEPTH32F_STENCIL8,
+    /// 8 bits for stencil
+    STENCIL8,
 }
 
 /// Codec used to compress image data.
@@ -145,6 +209,78 @@ pub enum Compression {
     ETC2_SRGB,
     /// Use the EXT2 EAC algorithm on 4 components.
     ETC2_EAC_RGBA8,
+    /// BC1 (DXT1) on 3 components.
+    BC1_RGB,
+    /// BC1 (DXT1) on 4 components, with a 1-bit alpha.
+    BC1_RGBA,
+    /// BC2 (DXT3) on 4 components, with an explicit 4-bit alpha.
+    BC2_RGBA,
+    /// BC3 (DXT5) on 4 components, with an interpolated alpha.
+    BC3_RGBA,
+    /// BC4 (RGTC1) on the red component, unsigned normalized.
+    BC4_R,
+    /// BC4 (RGTC1) on the red component, signed normalized.
+    BC4_R_SIGNED,
+    /// BC5 (RGTC2) on the red and green components, unsigned normalized.
+    BC5_RG,
+    /// BC5 (RGTC2) on the red and green components, signed normalized.
+    BC5_RG_SIGNED,
+    /// BC6H (BPTC) on 3 unsigned floating-point components.
+    BC6H_RGB_UFLOAT,
+    /// BC6H (BPTC) on 3 signed floating-point components.
+    BC6H_RGB_SFLOAT,
+    /// BC7 (BPTC) on 4 components.
+    BC7_RGBA,
+    /// BC7 (BPTC) on 4 components in the sRGB color space.
+    BC7_SRGBA,
+    /// ASTC on 4 components, with the given block width and height in texels.
+    ASTC_RGBA(u8, u8),
+    /// ASTC on 4 components in the sRGB color space, with the given block
+    /// width and height in texels.
+    ASTC_SRGBA(u8, u8),
+}
+
+impl Compression {
+    /// Get the width and height of a single compressed block, in texels.
+    pub fn get_block_size(&self) -> (u8, u8) {
+        match *self {
+            ASTC_RGBA(w, h) | ASTC_SRGBA(w, h) => (w, h),
+            _ => (4, 4),
+        }
+    }
+
+    /// Check if the compressed data can be reinterpreted as the other codec.
+    pub fn is_view_compatible(&self, other: &Compression) -> bool {
+        match (*self, *other) {
+            (a, b) if a == b => true,
+            (ETC2_RGB, ETC2_SRGB) | (ETC2_SRGB, ETC2_RGB) => true,
+            (BC4_R, BC4_R_SIGNED) | (BC4_R_SIGNED, BC4_R) => true,
+            (BC5_RG, BC5_RG_SIGNED) | (BC5_RG_SIGNED, BC5_RG) => true,
+            (BC6H_RGB_UFLOAT, BC6H_RGB_SFLOAT) | (BC6H_RGB_SFLOAT, BC6H_RGB_UFLOAT) => true,
+            (BC7_RGBA, BC7_SRGBA) | (BC7_SRGBA, BC7_RGBA) => true,
+            (ASTC_RGBA(w0, h0), ASTC_SRGBA(w1, h1)) |
+            (ASTC_SRGBA(w0, h0), ASTC_RGBA(w1, h1)) => w0 == w1 && h0 == h1,
+            _ => false,
+        }
+    }
+
+    /// Get the number of bytes a single compressed block occupies.
+    pub fn get_block_bytes(&self) -> uint {
+        match *self {
+            ETC2_RGB | ETC2_SRGB => 8,
+            BC1_RGB | BC1_RGBA | BC4_R | BC4_R_SIGNED => 8,
+            _ => 16,
+        }
+    }
+}
+
+fn components_to_count(c: Components) -> uint {
+    match c {
+        R    => 1,
+        RG   => 2,
+        RGB  => 3,
+        RGBA => 4,
+    }
 }
 
 impl Format {
@@ -155,12 +291,71 @@ impl Format {
             Integer(c, _, _) => c,
             Unsigned(c, _, _) => c,
             Compressed(_) => panic!("Tried to get components of compressed texel!"),
-            R3G3B2 | R11FG11FB10F | RGB9E5 => RGB,
-            RGB5A1 | RGB10A2 | RGB10A2UI => RGBA,
-            DEPTH24STENCIL8 => return None,
+            R3G3B2 | R11FG11FB10F | RGB9E5 | SRGB8 => RGB,
+            RGB5A1 | RGB10A2 | RGB10A2UI | SRGB8_A8 => RGBA,
+            DEPTH16 | DEPTH24 | DEPTH32F | DEPTH24STENCIL8 |
+            DEPTH32F_STENCIL8 | STENCIL8 => return None,
         })
     }
 
+    /// Check if the format contains a depth component
+    pub fn has_depth(&self) -> bool {
+        match *self {
+            DEPTH16 | DEPTH24 | DEPTH32F | DEPTH24STENCIL8 | DEPTH32F_STENCIL8 => true,
+            _ => false,
+        }
+    }
+
+    /// Check if the format contains a stencil component
+    pub fn has_stencil(&self) -> bool {
+        match *self {
+            DEPTH24STENCIL8 | DEPTH32F_STENCIL8 | STENCIL8 => true,
+            _ => false,
+        }
+    }
+
+    /// Get the size of a single texel in bytes, as it is provided
+    /// for the upload. Returns `None` for compressed formats, which
+    /// are stored in blocks of texels instead.
+    pub fn get_size(&self) -> Option<uint> {
+        Some(match *self {
+            Float(c, ::attrib::F16) => 2 * components_to_count(c),
+            Float(c, ::attrib::F32) => 4 * components_to_count(c),
+            Float(c, ::attrib::F64) => 8 * components_to_count(c),
+            Integer(c, bits, _)  => bits as uint * components_to_count(c) >> 3,
+            Unsigned(c, bits, _) => bits as uint * components_to_count(c) >> 3,
+            Compressed(_) => return None,
+            R3G3B2       => 1,
+            RGB5A1       => 2,
+            RGB10A2      => 4,
+            RGB10A2UI    => 4,
+            R11FG11FB10F => 4,
+            RGB9E5       => 4,
+            SRGB8        => 3,
+            SRGB8_A8     => 4,
+            DEPTH16      => 2,
+            DEPTH24      => 4,
+            DEPTH32F     => 4,
+            DEPTH24STENCIL8 => 4,
+            DEPTH32F_STENCIL8 => 8,
+            STENCIL8     => 1,
+        })
+    }
+
+    /// Check if a texture view of this format can use the other one. Color
+    /// formats of the same texel size are compatible with each other, while
+    /// depth and stencil formats are only compatible with themselves.
+    pub fn is_view_compatible(&self, other: &Format) -> bool {
+        match (*self, *other) {
+            (a, b) if a == b => true,
+            (Compressed(a), Compressed(b)) => a.is_view_compatible(&b),
+            (Compressed(_), _) | (_, Compressed(_)) => false,
+            (a, b) => !a.has_depth() && !a.has_stencil() &&
+                      !b.has_depth() && !b.has_stencil() &&
+                      a.get_size() == b.get_size(),
+        }
+    }
+
     /// Check if it's a compressed format
     pub fn is_compressed(&self) -> bool {
         match *self {
@@ -168,10 +363,33 @@ impl Format {
             _ => false
         }
     }
+
+    /// Check if the color components are stored in the sRGB color space,
+    /// in which case they are converted to linear space when sampled.
+    pub fn is_srgb(&self) -> bool {
+        match *self {
+            SRGB8 | SRGB8_A8 => true,
+            Compressed(ETC2_SRGB) | Compressed(BC7_SRGBA) | Compressed(ASTC_SRGBA(_, _)) => true,
+            _ => false,
+        }
+    }
+
+    /// Check if the texels are read as raw integers by the shader,
+    /// which requires an integer sampler (`isampler*` or `usampler*`)
+    pub fn is_integer(&self) -> bool {
+        match *self {
+            Integer(_, _, ::attrib::IntRaw) => true,
+            Unsigned(_, _, ::attrib::IntRaw) => true,
+            RGB10A2UI => true,
+            _ => false,
+        }
+    }
 }
 
 /// A commonly used RGBA8 format
 pub static RGBA8: Format = Unsigned(RGBA, 8, ::attrib::IntNormalized);
+/// A commonly used RGBA8 format with sRGB-encoded color components
+pub static SRGBA8: Format = SRGB8_A8;
 
 /// Describes the storage of a surface
 #[allow(missing_docs)]
@@ -193,6 +411,9 @@ pub struct SurfaceInfo {
 /// textures. Similarly for trilinear, it is really Quadralinear(?) for 3D
 /// textures. Alas, these names are simple, and match certain intuitions
 /// ingrained by many years of public use of inaccurate terminology.
+///
+/// When the presets don't fit, `Separate` allows picking the filter of each
+/// stage independently.
 #[deriving(Eq, Ord, PartialEq, PartialOrd, Hash, Clone, Show)]
 pub enum FilterMethod {
     /// The dumbest filtering possible, nearest-neighbor interpolation.
@@ -204,9 +425,34 @@ pub enum FilterMethod {
     Bilinear,
     /// Sample multiple texels across two mipmap levels to increase quality.
     Trilinear,
-    /// Anisotropic filtering with a given "max", must be between 1 and 16,
-    /// inclusive.
-    Anisotropic(u8)
+    /// Anisotropic filtering with a given "max". It is clamped to the
+    /// `max_anisotropy` of the device when the sampler is created.
+    Anisotropic(u8),
+    /// Independent filters for the minification, magnification, and between
+    /// the mipmap levels. Without a mipmap filter only the base level is used.
+    Separate(FilterMode, FilterMode, Option<FilterMode>),
+}
+
+impl FilterMethod {
+    /// Clamp the anisotropy to the maximum supported by the device. Without
+    /// any support anisotropic filtering falls back to trilinear.
+    pub fn clamp_anisotropy(&self, max_anisotropy: uint) -> FilterMethod {
+        use std::cmp::{max, min};
+        match *self {
+            Anisotropic(_) if max_anisotropy <= 1 => Trilinear,
+            Anisotropic(fac) => Anisotropic(min(max(fac as uint, 1), max_anisotropy) as u8),
+            f => f,
+        }
+    }
+}
+
+/// The filter of a single sampling stage in `FilterMethod::Separate`.
+#[deriving(Eq, Ord, PartialEq, PartialOrd, Hash, Clone, Show)]
+pub enum FilterMode {
+    /// Take the nearest texel, or the nearest mipmap level.
+    Nearest,
+    /// Interpolate between the neighbouring texels, or mipmap levels.
+    Linear,
 }
 
 /// Specifies how a given texture may be used. The available texture types are
@@ -233,23 +479,40 @@ pub enum TextureKind {
     Texture2DMultiSampleArray(AaMode),
     /// A set of 6 2D textures, one for each face of a cube.
     ///
-    /// When creating a cube texture, the face is ignored, and storage for all 6 faces is created.
-    /// When updating, only the face specified is updated.
-    TextureCube(CubeFace),
+    /// Storage for all 6 faces is created at once, and the face to update
+    /// is selected by the `ImageInfo`.
+    TextureCube,
+    /// An array of cube textures. The depth is the number of cubes.
+    TextureCubeArray,
     /// A volume texture, with each 2D layer arranged contiguously.
     Texture3D,
+    /// A 1D array of texels stored in a buffer, see `TextureInfo::from_buffer`.
+    /// It has a single level and can't be updated or sampled with filtering.
+    /// Holds the number of texels, as the texture has no image dimensions.
+    TextureBuffer(uint),
 }
 
 /// The face of a cube texture to do an operation on.
 #[deriving(Eq, Ord, PartialEq, PartialOrd, Hash, Clone, Show)]
 #[allow(missing_docs)]
 pub enum CubeFace {
-    PosZ,
-    NegZ,
     PosX,
     NegX,
     PosY,
-    NegY
+    NegY,
+    PosZ,
+    NegZ,
+}
+
+/// All the faces of a cube, in the order they are stored in.
+pub static CUBE_FACES: [CubeFace, ..6] = [PosX, NegX, PosY, NegY, PosZ, NegZ];
+
+impl CubeFace {
+    /// Get the layer that addresses this face of the given cube, when
+    /// binding a cube texture (or cube array) as a render target.
+    pub fn to_layer(&self, cube: u16) -> u16 {
+        cube * 6 + *self as u16
+    }
 }
 
 impl TextureKind {
@@ -261,8 +524,58 @@ impl TextureKind {
             _ => None,
         }
     }
+
+    /// Get the number of layers a texture of this kind has, given its info.
+    fn get_layers(&self, info: &TextureInfo) -> u16 {
+        match *self {
+            Texture1DArray => info.height,
+            Texture2DArray | Texture2DMultiSampleArray(_) => info.depth,
+            TextureCube => 6,
+            TextureCubeArray => info.depth * 6,
+            _ => 1,
+        }
+    }
+
+    /// Check if a texture of this kind can be viewed as the other kind.
+    pub fn is_view_compatible(&self, other: &TextureKind) -> bool {
+        match (*self, *other) {
+            (Texture1D, Texture1D) | (Texture1D, Texture1DArray) |
+            (Texture1DArray, Texture1D) | (Texture1DArray, Texture1DArray) => true,
+            (Texture2D, Texture2D) | (Texture2D, Texture2DArray) => true,
+            (Texture2DArray, Texture2D) | (Texture2DArray, Texture2DArray) |
+            (Texture2DArray, TextureCube) | (Texture2DArray, TextureCubeArray) => true,
+            (TextureCube, Texture2D) | (TextureCube, Texture2DArray) |
+            (TextureCube, TextureCube) | (TextureCube, TextureCubeArray) => true,
+            (TextureCubeArray, Texture2D) | (TextureCubeArray, Texture2DArray) |
+            (TextureCubeArray, TextureCube) | (TextureCubeArray, TextureCubeArray) => true,
+            (Texture3D, Texture3D) => true,
+            (Texture2DMultiSample(a), Texture2DMultiSample(b)) |
+            (Texture2DMultiSample(a), Texture2DMultiSampleArray(b)) |
+            (Texture2DMultiSampleArray(a), Texture2DMultiSample(b)) |
+            (Texture2DMultiSampleArray(a), Texture2DMultiSampleArray(b)) => a == b,
+            _ => false,
+        }
+    }
 }
 
+/// Source of a single component when sampling a texture.
+#[deriving(Eq, Ord, PartialEq, PartialOrd, Hash, Clone, Show)]
+#[allow(missing_docs)]
+pub enum ComponentSwizzle {
+    SwizzleR,
+    SwizzleG,
+    SwizzleB,
+    SwizzleA,
+    SwizzleZero,
+    SwizzleOne,
+}
+
+/// Sources of the red, green, blue and alpha components, in that order.
+pub type Swizzle = (ComponentSwizzle, ComponentSwizzle, ComponentSwizzle, ComponentSwizzle);
+
+/// Swizzle that keeps all the components in place.
+pub static IDENTITY_SWIZZLE: Swizzle = (SwizzleR, SwizzleG, SwizzleB, SwizzleA);
+
 /// Describes the storage of a texture.
 ///
 /// # Portability note
@@ -282,6 +595,37 @@ pub struct TextureInfo {
     pub levels: u8,
     pub kind: TextureKind,
     pub format: Format,
+    /// Remapping of the components seen by the shader.
+    pub swizzle: Swizzle,
+}
+
+/// Describes a view into the storage of an existing texture, which is
+/// reinterpreted with a different kind, format or subset of levels and layers.
+#[allow(missing_docs)]
+#[deriving(Eq, Ord, PartialEq, PartialOrd, Hash, Clone, Show)]
+pub struct ViewInfo {
+    pub kind: TextureKind,
+    /// Format of the view, needs to be compatible with the texture format.
+    pub format: Format,
+    /// First mipmap level of the texture visible through the view.
+    pub min_level: u8,
+    pub levels: u8,
+    /// First array layer (or cube face) of the texture visible through the view.
+    pub min_layer: u16,
+    pub layers: u16,
+    /// Remapping of the components seen by the shader.
+    pub swizzle: Swizzle,
+}
+
+/// The access allowed to a texture level bound as an image in the shaders.
+#[deriving(Eq, Ord, PartialEq, PartialOrd, Hash, Clone, Show)]
+pub enum ImageAccess {
+    /// Only allow image loads.
+    ReadOnly,
+    /// Only allow image stores.
+    WriteOnly,
+    /// Allow both loads and stores, including atomic operations.
+    ReadWrite,
 }
 
 /// Describes a subvolume of a texture, which image data can be uploaded into.
@@ -298,6 +642,9 @@ pub struct ImageInfo {
     pub format: Format,
     /// Which mipmap to select.
     pub mipmap: u8,
+    /// Which face to select, required for cube textures. For cube arrays,
+    /// `zoffset` and `depth` select the range of cubes.
+    pub face: Option<CubeFace>,
 }
 
 impl Default for ImageInfo {
@@ -310,7 +657,8 @@ impl Default for ImageInfo {
             height: 1,
             depth: 1,
             format: RGBA8,
-            mipmap: 0
+            mipmap: 0,
+            face: None,
         }
     }
 }
@@ -324,6 +672,7 @@ impl Default for TextureInfo {
             levels: -1,
             kind: Texture2D,
             format: RGBA8,
+            swizzle: IDENTITY_SWIZZLE,
         }
     }
 }
@@ -334,6 +683,29 @@ impl TextureInfo {
         Default::default()
     }
 
+    /// Describe a buffer texture over `size` bytes of buffer data, with
+    /// each texel in the given format. The number of texels is only held by
+    /// the kind, the width, height and depth are zero.
+    pub fn from_buffer(size: uint, format: Format) -> Result<TextureInfo, TextureError> {
+        let texel = match format.get_size() {
+            Some(s) => s,
+            None => return Err(UnsupportedTextureFormat),
+        };
+        let texels = size / texel;
+        if size % texel != 0 || texels == 0 {
+            return Err(InvalidTextureBuffer(size))
+        }
+        Ok(TextureInfo {
+            width: 0,
+            height: 0,
+            depth: 0,
+            levels: 1,
+            kind: TextureBuffer(texels),
+            format: format,
+            swizzle: IDENTITY_SWIZZLE,
+        })
+    }
+
     /// Convert to a default ImageInfo that could be used
     /// to update the contents of the whole texture.
     pub fn to_image_info(&self) -> ImageInfo {
@@ -346,9 +718,107 @@ impl TextureInfo {
             depth: self.depth,
             format: self.format,
             mipmap: 0,
+            face: None,
+        }
+    }
+
+    /// Convert to an ImageInfo that covers the whole given mipmap level.
+    /// Dimensions are halved for each level (but never go below 1), except
+    /// for the array layers, which are preserved.
+    pub fn to_image_info_level(&self, level: u8) -> ImageInfo {
+        fn shift(size: u16, level: u8) -> u16 {
+            ::std::cmp::max(1, size >> level as uint)
+        }
+        let (height, depth) = match self.kind {
+            Texture1DArray => (self.height, self.depth),
+            Texture2DArray | Texture2DMultiSampleArray(_) | TextureCubeArray =>
+                (shift(self.height, level), self.depth),
+            Texture3D => (shift(self.height, level), shift(self.depth, level)),
+            _ => (shift(self.height, level), self.depth),
+        };
+        ImageInfo {
+            xoffset: 0,
+            yoffset: 0,
+            zoffset: 0,
+            width: shift(self.width, level),
+            height: height,
+            depth: depth,
+            format: self.format,
+            mipmap: level,
+            face: None,
         }
     }
 
+    /// Get the number of mipmap levels in the full chain, down to a single
+    /// texel. Array layers are not taken into account.
+    pub fn get_max_levels(&self) -> u8 {
+        use std::cmp::max;
+        let size = match self.kind {
+            TextureBuffer(_) => return 1,
+            Texture1D | Texture1DArray => self.width,
+            Texture3D => max(self.width, max(self.height, self.depth)),
+            _ => max(self.width, self.height),
+        };
+        ((size as f32).log2() + 1.0) as u8
+    }
+
+    /// Convert to a ViewInfo that covers the whole texture.
+    pub fn to_view_info(&self) -> ViewInfo {
+        ViewInfo {
+            kind: self.kind,
+            format: self.format,
+            min_level: 0,
+            levels: ::std::cmp::min(self.levels, self.get_max_levels()),
+            min_layer: 0,
+            layers: self.kind.get_layers(self),
+            swizzle: self.swizzle,
+        }
+    }
+
+    /// Get the description of a view into this texture, checking that
+    /// the view is compatible with it.
+    pub fn get_view(&self, view: &ViewInfo) -> Result<TextureInfo, TextureError> {
+        let levels = ::std::cmp::min(self.levels, self.get_max_levels());
+        let layers_valid = match view.kind {
+            Texture1DArray | Texture2DArray | Texture2DMultiSampleArray(_) => view.layers > 0,
+            TextureCube => view.layers == 6,
+            TextureCubeArray => view.layers > 0 && view.layers % 6 == 0,
+            _ => view.layers == 1,
+        };
+        // the faces of a cube have to be square
+        let shape_valid = match view.kind {
+            TextureCube | TextureCubeArray => self.width == self.height,
+            _ => true,
+        };
+        if !layers_valid || !shape_valid || view.levels == 0 ||
+                !self.kind.is_view_compatible(&view.kind) ||
+                !self.format.is_view_compatible(&view.format) ||
+                view.min_level as uint + view.levels as uint > levels as uint ||
+                view.min_layer as uint + view.layers as uint >
+                    self.kind.get_layers(self) as uint {
+            return Err(InvalidTextureView(*view))
+        }
+        let base = self.to_image_info_level(view.min_level);
+        Ok(TextureInfo {
+            width: base.width,
+            height: match view.kind {
+                Texture1D => 1,
+                Texture1DArray => view.layers,
+                _ => base.height,
+            },
+            depth: match view.kind {
+                Texture2DArray | Texture2DMultiSampleArray(_) => view.layers,
+                TextureCubeArray => view.layers / 6,
+                Texture3D => base.depth,
+                _ => 1,
+            },
+            levels: view.levels,
+            kind: view.kind,
+            format: view.format,
+            swizzle: view.swizzle,
+        })
+    }
+
     /// Convert to a `SurfaceInfo`, used as a common denominator between
     /// surfaces and textures.
     pub fn to_surface_info(&self) -> SurfaceInfo {
@@ -374,6 +844,20 @@ impl TextureInfo {
 impl ImageInfo {
     /// Create a new `ImageInfo`, using default values.
     pub fn new() -> ImageInfo { Default::default() }
+
+    /// Get the size in bytes of the data required to fill this image.
+    /// For compressed formats the size is rounded up to whole blocks.
+    pub fn get_data_size(&self) -> uint {
+        let (w, h, d) = (self.width as uint, self.height as uint, self.depth as uint);
+        match self.format {
+            Compressed(c) => {
+                let (bw, bh) = c.get_block_size();
+                let (bw, bh) = (bw as uint, bh as uint);
+                ((w + bw - 1) / bw) * ((h + bh - 1) / bh) * d * c.get_block_bytes()
+            },
+            f => w * h * d * f.get_size().unwrap(),
+        }
+    }
 }
 
 /// Specifies how texture coordinates outside the range `[0, 1]` are handled.
@@ -386,8 +870,13 @@ pub enum WrapMode {
     Mirror,
     /// Clamp the texture to the value at `0.0` or `1.0` respectively.
     Clamp,
+    /// Use the border color of the sampler outside of the texture.
+    Border,
 }
 
+/// The RGBA color used by the `Border` wrap mode.
+pub type BorderColor = (f32, f32, f32, f32);
+
 /// Specified how the Comparison operator should be used when sampling
 #[deriving(Eq, Ord, PartialEq, PartialOrd, Hash, Clone, Show)]
 pub enum ComparsionMode {
@@ -413,7 +902,9 @@ pub struct SamplerInfo {
     /// This range is used to clamp LOD level used for sampling
     pub lod_range: (f32, f32),
     /// comparison mode, used primary for a shadow map
-    pub comparison: ComparsionMode
+    pub comparison: ComparsionMode,
+    /// Color sampled outside of the texture with the `Border` wrap mode
+    pub border: BorderColor,
 }
 
 impl SamplerInfo {
@@ -425,7 +916,157 @@ impl SamplerInfo {
             wrap_mode: (wrap, wrap, wrap),
             lod_bias: 0.0,
             lod_range: (-1000.0, 1000.0),
-            comparison: NoComparsion
+            comparison: NoComparsion,
+            border: (0.0, 0.0, 0.0, 0.0),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{RGBA8, DEPTH16, DEPTH24STENCIL8, DEPTH32F_STENCIL8, STENCIL8};
+    use super::{Compressed, BC1_RGB, BC7_RGBA, ASTC_RGBA};
+    use super::{ImageInfo, TextureInfo, Texture2D, Texture2DArray, TextureCube};
+    use super::{TextureCubeArray, CUBE_FACES, PosX, PosY, NegZ};
+    use super::{IDENTITY_SWIZZLE, SwizzleR, SwizzleOne, InvalidTextureView, BC7_SRGBA};
+    use super::{Float, RGBA, TextureBuffer, InvalidTextureBuffer, UnsupportedTextureFormat};
+    use super::{Anisotropic, Trilinear};
+
+    #[test]
+    fn test_image_data_size() {
+        let img = ImageInfo {
+            width: 5,
+            height: 3,
+            ..ImageInfo::new()
+        };
+        assert_eq!(img.get_data_size(), 5 * 3 * 4);
+        assert_eq!(ImageInfo { format: Compressed(BC1_RGB), ..img }.get_data_size(), 2 * 1 * 8);
+        assert_eq!(ImageInfo { format: Compressed(BC7_RGBA), ..img }.get_data_size(), 2 * 1 * 16);
+        assert_eq!(ImageInfo { format: Compressed(ASTC_RGBA(6, 5)), ..img }.get_data_size(), 16);
+    }
+
+    #[test]
+    fn test_image_level() {
+        let info = TextureInfo {
+            width: 64,
+            height: 16,
+            depth: 6,
+            levels: 7,
+            kind: Texture2DArray,
+            format: Compressed(BC7_RGBA),
+            swizzle: IDENTITY_SWIZZLE,
+        };
+        let img = info.to_image_info_level(5);
+        assert_eq!((img.width, img.height, img.depth, img.mipmap), (2, 1, 6, 5));
+        assert_eq!(img.get_data_size(), 6 * 16);
+        let img = TextureInfo { kind: Texture2D, depth: 1, ..info }.to_image_info_level(3);
+        assert_eq!((img.width, img.height, img.depth), (8, 2, 1));
+    }
+
+    #[test]
+    fn test_depth_stencil_formats() {
+        assert_eq!(DEPTH16.get_components(), None);
+        assert_eq!(STENCIL8.get_components(), None);
+        assert!(DEPTH16.has_depth() && !DEPTH16.has_stencil());
+        assert!(!STENCIL8.has_depth() && STENCIL8.has_stencil());
+        assert!(DEPTH24STENCIL8.has_depth() && DEPTH24STENCIL8.has_stencil());
+        assert!(DEPTH32F_STENCIL8.has_depth() && DEPTH32F_STENCIL8.has_stencil());
+        assert!(!RGBA8.has_depth() && !RGBA8.has_stencil());
+    }
+
+    #[test]
+    fn test_texture_view() {
+        let info = TextureInfo {
+            width: 64,
+            height: 64,
+            depth: 12,
+            levels: 4,
+            kind: Texture2DArray,
+            format: Compressed(BC7_RGBA),
+            swizzle: IDENTITY_SWIZZLE,
+        };
+        let view = info.to_view_info();
+        assert_eq!(info.get_view(&view), Ok(info));
+
+        let cube = super::ViewInfo {
+            kind: TextureCube,
+            format: Compressed(BC7_SRGBA),
+            min_level: 1,
+            levels: 3,
+            min_layer: 6,
+            layers: 6,
+            swizzle: (SwizzleR, SwizzleR, SwizzleR, SwizzleOne),
+        };
+        let cube_info = info.get_view(&cube).unwrap();
+        assert_eq!((cube_info.width, cube_info.height, cube_info.depth), (32, 32, 1));
+        assert_eq!(cube_info.levels, 3);
+
+        let bad = super::ViewInfo { min_layer: 7, ..cube };
+        assert_eq!(info.get_view(&bad), Err(InvalidTextureView(bad)));
+        let bad = super::ViewInfo { format: RGBA8, ..cube };
+        assert_eq!(info.get_view(&bad), Err(InvalidTextureView(bad)));
+        let bad = super::ViewInfo { layers: 5, ..cube };
+        assert_eq!(info.get_view(&bad), Err(InvalidTextureView(bad)));
+        let wide = TextureInfo { width: 128, ..info };
+        assert_eq!(wide.get_view(&cube), Err(InvalidTextureView(cube)));
+    }
+
+    #[test]
+    fn test_cube_array_view() {
+        let info = TextureInfo {
+            width: 16,
+            height: 16,
+            depth: 18,
+            levels: 1,
+            kind: Texture2DArray,
+            format: RGBA8,
+            swizzle: IDENTITY_SWIZZLE,
+        };
+        let cubes = super::ViewInfo {
+            kind: TextureCubeArray,
+            min_layer: 6,
+            layers: 12,
+            ..info.to_view_info()
+        };
+        let cubes_info = info.get_view(&cubes).unwrap();
+        assert_eq!((cubes_info.width, cubes_info.height, cubes_info.depth), (16, 16, 2));
+        assert_eq!(TextureCubeArray.get_layers(&cubes_info), 12);
+
+        let bad = super::ViewInfo { layers: 9, ..cubes };
+        assert_eq!(info.get_view(&bad), Err(InvalidTextureView(bad)));
+        let bad = super::ViewInfo { layers: 0, ..cubes };
+        assert_eq!(info.get_view(&bad), Err(InvalidTextureView(bad)));
+        let tall = TextureInfo { height: 32, ..info };
+        assert_eq!(tall.get_view(&cubes), Err(InvalidTextureView(cubes)));
+    }
+
+    #[test]
+    fn test_cube_face_layer() {
+        assert_eq!(PosX.to_layer(0), 0);
+        assert_eq!(NegZ.to_layer(0), 5);
+        assert_eq!(PosY.to_layer(3), 20);
+        for (i, face) in CUBE_FACES.iter().enumerate() {
+            assert_eq!(face.to_layer(1) as uint, 6 + i);
+        }
+    }
+    #[test]
+    fn test_texture_buffer() {
+        let info = TextureInfo::from_buffer(64 * 16, Float(RGBA, ::attrib::F32)).unwrap();
+        assert_eq!((info.width, info.levels, info.kind), (0, 1, TextureBuffer(64)));
+        assert_eq!(TextureInfo::from_buffer(20, RGBA8), Err(InvalidTextureBuffer(20)));
+        assert_eq!(TextureInfo::from_buffer(0, RGBA8), Err(InvalidTextureBuffer(0)));
+        // thousands of instances with a matrix each
+        let info = TextureInfo::from_buffer(0x40000 * 16, Float(RGBA, ::attrib::F32)).unwrap();
+        assert_eq!(info.kind, TextureBuffer(0x40000));
+        assert_eq!(TextureInfo::from_buffer(16, Compressed(BC1_RGB)),
+                   Err(UnsupportedTextureFormat));
+    }
+
+    #[test]
+    fn test_clamp_anisotropy() {
+        assert_eq!(Anisotropic(16).clamp_anisotropy(8), Anisotropic(8));
+        assert_eq!(Anisotropic(0).clamp_anisotropy(8), Anisotropic(1));
+        assert_eq!(Anisotropic(4).clamp_anisotropy(1), Trilinear);
+        assert_eq!(Trilinear.clamp_anisotropy(16), Trilinear);
+    }
+}
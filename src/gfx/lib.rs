@@ -31,12 +31,17 @@ extern crate render;
 // public re-exports
 pub use render::{DeviceHelper, Renderer};
 pub use render::batch;
+pub use render::cache;
 pub use render::mesh::{Attribute, Mesh, VertexFormat};
+pub use render::mipmap;
+pub use render::preprocess;
 pub use render::mesh::{Slice, ToSlice};
 pub use render::mesh::{VertexSlice, IndexSlice8, IndexSlice16, IndexSlice32};
 pub use render::state::{DrawState, BlendAdditive, BlendAlpha};
 pub use render::shade;
 pub use render::target::{Frame, Plane, PlaneSurface, PlaneTexture};
+pub use render::texture;
+pub use render::watch;
 pub use device::Device;
 pub use device::{attrib, state, tex};
 pub use device::with_slice;
@@ -44,14 +49,25 @@ pub use device::{BufferHandle, BufferInfo, RawBufferHandle, ShaderHandle};
 pub use device::{ProgramHandle, SurfaceHandle, TextureHandle};
 pub use device::{BufferUsage, UsageStatic, UsageDynamic, UsageStream};
 pub use device::{VertexCount, InstanceCount};
+pub use device::{Barrier, BARRIER_VERTEX_ATTRIB, BARRIER_INDEX, BARRIER_UNIFORM,
+    BARRIER_TEXTURE_FETCH, BARRIER_IMAGE_ACCESS, BARRIER_COMMAND, BARRIER_BUFFER_UPDATE,
+    BARRIER_TEXTURE_UPDATE, BARRIER_FRAME_BUFFER, BARRIER_STORAGE, BARRIER_ALL};
 pub use device::{PrimitiveType, Point, Line, LineStrip,
     TriangleList, TriangleStrip, TriangleFan};
 pub use device::draw::CommandBuffer;
 pub use device::shade::UniformValue;
-pub use device::shade::{ValueI32, ValueF32};
+pub use device::shade::{ValueI32, ValueU32, ValueF32, ValueF64, ValueBool};
 pub use device::shade::{ValueI32Vector2, ValueI32Vector3, ValueI32Vector4};
+pub use device::shade::{ValueU32Vector2, ValueU32Vector3, ValueU32Vector4};
 pub use device::shade::{ValueF32Vector2, ValueF32Vector3, ValueF32Vector4};
+pub use device::shade::{ValueF64Vector2, ValueF64Vector3, ValueF64Vector4};
+pub use device::shade::{ValueBoolVector2, ValueBoolVector3, ValueBoolVector4};
 pub use device::shade::{ValueF32Matrix2, ValueF32Matrix3, ValueF32Matrix4};
+pub use device::shade::{ValueF32Matrix2x3, ValueF32Matrix2x4, ValueF32Matrix3x2,
+                        ValueF32Matrix3x4, ValueF32Matrix4x2, ValueF32Matrix4x3};
+pub use device::shade::{ValueF64Matrix2, ValueF64Matrix3, ValueF64Matrix4};
+pub use device::shade::{ValueF64Matrix2x3, ValueF64Matrix2x4, ValueF64Matrix3x2,
+                        ValueF64Matrix3x4, ValueF64Matrix4x2, ValueF64Matrix4x3};
 pub use device::shade::{ShaderSource, ProgramInfo};
 pub use device::target::{ColorValue, ClearData, Mask, Layer, Level, Rect, Target};
 pub use device::target::{COLOR, DEPTH, STENCIL};
@@ -76,10 +92,11 @@ impl<D: device::Device<C>, C: device::draw::CommandBuffer> Graphics<D, C> {
     /// Create a new graphics wrapper.
     pub fn new(mut device: D) -> Graphics<D, C> {
         let rend = device.create_renderer();
+        let max_attributes = device.get_capabilities().max_vertex_attributes;
         Graphics {
             device: device,
             renderer: rend,
-            context: BatchContext::new(),
+            context: BatchContext::new(max_attributes),
         }
     }
 
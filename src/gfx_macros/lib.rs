@@ -20,8 +20,8 @@
 #![feature(macro_rules, plugin_registrar, quote)]
 
 //! Macro extensions crate.
-//! Implements `shaders!` macro as well as `#[shader_param]` and
-//! `#[vertex_format]` attributes.
+//! Implements `shaders!` and `preprocess!` macros as well as `#[shader_param]`,
+//! `#[uniform_block]` and `#[vertex_format]` attributes.
 
 extern crate rustc;
 extern crate syntax;
@@ -32,7 +32,13 @@ use syntax::parse::token;
 use syntax::fold::Folder;
 use syntax::ptr::P;
 
+pub mod preprocess;
+// shared with `render` rather than linking the renderer into the plugin
+#[path = "../render/preprocess.rs"]
+#[allow(dead_code)]
+mod preprocessor;
 pub mod shader_param;
+pub mod uniform_block;
 pub mod vertex_format;
 
 /// Entry point for the plugin phase
@@ -43,9 +49,14 @@ pub fn registrar(reg: &mut rustc::plugin::Registry) {
     // Register the `#[shader_param]` attribute.
     reg.register_syntax_extension(intern("shader_param"),
         base::Decorator(box shader_param::expand));
+    // Register the `#[uniform_block]` attribute.
+    reg.register_syntax_extension(intern("uniform_block"),
+        base::Decorator(box uniform_block::expand));
     // Register the `#[vertex_format]` attribute.
     reg.register_syntax_extension(intern("vertex_format"),
         base::Decorator(box vertex_format::expand));
+    // Register the `preprocess!` macro.
+    reg.register_macro("preprocess", preprocess::expand);
 }
 
 /// Scan through the field's attributes and extract the field vertex name. If
@@ -74,6 +85,55 @@ fn find_name(cx: &mut ext::base::ExtCtxt, span: codemap::Span,
     })
 }
 
+/// The length of a fixed-size array type, if it is an integer literal
+fn literal_len(expr: &ast::Expr) -> Option<u64> {
+    match expr.node {
+        ast::ExprLit(ref lit) => match lit.node {
+            ast::LitInt(n, _) => Some(n),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether a fixed-size array type is a vector (`[f32, ..4]`) or a matrix
+/// of any shape (`[[f32, ..3], ..2]` is a `mat2x3`, and
+/// `[PaddedVec3<f32>, ..3]` a `mat3` in uniform blocks),
+/// rather than an array of them. Only literal lengths from 2 to 4 make
+/// vectors and matrices. Shader parameter fields don't depend on this, as
+/// the program tells their arrays from vectors and matrices at link time,
+/// and std140 lays out a matrix as the array of its columns, which uniform
+/// blocks accept either way.
+fn is_vector_or_matrix(node: &ast::Ty_) -> bool {
+    match *node {
+        ast::TyFixedLengthVec(ref ty, ref len) => match (literal_len(&**len), &ty.node) {
+            (Some(n), &ast::TyFixedLengthVec(ref inner, ref inner_len)) =>
+                n >= 2 && n <= 4 && is_component(&inner.node, false) &&
+                literal_len(&**inner_len).map_or(false, |m| m >= 2 && m <= 4),
+            (Some(n), node) => n >= 2 && n <= 4 && is_component(node, true),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Whether the type is a scalar that vectors and matrices are made of, or a
+/// padded matrix column (`PaddedVec3`) when `column` is set. Arrays of any
+/// other type, such as `[TextureParam, ..2]`, are never vectors.
+fn is_component(node: &ast::Ty_, column: bool) -> bool {
+    match *node {
+        ast::TyPath(ref path, _, _) => match path.segments.last() {
+            Some(segment) => match segment.identifier.name.as_str() {
+                "f32" | "f64" | "i32" | "u32" | "bool" => true,
+                "PaddedVec3" => column,
+                _ => false,
+            },
+            None => false,
+        },
+        _ => false,
+    }
+}
+
 /// Marker string to base the unique identifier generated by `extern_crate_hack()` on
 static EXTERN_CRATE_HACK: &'static str = "__gfx_extern_crate_hack";
 
@@ -171,53 +231,59 @@ fn fixup_extern_crate_paths(item: P<ast::Item>, path_root: ast::Ident) -> P<ast:
 // any names outside its lexical scope.
 #[macro_export]
 macro_rules! shaders {
-    (GLSL_120: $v:expr $($t:tt)*) => {
+    (__source $field:ident: $v:expr $($t:tt)*) => {
         {
             mod __gfx_extern_crate_hack {
                 extern crate "gfx" as gfx_;
                 pub use self::gfx_ as gfx;
             }
             __gfx_extern_crate_hack::gfx::ShaderSource {
-                glsl_120: Some($v),
+                $field: Some($v),
                 ..shaders!($($t)*)
             }
         }
     };
+    (GLSL_100_ES: $v:expr $($t:tt)*) => {
+        shaders!(__source glsl_100_es: $v $($t)*)
+    };
+    (GLSL_300_ES: $v:expr $($t:tt)*) => {
+        shaders!(__source glsl_300_es: $v $($t)*)
+    };
+    (GLSL_120: $v:expr $($t:tt)*) => {
+        shaders!(__source glsl_120: $v $($t)*)
+    };
     (GLSL_130: $v:expr $($t:tt)*) => {
-        {
-            mod __gfx_extern_crate_hack {
-                extern crate "gfx" as gfx_;
-                pub use self::gfx_ as gfx;
-            }
-            __gfx_extern_crate_hack::gfx::ShaderSource {
-                glsl_130: Some($v),
-                ..shaders!($($t)*)
-            }
-        }
+        shaders!(__source glsl_130: $v $($t)*)
     };
     (GLSL_140: $v:expr $($t:tt)*) => {
-        {
-            mod __gfx_extern_crate_hack {
-                extern crate "gfx" as gfx_;
-                pub use self::gfx_ as gfx;
-            }
-            __gfx_extern_crate_hack::gfx::ShaderSource {
-                glsl_140: Some($v),
-                ..shaders!($($t)*)
-            }
-        }
+        shaders!(__source glsl_140: $v $($t)*)
     };
     (GLSL_150: $v:expr $($t:tt)*) => {
-        {
-            mod __gfx_extern_crate_hack {
-                extern crate "gfx" as gfx_;
-                pub use self::gfx_ as gfx;
-            }
-            __gfx_extern_crate_hack::gfx::ShaderSource {
-                glsl_150: Some($v),
-                ..shaders!($($t)*)
-            }
-        }
+        shaders!(__source glsl_150: $v $($t)*)
+    };
+    (GLSL_330: $v:expr $($t:tt)*) => {
+        shaders!(__source glsl_330: $v $($t)*)
+    };
+    (GLSL_400: $v:expr $($t:tt)*) => {
+        shaders!(__source glsl_400: $v $($t)*)
+    };
+    (GLSL_410: $v:expr $($t:tt)*) => {
+        shaders!(__source glsl_410: $v $($t)*)
+    };
+    (GLSL_420: $v:expr $($t:tt)*) => {
+        shaders!(__source glsl_420: $v $($t)*)
+    };
+    (GLSL_430: $v:expr $($t:tt)*) => {
+        shaders!(__source glsl_430: $v $($t)*)
+    };
+    (GLSL_440: $v:expr $($t:tt)*) => {
+        shaders!(__source glsl_440: $v $($t)*)
+    };
+    (GLSL_450: $v:expr $($t:tt)*) => {
+        shaders!(__source glsl_450: $v $($t)*)
+    };
+    (SPIRV: $v:expr $($t:tt)*) => {
+        shaders!(__source spirv: $v $($t)*)
     };
     () => {
         {
@@ -226,10 +292,20 @@ macro_rules! shaders {
                 pub use self::gfx_ as gfx;
             }
             __gfx_extern_crate_hack::gfx::ShaderSource {
+                glsl_100_es: None,
+                glsl_300_es: None,
                 glsl_120: None,
                 glsl_130: None,
                 glsl_140: None,
                 glsl_150: None,
+                glsl_330: None,
+                glsl_400: None,
+                glsl_410: None,
+                glsl_420: None,
+                glsl_430: None,
+                glsl_440: None,
+                glsl_450: None,
+                spirv: None,
             }
         }
     }
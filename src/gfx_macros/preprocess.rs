@@ -0,0 +1,110 @@
+// Copyright 2014 The Gfx-rs Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cell::RefCell;
+use std::io::File;
+use std::rc::Rc;
+use std::str;
+use syntax::{ast, codemap, ext};
+use syntax::ext::base::{DummyResult, MacExpr, MacResult};
+use syntax::ext::build::AstBuilder;
+use preprocessor::{FileSystem, Preprocessor};
+
+/// A directory on disk, remembering the files read from it.
+struct IncludeDir {
+    root: Path,
+    files: RefCell<Vec<(Path, Vec<u8>)>>,
+}
+
+impl<'a> FileSystem for &'a IncludeDir {
+    fn read(&self, path: &str) -> Option<Vec<u8>> {
+        let path = self.root.join(path);
+        let data = File::open(&path).read_to_end().ok();
+        match data {
+            Some(ref data) => self.files.borrow_mut().push((path, data.clone())),
+            None => (),
+        }
+        data
+    }
+}
+
+/// The contents of a string or byte string literal
+fn literal_bytes(expr: &ast::Expr) -> Option<Vec<u8>> {
+    match expr.node {
+        ast::ExprLit(ref lit) => match lit.node {
+            ast::LitStr(ref s, _) => Some(s.get().as_bytes().to_vec()),
+            ast::LitBinary(ref data) => Some(data.as_slice().to_vec()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Expand `preprocess!("dir", b"source", "NAME value", ..)` into the
+/// preprocessed source, with the includes resolved against `dir`, relative
+/// to the current file. Meant to be used inside `shaders!`.
+pub fn expand(cx: &mut ext::base::ExtCtxt, span: codemap::Span,
+              tts: &[ast::TokenTree]) -> Box<MacResult + 'static> {
+    let args = match ext::base::get_exprs_from_tts(cx, span, tts) {
+        Some(args) => args,
+        None => return DummyResult::expr(span),
+    };
+    let literals: Vec<Option<Vec<u8>>> = args.iter().map(|e| literal_bytes(&**e)).collect();
+    let (dir, source) = match literals.as_slice() {
+        [Some(ref dir), Some(ref source), ..] => match str::from_utf8(dir.as_slice()) {
+            Some(dir) => (dir, source),
+            None => {
+                cx.span_err(args[0].span, "Invalid include directory");
+                return DummyResult::expr(span)
+            },
+        },
+        _ => {
+            cx.span_err(span, "`preprocess!` takes the include directory and the \
+                               shader source, followed by the defines");
+            return DummyResult::expr(span)
+        },
+    };
+    let mut root = Path::new(cx.codemap().span_to_filename(span));
+    root.pop();
+    root.push(dir);
+    let include_dir = IncludeDir {
+        root: root,
+        files: RefCell::new(Vec::new()),
+    };
+    let mut preprocessor = Preprocessor::new(&include_dir);
+    for (arg, define) in args.iter().zip(literals.iter()).skip(2) {
+        match define.as_ref().and_then(|d| str::from_utf8(d.as_slice())) {
+            Some(define) => match define.find(' ') {
+                Some(i) => preprocessor.define(define.slice_to(i), define.slice_from(i + 1).trim()),
+                None => preprocessor.define(define, ""),
+            },
+            None => cx.span_err(arg.span, "Defines have to be string literals, \
+                                           such as `\"SHADOWS 1\"`"),
+        }
+    }
+    let result = preprocessor.process(source.as_slice());
+    // add the included files to the code map, like `include_str!` does, so
+    // that they are listed as dependencies of the crate
+    for &(ref path, ref data) in include_dir.files.borrow().iter() {
+        cx.codemap().new_filemap(path.display().to_string(),
+                                 String::from_utf8_lossy(data.as_slice()).into_string());
+    }
+    match result {
+        Ok(out) => MacExpr::new(cx.expr_lit(span, ast::LitBinary(Rc::new(out)))),
+        Err(e) => {
+            cx.span_err(span, format!("Unable to preprocess the shader: {}", e).as_slice());
+            DummyResult::expr(span)
+        },
+    }
+}
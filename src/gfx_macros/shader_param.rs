@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use syntax::{ast, ast_util, ext};
+use syntax::{ast, ast_util, attr, ext};
 use syntax::ext::build::AstBuilder;
 use syntax::ext::deriving::generic;
 use syntax::codemap;
@@ -23,22 +23,76 @@ use syntax::ptr::P;
 enum ParamType {
     ParamUniform,
     ParamBlock,
+    ParamTypedBlock,
     ParamTexture,
+    ParamImage,
+    ParamStorageBlock,
+    ParamNested,
+}
+
+/// How a field holds the values of its parameter
+#[deriving(PartialEq, Show)]
+enum ParamShape {
+    /// A single value
+    ShapeSingle,
+    /// An `Option` of a value, that is not bound when `None`
+    ShapeOptional,
+    /// A fixed-size array of values, one per element of the shader array
+    ShapeArray,
 }
 
 #[deriving(Show)]
 enum ParamError {
     ErrorDeprecatedTexture,
+    ErrorUnsupportedShape,
+}
+
+/// Whether the field is marked `#[nested]`, holding another parameter
+/// structure (or an array of them) whose variables are prefixed by the
+/// field name
+fn is_nested(attributes: &[ast::Attribute]) -> bool {
+    attributes.iter().any(|attribute| match attribute.node.value.node {
+        ast::MetaWord(ref word) if word.get() == "nested" => {
+            attr::mark_used(attribute);
+            true
+        },
+        _ => false,
+    })
 }
 
-/// Classify variable types (`i32`, `TextureParam`, etc) into the `ParamType`
-fn classify(node: &ast::Ty_) -> Result<ParamType, ParamError> {
+/// Split the field type into the type of its values and the `ParamShape`.
+/// Any fixed-size array is an array of values, whether its length is a
+/// literal or a constant: the program decides at link time if an array of
+/// uniforms is a shader array, or a vector or matrix made of its elements.
+fn decompose(ty: &P<ast::Ty>) -> (P<ast::Ty>, ParamShape) {
+    match ty.node {
+        ast::TyFixedLengthVec(ref elem, _) => return (elem.clone(), ShapeArray),
+        ast::TyPath(ref path, _, _) => match path.segments.last() {
+            Some(segment) if segment.identifier.name.as_str() == "Option" =>
+                match segment.types.as_slice().head() {
+                    Some(inner) => return (inner.clone(), ShapeOptional),
+                    None => (),
+                },
+            _ => (),
+        },
+        _ => (),
+    }
+    (ty.clone(), ShapeSingle)
+}
+
+/// Classify value types (`i32`, `TextureParam`, etc) into the `ParamType`
+fn classify_value(node: &ast::Ty_) -> Result<ParamType, ParamError> {
     match *node {
+        ast::TyFixedLengthVec(..) if !super::is_vector_or_matrix(node) => Err(ErrorUnsupportedShape),
         ast::TyPath(ref path, _, _) => match path.segments.last() {
             Some(segment) => match segment.identifier.name.as_str() {
                 "RawBufferHandle" => Ok(ParamBlock),
+                "BufferHandle" => Ok(ParamTypedBlock),
                 "TextureParam" => Ok(ParamTexture),
+                "ImageParam" => Ok(ParamImage),
+                "StorageBufferParam" => Ok(ParamStorageBlock),
                 "TextureHandle" => Err(ErrorDeprecatedTexture),
+                "Option" => Err(ErrorUnsupportedShape),
                 _ => Ok(ParamUniform),
             },
             None => Ok(ParamUniform),
@@ -47,6 +101,31 @@ fn classify(node: &ast::Ty_) -> Result<ParamType, ParamError> {
     }
 }
 
+/// Classify the field into the `ParamType` and `ParamShape`
+fn classify(field: &ast::StructField) -> Result<(ParamType, ParamShape), ParamError> {
+    if is_nested(field.node.attrs.as_slice()) {
+        return match field.node.ty.node {
+            ast::TyFixedLengthVec(..) => Ok((ParamNested, ShapeArray)),
+            ast::TyPath(..) => Ok((ParamNested, ShapeSingle)),
+            _ => Err(ErrorUnsupportedShape),
+        }
+    }
+    let (value_ty, shape) = decompose(&field.node.ty);
+    classify_value(&value_ty.node).map(|ptype| (ptype, shape))
+}
+
+/// The name of the `ParamValues` vector and the var id type of a `ParamType`
+fn category(ptype: ParamType) -> (&'static str, &'static str) {
+    match ptype {
+        ParamUniform => ("uniforms", "VarUniform"),
+        ParamBlock | ParamTypedBlock => ("blocks", "VarBlock"),
+        ParamTexture => ("textures", "VarTexture"),
+        ParamImage => ("images", "VarImage"),
+        ParamStorageBlock => ("storage_blocks", "VarStorageBlock"),
+        ParamNested => ("", "NestedLink"),
+    }
+}
+
 /// Generates the the method body for `gfx::shade::ParamValues::create_link`
 fn method_create(cx: &mut ext::base::ExtCtxt, span: codemap::Span,
                  substr: &generic::Substructure,
@@ -61,15 +140,17 @@ fn method_create(cx: &mut ext::base::ExtCtxt, span: codemap::Span,
                     cx.field_imm(fspan, fname, cx.expr_none(fspan))
                 }).collect()
             );
-            let class_info: Vec<(ParamType, P<ast::Expr>)> = definition.fields.iter()
-                    .zip(fields.iter()).scan((), |_, (def, &(fname, fspan))|
-                match classify(&def.node.ty.node) {
-                    Ok(c) => {
+            let class_info: Vec<(ParamType, ParamShape, token::InternedString)> =
+                    definition.fields.iter().zip(fields.iter()).scan((), |_, (def, &(fname, fspan))|
+                match classify(def) {
+                    Ok((ptype, shape)) => {
                         let name = match super::find_name(cx, span, def.node.attrs.as_slice()) {
                             Some(name) => name,
+                            None if ptype == ParamNested && shape == ShapeSingle =>
+                                token::intern_and_get_ident(format!("{}.", fname.as_str()).as_slice()),
                             None => token::get_ident(fname),
                         };
-                        Some((c, cx.expr_str(fspan, name)))
+                        Some((ptype, shape, name))
                     },
                     Err(e) => {
                         cx.span_err(fspan, format!(
@@ -81,45 +162,157 @@ fn method_create(cx: &mut ext::base::ExtCtxt, span: codemap::Span,
                     },
                 }
             ).collect();
-            let gen_arms = |ptype: ParamType, var: ast::Ident| -> Vec<ast::Arm> {
-                class_info.iter().zip(fields.iter())
-                          .filter(|&(&(class, _), _)| class == ptype)
-                          .map(|(&(_, ref name_expr), &(fname, fspan))|
-                    cx.arm(fspan,
-                        vec![cx.pat_lit(fspan, name_expr.clone())],
+            let input = &substr.nonself_args[1];
+            let gen_arms = |cx: &mut ext::base::ExtCtxt, ptypes: &[ParamType]| -> Vec<ast::Arm> {
+                class_info.iter().zip(fields.iter()).zip(definition.fields.iter())
+                          .filter(|&((&(ptype, _, _), _), _)| ptypes.contains(&ptype) || ptype == ParamNested)
+                          .map(|((&(ptype, shape, ref name), &(fname, fspan)), def)| {
+                    let name_expr = cx.expr_str(fspan, name.clone());
+                    let var = cx.ident_of(category(ptypes[0]).val1());
+                    let (value_ty, _) = decompose(&def.node.ty);
+                    let check = if ptype == ParamTypedBlock {
                         quote_expr!(cx,
-                            out.$fname = Some(i as $path_root::gfx::shade::$var)
+                            try!($path_root::gfx::shade::check_block(None::<&$value_ty>, var))
                         )
-                    )
-                ).collect()
+                    } else {
+                        quote_expr!(cx, ())
+                    };
+                    match (ptype, shape) {
+                        // claimed by one of the elements of the nested array
+                        (ParamNested, ShapeArray) => {
+                            let prefix = token::intern_and_get_ident(
+                                format!("{}[", name.get()).as_slice());
+                            let prefix_expr = cx.expr_str(fspan, prefix);
+                            ast::Arm {
+                                attrs: Vec::new(),
+                                pats: vec![cx.pat_ident(fspan, cx.ident_of("name"))],
+                                guard: Some(quote_expr!(cx, name.starts_with($prefix_expr))),
+                                body: quote_expr!(cx, ()),
+                            }
+                        },
+                        // claimed by the nested structure
+                        (ParamNested, _) => ast::Arm {
+                            attrs: Vec::new(),
+                            pats: vec![cx.pat_ident(fspan, cx.ident_of("name"))],
+                            guard: Some(quote_expr!(cx, name.starts_with($name_expr))),
+                            body: quote_expr!(cx, ()),
+                        },
+                        // uniform array elements are reported one by one, in
+                        // order, unless the program declares a vector or matrix
+                        (ParamUniform, ShapeArray) => {
+                            let len = match def.node.ty.node {
+                                ast::TyFixedLengthVec(_, ref len) => len.clone(),
+                                _ => cx.expr_uint(fspan, 0),
+                            };
+                            ast::Arm {
+                                attrs: Vec::new(),
+                                pats: vec![cx.pat_ident(fspan, cx.ident_of("name"))],
+                                guard: Some(quote_expr!(cx,
+                                    name == $name_expr ||
+                                    $path_root::gfx::shade::is_array_element(name, $name_expr)
+                                )),
+                                body: quote_expr!(cx,
+                                    out.$fname = Some(try!($path_root::gfx::shade::link_uniform_array(
+                                        out.$fname, var, i, $len)))
+                                ),
+                            }
+                        },
+                        // array elements are reported one by one, in order
+                        (_, ShapeArray) => {
+                            let len = match def.node.ty.node {
+                                ast::TyFixedLengthVec(_, ref len) => len.clone(),
+                                _ => cx.expr_uint(fspan, 0),
+                            };
+                            ast::Arm {
+                                attrs: Vec::new(),
+                                pats: vec![cx.pat_ident(fspan, cx.ident_of("name"))],
+                                guard: Some(quote_expr!(cx,
+                                    $path_root::gfx::shade::is_array_element(name, $name_expr)
+                                )),
+                                body: quote_expr!(cx, {
+                                    $check;
+                                    let (first, count) = out.$fname.unwrap_or(
+                                        (i as $path_root::gfx::shade::$var, 0));
+                                    if count as uint >= $len {
+                                        return Err($path_root::gfx::shade::ArraySizeMismatch(name.to_string()))
+                                    }
+                                    out.$fname = Some((first, count + 1))
+                                }),
+                            }
+                        },
+                        (_, _) => cx.arm(fspan,
+                            vec![cx.pat_lit(fspan, name_expr)],
+                            quote_expr!(cx, {
+                                $check;
+                                out.$fname = Some(i as $path_root::gfx::shade::$var)
+                            })
+                        ),
+                    }
+                }).collect()
             };
-            let uniform_arms = gen_arms(ParamUniform, cx.ident_of("VarUniform"));
-            let block_arms = gen_arms(ParamBlock, cx.ident_of("VarBlock"));
-            let texture_arms = gen_arms(ParamTexture, cx.ident_of("VarTexture"));
-            let input = &substr.nonself_args[1];
+            let uniform_arms = gen_arms(cx, [ParamUniform]);
+            let block_arms = gen_arms(cx, [ParamBlock, ParamTypedBlock]);
+            let texture_arms = gen_arms(cx, [ParamTexture]);
+            let image_arms = gen_arms(cx, [ParamImage]);
+            let storage_arms = gen_arms(cx, [ParamStorageBlock]);
+            // nested structures link against their own variables
+            let nested_links: Vec<P<ast::Expr>> = class_info.iter().zip(fields.iter())
+                    .zip(definition.fields.iter())
+                    .filter(|&((&(ptype, _, _), _), _)| ptype == ParamNested)
+                    .map(|((&(_, shape, ref name), &(fname, fspan)), def)| {
+                let name_expr = cx.expr_str(fspan, name.clone());
+                match (shape, &def.node.ty.node) {
+                    (ShapeArray, &ast::TyFixedLengthVec(ref ty, ref len)) => quote_expr!(cx,
+                        out.$fname = Some(try!($path_root::gfx::shade::link_nested_array(
+                            None::<&$ty>, $input, $name_expr, $len)))
+                    ),
+                    _ => {
+                        let ty = def.node.ty.clone();
+                        quote_expr!(cx,
+                            out.$fname = Some(try!($path_root::gfx::shade::link_nested(
+                                None::<&$ty>, $input, $name_expr)))
+                        )
+                    },
+                }
+            }).collect();
             quote_expr!(cx, {
                 let mut out = $init_expr;
-                for (i, u) in $input.uniforms.iter().enumerate() {
+                for (i, var) in $input.uniforms.iter().enumerate() {
                     let _ = i; // suppress warning about unused i
-                    match u.name.as_slice() {
+                    match var.name.as_slice() {
                         $uniform_arms
-                        _ => return Err($path_root::gfx::shade::MissingUniform(u.name.clone())),
+                        _ => return Err($path_root::gfx::shade::MissingUniform(var.name.clone())),
                     }
                 }
-                for (i, b) in $input.blocks.iter().enumerate() {
+                for (i, var) in $input.blocks.iter().enumerate() {
                     let _ = i; // suppress warning about unused i
-                    match b.name.as_slice() {
+                    match var.name.as_slice() {
                         $block_arms
-                        _ => return Err($path_root::gfx::shade::MissingBlock(b.name.clone())),
+                        _ => return Err($path_root::gfx::shade::MissingBlock(var.name.clone())),
                     }
                 }
-                for (i, t) in $input.textures.iter().enumerate() {
+                for (i, var) in $input.textures.iter().enumerate() {
                     let _ = i; // suppress warning about unused i
-                    match t.name.as_slice() {
+                    match var.name.as_slice() {
                         $texture_arms
-                        _ => return Err($path_root::gfx::shade::MissingTexture(t.name.clone())),
+                        _ => return Err($path_root::gfx::shade::MissingTexture(var.name.clone())),
                     }
                 }
+                for (i, var) in $input.images.iter().enumerate() {
+                    let _ = i; // suppress warning about unused i
+                    match var.name.as_slice() {
+                        $image_arms
+                        _ => return Err($path_root::gfx::shade::MissingImage(var.name.clone())),
+                    }
+                }
+                for (i, var) in $input.storage_blocks.iter().enumerate() {
+                    let _ = i; // suppress warning about unused i
+                    match var.name.as_slice() {
+                        $storage_arms
+                        _ => return Err($path_root::gfx::shade::MissingStorageBlock(var.name.clone())),
+                    }
+                }
+                $nested_links;
                 Ok(out)
             })
         },
@@ -144,6 +337,8 @@ fn method_fill(cx: &mut ext::base::ExtCtxt, span: codemap::Span,
                 quote_stmt!(cx, $out.uniforms.reserve($max_num);),
                 quote_stmt!(cx, $out.blocks.reserve($max_num);),
                 quote_stmt!(cx, $out.textures.reserve($max_num);),
+                quote_stmt!(cx, $out.images.reserve($max_num);),
+                quote_stmt!(cx, $out.storage_blocks.reserve($max_num);),
             ];
             calls.extend(definition.fields.iter().zip(fields.iter())
                                    .map(|(def, f)| {
@@ -153,38 +348,87 @@ fn method_fill(cx: &mut ext::base::ExtCtxt, span: codemap::Span,
                     substr.nonself_args[0].clone(),
                     f.name.unwrap()
                     );
-                match classify(&def.node.ty.node) {
-                    Ok(ParamUniform) => quote_stmt!(cx,
-                        $var_id.map_or((), |id| {
-                            if $out.uniforms.len() <= id as uint {
-                                unsafe { $out.uniforms.set_len(id as uint + 1) }
-                            }
-                            *$out.uniforms.get_mut(id as uint).unwrap() = $value_id.to_uniform()
-                        })
-                    ),
-                    Ok(ParamBlock)   => quote_stmt!(cx,
-                        $var_id.map_or((), |id| {
-                            if $out.blocks.len() <= id as uint {
-                                unsafe { $out.blocks.set_len(id as uint + 1) }
-                            }
-                            *$out.blocks.get_mut(id as uint).unwrap() = {$value_id}
-                        })
-                    ),
-                    Ok(ParamTexture) => quote_stmt!(cx,
-                        $var_id.map_or((), |id| {
-                            if $out.textures.len() <= id as uint {
-                                unsafe { $out.textures.set_len(id as uint + 1) }
-                            }
-                            *$out.textures.get_mut(id as uint).unwrap() = {$value_id}
-                        })
-                    ),
+                let (ptype, shape) = match classify(def) {
+                    Ok(class) => class,
                     Err(_) => {
                         cx.span_err(span, format!(
                             "Invalid uniform: {}",
                             f.name.unwrap().as_str(),
                             ).as_slice()
                         );
-                        cx.stmt_expr(cx.expr_uint(span, 0))
+                        return cx.stmt_expr(cx.expr_uint(span, 0))
+                    },
+                };
+                if ptype == ParamNested && shape == ShapeArray {
+                    return quote_stmt!(cx,
+                        $var_id.as_ref().map_or((), |links| {
+                            $path_root::gfx::shade::fill_nested_array($value_id.as_slice(),
+                                links.as_slice(),
+                                $path_root::gfx::shade::ParamValues {
+                                    uniforms: &mut *$out.uniforms,
+                                    blocks: &mut *$out.blocks,
+                                    textures: &mut *$out.textures,
+                                    images: &mut *$out.images,
+                                    storage_blocks: &mut *$out.storage_blocks,
+                                })
+                        })
+                    )
+                }
+                if ptype == ParamNested {
+                    return quote_stmt!(cx,
+                        $var_id.as_ref().map_or((), |link| {
+                            $path_root::gfx::shade::fill_nested(&$value_id, link,
+                                $path_root::gfx::shade::ParamValues {
+                                    uniforms: &mut *$out.uniforms,
+                                    blocks: &mut *$out.blocks,
+                                    textures: &mut *$out.textures,
+                                    images: &mut *$out.images,
+                                    storage_blocks: &mut *$out.storage_blocks,
+                                })
+                        })
+                    )
+                }
+                let values = cx.ident_of(category(ptype).val0());
+                // convert a reference to the value into the stored parameter
+                let convert = |cx: &mut ext::base::ExtCtxt, v: P<ast::Expr>| match ptype {
+                    ParamUniform => quote_expr!(cx, $v.to_uniform()),
+                    ParamTypedBlock => quote_expr!(cx, $v.raw()),
+                    _ => quote_expr!(cx, *$v),
+                };
+                match shape {
+                    ShapeSingle => {
+                        let value = convert(cx, quote_expr!(cx, (&$value_id)));
+                        quote_stmt!(cx,
+                            $var_id.map_or((), |id| {
+                                $path_root::gfx::shade::set_value($out.$values, id as uint, Some($value))
+                            })
+                        )
+                    },
+                    ShapeOptional => {
+                        let value = convert(cx, quote_expr!(cx, v));
+                        quote_stmt!(cx,
+                            $var_id.map_or((), |id| {
+                                $path_root::gfx::shade::set_value($out.$values, id as uint,
+                                    $value_id.as_ref().map(|v| $value))
+                            })
+                        )
+                    },
+                    ShapeArray if ptype == ParamUniform => quote_stmt!(cx,
+                        $var_id.map_or((), |link| {
+                            $path_root::gfx::shade::fill_uniform_array($value_id.as_slice(),
+                                link, $out.uniforms)
+                        })
+                    ),
+                    ShapeArray => {
+                        let value = convert(cx, quote_expr!(cx, v));
+                        quote_stmt!(cx,
+                            $var_id.map_or((), |(id, count)| {
+                                for (k, v) in $value_id.iter().take(count as uint).enumerate() {
+                                    $path_root::gfx::shade::set_value($out.$values,
+                                        id as uint + k, Some($value))
+                                }
+                            })
+                        )
                     },
                 }
             }));
@@ -208,27 +452,63 @@ fn method_fill(cx: &mut ext::base::ExtCtxt, span: codemap::Span,
     }
 }
 
-/// A helper function that translates variable type (`i32`, `TextureHandle`, etc)
-/// into the corresponding shader var id type (`VarUniform`, `VarBlock`, or `VarTexture`)
+/// The link type of a nested parameter structure, `foo::_MaterialLink` for
+/// `foo::Material`
+fn link_type(cx: &mut ext::base::ExtCtxt, path: &ast::Path) -> P<ast::Ty> {
+    let mut path = path.clone();
+    match path.segments.last_mut() {
+        Some(segment) => {
+            segment.identifier = cx.ident_of(format!("_{}Link",
+                segment.identifier.as_str()).as_slice());
+            segment.types = ::syntax::owned_slice::OwnedSlice::empty();
+        },
+        None => (),
+    }
+    cx.ty_path(path, None)
+}
+
+/// A helper function that translates the field type (`i32`, `TextureParam`, etc)
+/// into the corresponding shader var id type (`VarUniform`, `VarBlock`, `VarTexture`,
+/// `VarImage`, `VarStorageBlock`, their array versions, `NestedLink` or
+/// `NestedLinkArray`)
 fn node_to_var_type(cx: &mut ext::base::ExtCtxt,
-                    span: codemap::Span, node: &ast::Ty_,
+                    span: codemap::Span, field: &ast::StructField,
                     path_root: ast::Ident) -> P<ast::Ty> {
-    let id = match classify(node) {
-        Ok(ParamUniform) => "VarUniform",
-        Ok(ParamBlock)   => "VarBlock",
-        Ok(ParamTexture) => "VarTexture",
+    let (id, params) = match classify(field) {
+        Ok((ParamNested, _)) => match field.node.ty.node {
+            ast::TyFixedLengthVec(ref ty, _) => match ty.node {
+                ast::TyPath(ref path, _, _) =>
+                    ("NestedLinkArray".to_string(), vec![link_type(cx, path)]),
+                _ => {
+                    cx.span_err(span, "Nested parameters have to be named structures");
+                    ("".to_string(), Vec::new())
+                },
+            },
+            ast::TyPath(ref path, _, _) =>
+                ("NestedLink".to_string(), vec![link_type(cx, path)]),
+            _ => {
+                cx.span_err(span, "Nested parameters have to be named structures");
+                ("".to_string(), Vec::new())
+            },
+        },
+        Ok((ptype, ShapeArray)) => (format!("{}Array", category(ptype).val1()), Vec::new()),
+        Ok((ptype, _)) => (category(ptype).val1().to_string(), Vec::new()),
         Err(ErrorDeprecatedTexture) => {
             cx.span_err(span, "Use gfx::shade::TextureParam for texture vars instead of gfx::shade::TextureHandle");
-            ""
+            ("".to_string(), Vec::new())
+        },
+        Err(ErrorUnsupportedShape) => {
+            cx.span_err(span, "Parameters can only be a single value, an `Option` or an array of them");
+            ("".to_string(), Vec::new())
         },
     };
     cx.ty_option(cx.ty_path(
-        cx.path(span, vec![
+        cx.path_all(span, false, vec![
             path_root,
             cx.ident_of("gfx"),
             cx.ident_of("shade"),
-            cx.ident_of(id),
-        ]),
+            cx.ident_of(id.as_slice()),
+        ], Vec::new(), params),
         None
     ))
 }
@@ -262,7 +542,7 @@ pub fn expand(context: &mut ext::base::ExtCtxt, span: codemap::Span,
                         node: ast::StructField_ {
                             kind: f.node.kind,
                             id: f.node.id,
-                            ty: node_to_var_type(context, f.span, &f.node.ty.node, path_root),
+                            ty: node_to_var_type(context, f.span, f, path_root),
                             attrs: Vec::new(),
                         },
                         span: f.span,
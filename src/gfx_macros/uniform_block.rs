@@ -0,0 +1,143 @@
+// Copyright 2014 The Gfx-rs Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use syntax::{ast, ext};
+use syntax::ext::build::AstBuilder;
+use syntax::ext::deriving::generic;
+use syntax::codemap;
+use syntax::parse::token;
+use syntax::ptr::P;
+
+/// Split the field type into the element type and the array length, if it is
+/// an array of vectors, matrices or scalars.
+fn decode_element(cx: &mut ext::base::ExtCtxt, span: codemap::Span,
+                  ty: &P<ast::Ty>) -> (P<ast::Ty>, P<ast::Expr>) {
+    match ty.node {
+        ast::TyFixedLengthVec(ref elem, ref len) if !super::is_vector_or_matrix(&ty.node) =>
+            (elem.clone(), quote_expr!(cx, Some($len as uint))),
+        ast::TyFixedLengthVec(..) | ast::TyPath(..) =>
+            (ty.clone(), quote_expr!(cx, None)),
+        _ => {
+            cx.span_err(span, format!("Unsupported block member type: `{}`",
+                                      ty.node).as_slice());
+            (ty.clone(), quote_expr!(cx, None))
+        },
+    }
+}
+
+/// Generates the the method body for `gfx::shade::UniformBlock::get_members`.
+fn method_body(cx: &mut ext::base::ExtCtxt, span: codemap::Span,
+               substr: &generic::Substructure,
+               path_root: ast::Ident) -> P<ast::Expr> {
+    match *substr.fields {
+        generic::StaticStruct(ref definition, generic::Named(ref fields)) => {
+            let member_pushes = definition.fields.iter().zip(fields.iter())
+                .map(|(def, &(ident, fspan))| {
+                    let struct_ident = substr.type_ident;
+                    let (elem_ty, len_expr) = decode_element(cx, fspan, &def.node.ty);
+                    let name = match super::find_name(cx, span, def.node.attrs.as_slice()) {
+                        Some(name) => name,
+                        None => token::get_ident(ident),
+                    };
+                    let name = name.get();
+                    quote_expr!(cx, {
+                        members.push($path_root::gfx::shade::describe_member(
+                            $name,
+                            unsafe {
+                                let x: $struct_ident = ::std::mem::uninitialized();
+                                let offset = (&x.$ident as *const _ as uint) -
+                                    (&x as *const _ as uint);
+                                ::std::mem::forget(x);
+                                offset
+                            },
+                            None::<&$elem_ty>,
+                            $len_expr
+                        ));
+                    })
+                }).collect::<Vec<P<ast::Expr>>>();
+            let capacity = fields.len();
+            quote_expr!(cx, {
+                let mut members = Vec::with_capacity($capacity);
+                $member_pushes;
+                members
+            })
+        },
+        _ => {
+            cx.span_err(span, "Unable to implement `gfx::shade::UniformBlock::get_members` \
+                              on a non-structure");
+            cx.expr_lit(span, ast::LitNil)
+        }
+    }
+}
+
+/// Derive a `gfx::shade::UniformBlock` implementation for the `struct`
+pub fn expand(context: &mut ext::base::ExtCtxt, span: codemap::Span,
+              meta_item: &ast::MetaItem, item: &ast::Item,
+              push: |P<ast::Item>|) {
+    // Insert the `gfx` reexport module
+    let path_root = super::extern_crate_hack(context, span, |i| push(i));
+    let fixup = |item| {
+        push(super::fixup_extern_crate_paths(item, path_root))
+    };
+
+    // `impl gfx::shade::UniformBlock for $item`
+    generic::TraitDef {
+        span: span,
+        attributes: Vec::new(),
+        path: generic::ty::Path {
+            path: vec![super::EXTERN_CRATE_HACK, "gfx", "shade", "UniformBlock"],
+            lifetime: None,
+            params: Vec::new(),
+            global: true,
+        },
+        additional_bounds: Vec::new(),
+        generics: generic::ty::LifetimeBounds::empty(),
+        methods: vec![
+            // `fn get_members(Option<&Self>) -> Vec<gfx::shade::BlockMember>`
+            generic::MethodDef {
+                name: "get_members",
+                generics: generic::ty::LifetimeBounds::empty(),
+                explicit_self: None,
+                args: vec![
+                    generic::ty::Literal(generic::ty::Path {
+                        path: vec!["Option"],
+                        lifetime: None,
+                        params: vec![
+                            box generic::ty::Ptr(
+                                box generic::ty::Self,
+                                generic::ty::Borrowed(None, ast::MutImmutable)
+                            ),
+                        ],
+                        global: false,
+                    }),
+                ],
+                ret_ty: generic::ty::Literal(
+                    generic::ty::Path {
+                        path: vec!["Vec"],
+                        lifetime: None,
+                        params: vec![
+                            box generic::ty::Literal(generic::ty::Path::new(
+                                vec![super::EXTERN_CRATE_HACK, "gfx", "shade", "BlockMember"])),
+                        ],
+                        global: false,
+                    },
+                ),
+                attributes: Vec::new(),
+                // generate the method body
+                combine_substructure: generic::combine_substructure(
+                    |c, s, ss| method_body(c, s, ss, path_root)),
+            },
+        ],
+    }.expand(context, meta_item, item, fixup);
+}
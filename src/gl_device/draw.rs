@@ -69,8 +69,9 @@ impl ::draw::CommandBuffer for GlCommandBuffer {
 
     fn bind_target_texture(&mut self, access: ::target::Access,
                            tar: ::target::Target, tex: super::Texture,
-                           level: ::target::Level, layer: Option<::target::Layer>) {
-        self.buf.push(::BindTargetTexture(access, tar, tex, level, layer));
+                           kind: ::tex::TextureKind, level: ::target::Level,
+                           layer: Option<::target::Layer>) {
+        self.buf.push(::BindTargetTexture(access, tar, tex, kind, level, layer));
     }
 
     fn bind_uniform_block(&mut self, prog: super::Program, slot: ::UniformBufferSlot,
@@ -86,6 +87,21 @@ impl ::draw::CommandBuffer for GlCommandBuffer {
         self.buf.push(::BindTexture(slot, kind, tex, sampler));
     }
 
+    fn bind_storage_block(&mut self, prog: super::Program, slot: ::StorageBufferSlot,
+                          index: ::StorageBlockIndex, buf: super::Buffer) {
+        self.buf.push(::BindStorageBlock(prog, slot, index, buf));
+    }
+
+    fn bind_image(&mut self, slot: ::ImageSlot, tex: super::Texture,
+                  level: ::target::Level, layer: Option<::target::Layer>,
+                  access: ::tex::ImageAccess, format: ::tex::Format) {
+        self.buf.push(::BindImage(slot, tex, level, layer, access, format));
+    }
+
+    fn memory_barrier(&mut self, barrier: ::Barrier) {
+        self.buf.push(::MemoryBarrier(barrier));
+    }
+
     fn set_primitive(&mut self, prim: ::state::Primitive) {
         self.buf.push(::SetPrimitiveState(prim));
     }
@@ -115,6 +131,10 @@ impl ::draw::CommandBuffer for GlCommandBuffer {
         self.buf.push(::SetColorMask(mask));
     }
 
+    fn set_frame_buffer_srgb(&mut self, enable: bool) {
+        self.buf.push(::SetFrameBufferSrgb(enable));
+    }
+
     fn update_buffer(&mut self, buf: super::Buffer, data: ::draw::DataPointer,
                         offset_bytes: uint) {
         self.buf.push(::UpdateBuffer(buf, data, offset_bytes));
@@ -196,6 +196,17 @@ impl fmt::Show for Version {
     }
 }
 
+/// Strip the prefix OpenGL ES puts in front of its version strings, such as
+/// `"OpenGL ES 3.0 ..."` or `"OpenGL ES GLSL ES 3.00 ..."`, returning whether
+/// one was found.
+fn strip_es_prefix(src: &'static str) -> (bool, &'static str) {
+    static PREFIXES: [&'static str, ..3] = ["OpenGL ES GLSL ES ", "OpenGL ES-CM ", "OpenGL ES "];
+    match PREFIXES.iter().find(|p| src.starts_with(**p)) {
+        Some(p) => (true, src.slice_from(p.len())),
+        None => (false, src),
+    }
+}
+
 /// Get a statically allocated string from the implementation using
 /// `glGetString`. Fails if it `GLenum` cannot be handled by the
 /// implementation's `gl.GetString` function.
@@ -243,6 +254,8 @@ pub struct Info {
     pub version: Version,
     /// The GLSL vesion number
     pub shading_language: Version,
+    /// Whether the implementation is OpenGL ES rather than desktop OpenGL
+    pub is_embedded: bool,
     /// The extensions supported by the implementation
     pub extensions: HashSet<&'static str>,
 }
@@ -250,8 +263,10 @@ pub struct Info {
 impl Info {
     fn get(gl: &gl::Gl) -> Info {
         let platform_name = PlatformName::get(gl);
-        let version = Version::parse(get_string(gl, gl::VERSION)).unwrap();
-        let shading_language = Version::parse(get_string(gl, gl::SHADING_LANGUAGE_VERSION)).unwrap();
+        let (is_embedded, version) = strip_es_prefix(get_string(gl, gl::VERSION));
+        let version = Version::parse(version).unwrap();
+        let (_, shading_language) = strip_es_prefix(get_string(gl, gl::SHADING_LANGUAGE_VERSION));
+        let shading_language = Version::parse(shading_language).unwrap();
         let extensions = if version >= Version::new(3, 2, None, "") {
             let num_exts = get_uint(gl, gl::NUM_EXTENSIONS) as gl::types::GLuint;
             range(0, num_exts).map(|i| {
@@ -269,6 +284,7 @@ impl Info {
             platform_name: platform_name,
             version: version,
             shading_language: shading_language,
+            is_embedded: is_embedded,
             extensions: extensions,
         }
     }
@@ -278,6 +294,13 @@ impl Info {
         self.extensions.contains_equiv(s)
     }
 
+    /// Returns a string identifying the driver, which changes whenever the
+    /// binaries it produces may become incompatible
+    pub fn get_driver_id(&self) -> String {
+        format!("{}; {}; {}", self.platform_name.vendor, self.platform_name.renderer,
+                self.version)
+    }
+
     pub fn is_version_or_extension_supported(&self, major: u32, minor: u32, ext: &str) -> bool {
         self.version >= Version::new(major, minor, None, "") || self.is_extension_supported(ext)
     }
@@ -297,11 +320,23 @@ fn to_shader_model(v: &Version) -> shade::ShaderModel {
 /// capabilities.
 pub fn get(gl: &gl::Gl) -> (Info, Capabilities) {
     let info = Info::get(gl);
+    let texture_buffer_supported =
+        info.is_version_or_extension_supported(3, 1, "GL_ARB_texture_buffer_object");
     let caps = Capabilities {
         shader_model: to_shader_model(&info.shading_language),
         max_draw_buffers: get_uint(gl, gl::MAX_DRAW_BUFFERS),
         max_texture_size: get_uint(gl, gl::MAX_TEXTURE_SIZE),
         max_vertex_attributes: get_uint(gl, gl::MAX_VERTEX_ATTRIBS),
+        max_texture_buffer_size: if texture_buffer_supported {
+            get_uint(gl, gl::MAX_TEXTURE_BUFFER_SIZE)
+        } else {
+            0
+        },
+        max_anisotropy: if info.is_extension_supported("GL_EXT_texture_filter_anisotropic") {
+            get_uint(gl, gl::MAX_TEXTURE_MAX_ANISOTROPY_EXT)
+        } else {
+            1
+        },
         uniform_block_supported:
             info.is_version_or_extension_supported(3, 0, "GL_ARB_uniform_buffer_object"),
         array_buffer_supported:
@@ -320,6 +355,25 @@ pub fn get(gl: &gl::Gl) -> (Info, Capabilities) {
             info.is_version_or_extension_supported(3, 2, "GL_ARB_draw_elements_base_vertex"),
         instance_base_supported:
             info.is_version_or_extension_supported(4, 2, "GL_ARB_base_instance"),
+        srgb_frame_buffer_supported:
+            info.is_version_or_extension_supported(3, 0, "GL_ARB_framebuffer_sRGB"),
+        texture_swizzle_supported:
+            info.is_version_or_extension_supported(3, 3, "GL_ARB_texture_swizzle"),
+        texture_view_supported:
+            info.is_version_or_extension_supported(4, 3, "GL_ARB_texture_view"),
+        texture_buffer_supported: texture_buffer_supported,
+        texture_cube_array_supported:
+            info.is_version_or_extension_supported(4, 0, "GL_ARB_texture_cube_map_array"),
+        image_load_store_supported:
+            info.is_version_or_extension_supported(4, 2, "GL_ARB_shader_image_load_store"),
+        storage_buffer_supported:
+            info.is_version_or_extension_supported(4, 3, "GL_ARB_shader_storage_buffer_object"),
+        program_binary_supported:
+            info.is_version_or_extension_supported(4, 1, "GL_ARB_get_program_binary"),
+        // `glSpecializeShader` is loaded apart, see `GlDevice::new`
+        spirv_supported: !info.is_embedded &&
+            info.is_version_or_extension_supported(4, 6, "GL_ARB_gl_spirv") &&
+            info.is_version_or_extension_supported(4, 1, "GL_ARB_ES2_compatibility"),
     };
     (info, caps)
 }
@@ -327,7 +381,7 @@ pub fn get(gl: &gl::Gl) -> (Info, Capabilities) {
 #[cfg(test)]
 mod tests {
     use super::Version;
-    use super::to_shader_model;
+    use super::{strip_es_prefix, to_shader_model};
     use shade;
 
     #[test]
@@ -345,6 +399,14 @@ mod tests {
         assert_eq!(Version::parse("1.2.3 h3l1o. W0rld"), Ok(Version::new(1, 2, Some(3), "h3l1o. W0rld")));
     }
 
+    #[test]
+    fn test_strip_es_prefix() {
+        assert_eq!(strip_es_prefix("4.50 NVIDIA"), (false, "4.50 NVIDIA"));
+        assert_eq!(strip_es_prefix("OpenGL ES 3.0 Mesa"), (true, "3.0 Mesa"));
+        assert_eq!(strip_es_prefix("OpenGL ES-CM 1.1"), (true, "1.1"));
+        assert_eq!(strip_es_prefix("OpenGL ES GLSL ES 3.00"), (true, "3.00"));
+    }
+
     #[test]
     fn test_shader_model() {
         assert_eq!(to_shader_model(&Version::parse("1.10").unwrap()), shade::ModelUnsupported);
@@ -21,6 +21,9 @@
 extern crate libc;
 extern crate "gfx_gl" as gl;
 
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUint, SeqCst};
+
 use log;
 
 use attrib;
@@ -98,6 +101,7 @@ static RESET_CB: &'static [::Command] = &[
     ::SetDepthStencilState(None, None, ::state::CullNothing),
     ::SetBlendState(None),
     ::SetColorMask(::state::MASK_ALL),
+    ::SetFrameBufferSrgb(false),
 ];
 
 fn primitive_to_gl(prim_type: ::PrimitiveType) -> gl::types::GLenum {
@@ -128,19 +132,57 @@ fn target_to_gl(target: ::target::Target) -> gl::types::GLenum {
     }
 }
 
+fn barrier_to_gl(barrier: ::Barrier) -> gl::types::GLbitfield {
+    let mut bits = 0;
+    for &(flag, bit) in [
+        (::BARRIER_VERTEX_ATTRIB, gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT),
+        (::BARRIER_INDEX, gl::ELEMENT_ARRAY_BARRIER_BIT),
+        (::BARRIER_UNIFORM, gl::UNIFORM_BARRIER_BIT),
+        (::BARRIER_TEXTURE_FETCH, gl::TEXTURE_FETCH_BARRIER_BIT),
+        (::BARRIER_IMAGE_ACCESS, gl::SHADER_IMAGE_ACCESS_BARRIER_BIT),
+        (::BARRIER_COMMAND, gl::COMMAND_BARRIER_BIT),
+        (::BARRIER_BUFFER_UPDATE, gl::BUFFER_UPDATE_BARRIER_BIT),
+        (::BARRIER_TEXTURE_UPDATE, gl::TEXTURE_UPDATE_BARRIER_BIT),
+        (::BARRIER_FRAME_BUFFER, gl::FRAMEBUFFER_BARRIER_BIT),
+        (::BARRIER_STORAGE, gl::SHADER_STORAGE_BARRIER_BIT),
+    ].iter() {
+        if barrier.contains(flag) {
+            bits |= bit;
+        }
+    }
+    bits
+}
+
 /// An OpenGL device with GLSL shaders
 pub struct GlDevice {
     info: Info,
     caps: ::Capabilities,
     gl: gl::Gl,
+    samplers: tex::SamplerCache,
+    applied_samplers: tex::AppliedSamplers,
+    specialize_shader: Option<shade::SpecializeShader>,
+    deletions: Arc<AtomicUint>,
 }
 
 impl GlDevice {
     /// Load OpenGL symbols and detect driver information
     pub fn new(fn_proc: |&str| -> *const ::libc::c_void) -> GlDevice {
+        // the bindings predate SPIR-V shaders
+        let mut specialize_shader = None;
+        for name in ["glSpecializeShader", "glSpecializeShaderARB"].iter() {
+            let ptr = fn_proc(*name);
+            if !ptr.is_null() {
+                specialize_shader = Some(unsafe {
+                    ::std::mem::transmute::<_, shade::SpecializeShader>(ptr)
+                });
+                break;
+            }
+        }
+
         let gl = gl::Gl::load_with(fn_proc);
 
-        let (info, caps) = info::get(&gl);
+        let (info, mut caps) = info::get(&gl);
+        caps.spirv_supported = caps.spirv_supported && specialize_shader.is_some();
 
         info!("Vendor: {}", info.platform_name.vendor);
         info!("Renderer: {}", info.platform_name.renderer);
@@ -155,6 +197,10 @@ impl GlDevice {
             info: info,
             caps: caps,
             gl: gl,
+            samplers: tex::SamplerCache::new(),
+            applied_samplers: tex::AppliedSamplers::new(),
+            specialize_shader: specialize_shader,
+            deletions: Arc::new(AtomicUint::new(0)),
         }
     }
 
@@ -162,6 +208,8 @@ impl GlDevice {
     /// can be found in the `gl` crate.
     pub unsafe fn with_gl(&mut self, fun: |&gl::Gl|) {
         self.reset_state();
+        // texture parameters may be changed by the closure
+        self.applied_samplers.clear();
         fun(&self.gl);
     }
 
@@ -333,17 +381,24 @@ impl GlDevice {
                 let att = target_to_gl(target);
                 unsafe { self.gl.FramebufferRenderbuffer(point, att, gl::RENDERBUFFER, name) };
             },
-            ::BindTargetTexture(access, target, name, level, layer) => {
+            ::BindTargetTexture(access, target, name, kind, level, layer) => {
                 if !self.caps.render_targets_supported {
                     panic!("Tried to do something with an FBO without FBO support!")
                 }
                 let point = access_to_gl(access);
                 let att = target_to_gl(target);
-                match layer {
-                    Some(layer) => unsafe { self.gl.FramebufferTextureLayer(
+                match (kind, layer) {
+                    // faces of a single cube are not layers in GL
+                    (::tex::TextureCube, Some(layer)) => match ::tex::CUBE_FACES.get(layer as uint) {
+                        Some(&face) => unsafe { self.gl.FramebufferTexture2D(
+                            point, att, tex::face_to_gl(face),
+                            name, level as gl::types::GLint) },
+                        None => error!("Invalid cube face layer: {}", layer),
+                    },
+                    (_, Some(layer)) => unsafe { self.gl.FramebufferTextureLayer(
                         point, att, name, level as gl::types::GLint,
                         layer as gl::types::GLint) },
-                    None => unsafe { self.gl.FramebufferTexture(
+                    (_, None) => unsafe { self.gl.FramebufferTexture(
                         point, att, name, level as gl::types::GLint) },
                 }
             },
@@ -359,12 +414,16 @@ impl GlDevice {
                     gl::TEXTURE0 + slot as gl::types::GLenum,
                     kind, texture);
                 match (anchor, kind.get_aa_mode(), sampler) {
+                    (_, _, Some(_)) if match kind { ::tex::TextureBuffer(_) => true, _ => false } =>
+                        error!("Unable to bind a buffer texture with a sampler"),
                     (anchor, None, Some(::Handle(sam, ref info))) => {
                         if self.caps.sampler_objects_supported {
                             unsafe { self.gl.BindSampler(slot as gl::types::GLenum, sam) };
                         } else {
                             debug_assert_eq!(sam, 0);
-                            tex::bind_sampler(&self.gl, anchor, info);
+                            if self.applied_samplers.apply(texture, info) {
+                                tex::bind_sampler(&self.gl, anchor, info);
+                            }
                         }
                     },
                     (_, Some(_), Some(_)) =>
@@ -372,6 +431,36 @@ impl GlDevice {
                     (_, _, _) => (),
                 }
             },
+            ::BindStorageBlock(program, slot, index, buffer) => {
+                if !self.caps.storage_buffer_supported {
+                    error!("Shader storage buffers are not supported");
+                    return
+                }
+                unsafe {
+                    self.gl.ShaderStorageBlockBinding(program, index as gl::types::GLuint,
+                                                      slot as gl::types::GLuint);
+                    self.gl.BindBufferBase(gl::SHADER_STORAGE_BUFFER, slot as gl::types::GLuint,
+                                           buffer);
+                }
+            },
+            ::BindImage(slot, texture, level, layer, access, format) => {
+                if !self.caps.image_load_store_supported {
+                    error!("Image load/store is not supported");
+                    return
+                }
+                match tex::bind_image(&self.gl, slot as gl::types::GLuint, texture,
+                                      level, layer, access, format) {
+                    Ok(()) => (),
+                    Err(e) => error!("Unable to bind the image in slot {}: {}", slot, e),
+                }
+            },
+            ::MemoryBarrier(barrier) => {
+                if !self.caps.image_load_store_supported {
+                    error!("Memory barriers are not supported");
+                    return
+                }
+                unsafe { self.gl.MemoryBarrier(barrier_to_gl(barrier)) };
+            },
             ::SetPrimitiveState(prim) => {
                 state::bind_primitive(&self.gl, prim);
             },
@@ -394,6 +483,13 @@ impl GlDevice {
             ::SetColorMask(mask) => {
                 state::bind_color_mask(&self.gl, mask);
             },
+            ::SetFrameBufferSrgb(enable) => {
+                if self.caps.srgb_frame_buffer_supported {
+                    state::bind_frame_buffer_srgb(&self.gl, enable);
+                } else if enable {
+                    error!("sRGB frame buffer writes are not supported");
+                }
+            },
             ::UpdateBuffer(buffer, pointer, offset) => {
                 let data = data_buf.get_ref(pointer);
                 self.update_sub_buffer(buffer, data.as_ptr(), data.len(), offset);
@@ -554,6 +650,10 @@ impl Device<GlCommandBuffer> for GlDevice {
         &self.caps
     }
 
+    fn get_deletions(&self) -> Arc<AtomicUint> {
+        self.deletions.clone()
+    }
+
     fn reset_state(&mut self) {
         let data = ::draw::DataBuffer::new();
         for com in RESET_CB.iter() {
@@ -606,7 +706,13 @@ impl Device<GlCommandBuffer> for GlDevice {
 
     fn create_shader(&mut self, stage: ::shade::Stage, code: ::shade::ShaderSource)
                      -> Result<::ShaderHandle, ::shade::CreateShaderError> {
-        let (name, info) = shade::create_shader(&self.gl, stage, code, self.info.shading_language);
+        let specialize_shader = if self.caps.spirv_supported {
+            self.specialize_shader
+        } else {
+            None
+        };
+        let (name, info) = shade::create_shader(&self.gl, stage, code, &self.info,
+                                                specialize_shader);
         info.map(|info| {
             let level = if name.is_err() { log::ERROR } else { log::WARN };
             log!(level, "\tShader compile log: {}", info);
@@ -614,7 +720,8 @@ impl Device<GlCommandBuffer> for GlDevice {
         name.map(|sh| ::Handle(sh, stage))
     }
 
-    fn create_program(&mut self, shaders: &[::ShaderHandle]) -> Result<::ProgramHandle, ()> {
+    fn create_program(&mut self, shaders: &[::ShaderHandle])
+                      -> Result<::ProgramHandle, ::shade::ShaderLog> {
         let (prog, log) = shade::create_program(&self.gl, &self.caps, shaders);
         log.map(|log| {
             let level = if prog.is_err() { log::ERROR } else { log::WARN };
@@ -623,6 +730,20 @@ impl Device<GlCommandBuffer> for GlDevice {
         prog
     }
 
+    fn create_program_binary(&mut self, binary: &::shade::ProgramBinary)
+                             -> Result<::ProgramHandle, ::shade::ShaderLog> {
+        if !self.caps.program_binary_supported {
+            error!("\tprogram binaries unsupported, ignored");
+            return Err(::shade::ShaderLog::parse(""))
+        }
+        let (prog, log) = shade::create_program_binary(&self.gl, &self.caps, binary);
+        log.map(|log| {
+            let level = if prog.is_err() { log::WARN } else { log::INFO };
+            log!(level, "\tProgram binary log: {}", log);
+        });
+        prog
+    }
+
     fn create_frame_buffer(&mut self) -> ::FrameBufferHandle {
         if !self.caps.render_targets_supported {
             panic!("No framebuffer objects, can't make a new one!");
@@ -647,6 +768,12 @@ impl Device<GlCommandBuffer> for GlDevice {
         if info.width == 0 || info.height == 0 || info.levels == 0 {
             return Err(::tex::InvalidTextureInfo(info))
         }
+        if info.swizzle != ::tex::IDENTITY_SWIZZLE && !self.caps.texture_swizzle_supported {
+            return Err(::tex::UnsupportedTextureSwizzle)
+        }
+        if info.kind == ::tex::TextureCubeArray && !self.caps.texture_cube_array_supported {
+            return Err(::tex::UnsupportedTextureCubeArray)
+        }
 
         let name = if self.caps.immutable_storage_supported {
             tex::make_with_storage(&self.gl, &info)
@@ -656,11 +783,55 @@ impl Device<GlCommandBuffer> for GlDevice {
         name.map(|tex| ::Handle(tex, info))
     }
 
+    fn create_texture_view(&mut self, texture: &::TextureHandle, view: ::tex::ViewInfo) ->
+                           Result<::TextureHandle, ::tex::TextureError> {
+        // views can only be made of textures with immutable storage
+        if !self.caps.texture_view_supported || !self.caps.immutable_storage_supported {
+            return Err(::tex::UnsupportedTextureView)
+        }
+        if view.swizzle != ::tex::IDENTITY_SWIZZLE && !self.caps.texture_swizzle_supported {
+            return Err(::tex::UnsupportedTextureSwizzle)
+        }
+        if view.kind == ::tex::TextureCubeArray && !self.caps.texture_cube_array_supported {
+            return Err(::tex::UnsupportedTextureCubeArray)
+        }
+        let info = try!(texture.get_info().get_view(&view));
+        tex::make_view(&self.gl, texture.get_name(), &view)
+            .map(|tex| ::Handle(tex, info))
+    }
+
+    fn create_texture_buffer_raw(&mut self, buf: ::BufferHandle<()>, format: ::tex::Format) ->
+                                 Result<::TextureHandle, ::tex::TextureError> {
+        if !self.caps.texture_buffer_supported {
+            return Err(::tex::UnsupportedTextureBuffer)
+        }
+        let info = try!(::tex::TextureInfo::from_buffer(buf.get_info().size, format));
+        match info.kind {
+            ::tex::TextureBuffer(texels) if texels > self.caps.max_texture_buffer_size =>
+                return Err(::tex::InvalidTextureBuffer(buf.get_info().size)),
+            _ => (),
+        }
+        tex::make_buffer_texture(&self.gl, buf.get_name(), format)
+            .map(|tex| ::Handle(tex, info))
+    }
+
     fn create_sampler(&mut self, info: ::tex::SamplerInfo) -> ::SamplerHandle {
-        let sam = if self.caps.sampler_objects_supported {
-            tex::make_sampler(&self.gl, &info)
-        } else {
-            0
+        let info = ::tex::SamplerInfo {
+            filtering: info.filtering.clamp_anisotropy(self.caps.max_anisotropy),
+            ..info
+        };
+        // equal descriptions share a single sampler object
+        let sam = match self.samplers.acquire(&info) {
+            Some(sam) => sam,
+            None => {
+                let sam = if self.caps.sampler_objects_supported {
+                    tex::make_sampler(&self.gl, &info)
+                } else {
+                    0
+                };
+                self.samplers.insert(info, sam);
+                sam
+            },
         };
         ::Handle(sam, info)
     }
@@ -678,6 +849,7 @@ impl Device<GlCommandBuffer> for GlDevice {
 
     fn delete_program(&mut self, handle: ::ProgramHandle) {
         unsafe { self.gl.DeleteProgram(handle.get_name()) };
+        self.deletions.fetch_add(1, SeqCst);
     }
 
     fn delete_surface(&mut self, handle: ::SurfaceHandle) {
@@ -689,15 +861,19 @@ impl Device<GlCommandBuffer> for GlDevice {
 
     fn delete_texture(&mut self, handle: ::TextureHandle) {
         let name = handle.get_name();
+        self.applied_samplers.forget(name);
         unsafe {
             self.gl.DeleteTextures(1, &name);
         }
+        self.deletions.fetch_add(1, SeqCst);
     }
 
     fn delete_sampler(&mut self, handle: ::SamplerHandle) {
-        let name = handle.get_name();
-        unsafe {
-            self.gl.DeleteSamplers(1, &name);
+        match self.samplers.release(handle.get_info()) {
+            Some(name) if self.caps.sampler_objects_supported => unsafe {
+                self.gl.DeleteSamplers(1, &name);
+            },
+            _ => (),
         }
     }
 
@@ -719,6 +895,14 @@ impl Device<GlCommandBuffer> for GlDevice {
         tex::generate_mipmap(&self.gl, texture.get_info().kind, texture.get_name());
     }
 
+    fn get_program_binary(&mut self, prog: &::ProgramHandle) -> Option<::shade::ProgramBinary> {
+        if self.caps.program_binary_supported {
+            shade::get_program_binary(&self.gl, prog.get_name())
+        } else {
+            None
+        }
+    }
+
     fn map_buffer_raw(&mut self, buf: BufferHandle<()>, access: MapAccess) -> RawMapping {
         let ptr;
         unsafe { self.gl.BindBuffer(gl::ARRAY_BUFFER, buf.get_name()) };
@@ -14,29 +14,92 @@
 
 use super::super::shade as s;
 use super::gl;
-use super::info::Version;
+use super::info::{Info, Version};
 
-pub fn create_shader(gl: &gl::Gl, stage: s::Stage, data: s::ShaderSource, lang: Version)
+/// The shader code picked for the current implementation.
+#[deriving(PartialEq, Show)]
+enum SourceCode<'a> {
+    Glsl(&'a [u8]),
+    SpirV(&'a [u8]),
+}
+
+/// Pick the highest GLSL version supported by the shading language `lang` of
+/// the given profile, falling back to the SPIR-V binary if allowed.
+fn select_source<'a>(data: &s::ShaderSource<'a>, lang: Version, embedded: bool,
+                     spirv_supported: bool) -> Option<SourceCode<'a>> {
+    let v = |major, minor| lang >= Version::new(major, minor, None, "");
+    let glsl = if embedded {
+        match *data {
+            s::ShaderSource { glsl_300_es: Some(s), .. } if v(3,  0) => Some(s),
+            s::ShaderSource { glsl_100_es: Some(s), .. } if v(1,  0) => Some(s),
+            _ => None,
+        }
+    } else {
+        match *data {
+            s::ShaderSource { glsl_450: Some(s), .. } if v(4, 50) => Some(s),
+            s::ShaderSource { glsl_440: Some(s), .. } if v(4, 40) => Some(s),
+            s::ShaderSource { glsl_430: Some(s), .. } if v(4, 30) => Some(s),
+            s::ShaderSource { glsl_420: Some(s), .. } if v(4, 20) => Some(s),
+            s::ShaderSource { glsl_410: Some(s), .. } if v(4, 10) => Some(s),
+            s::ShaderSource { glsl_400: Some(s), .. } if v(4,  0) => Some(s),
+            s::ShaderSource { glsl_330: Some(s), .. } if v(3, 30) => Some(s),
+            s::ShaderSource { glsl_150: Some(s), .. } if v(1, 50) => Some(s),
+            s::ShaderSource { glsl_140: Some(s), .. } if v(1, 40) => Some(s),
+            s::ShaderSource { glsl_130: Some(s), .. } if v(1, 30) => Some(s),
+            s::ShaderSource { glsl_120: Some(s), .. } if v(1, 20) => Some(s),
+            _ => None,
+        }
+    };
+    match (glsl, data.spirv) {
+        (Some(s), _) => Some(Glsl(s)),
+        (None, Some(s)) if spirv_supported => Some(SpirV(s)),
+        (None, _) => None,
+    }
+}
+
+/// `GL_SHADER_BINARY_FORMAT_SPIR_V`, which the bindings predate.
+const SHADER_BINARY_FORMAT_SPIR_V: gl::types::GLenum = 0x9551;
+
+/// `glSpecializeShader` of GL 4.6, or `glSpecializeShaderARB`, loaded apart
+/// from the bindings that predate it.
+pub type SpecializeShader = extern "system" fn(gl::types::GLuint, *const gl::types::GLchar,
+                                               gl::types::GLuint, *const gl::types::GLuint,
+                                               *const gl::types::GLuint);
+
+/// Create a shader from the best source for the driver. SPIR-V binaries are
+/// only used with `specialize_shader`, when the driver supports them.
+pub fn create_shader(gl: &gl::Gl, stage: s::Stage, data: s::ShaderSource, info: &Info,
+                     specialize_shader: Option<SpecializeShader>)
         -> (Result<super::Shader, s::CreateShaderError>, Option<String>) {
     let target = match stage {
         s::Vertex => gl::VERTEX_SHADER,
         s::Geometry => gl::GEOMETRY_SHADER,
         s::Fragment => gl::FRAGMENT_SHADER,
     };
-    let name = unsafe { gl.CreateShader(target) };
-    let data = match data {
-        s::ShaderSource { glsl_150: Some(s), .. } if lang >= Version::new(1, 50, None, "") => s,
-        s::ShaderSource { glsl_140: Some(s), .. } if lang >= Version::new(1, 40, None, "") => s,
-        s::ShaderSource { glsl_130: Some(s), .. } if lang >= Version::new(1, 30, None, "") => s,
-        s::ShaderSource { glsl_120: Some(s), .. } if lang >= Version::new(1, 20, None, "") => s,
-        _ => return (Err(s::NoSupportedShaderProvided),
-                     Some("[gfx-rs] No supported GLSL shader provided!".to_string())),
+    let code = match select_source(&data, info.shading_language, info.is_embedded,
+                                   specialize_shader.is_some()) {
+        Some(code) => code,
+        None => return (Err(s::NoSupportedShaderProvided),
+                        Some("[gfx-rs] No supported shader provided!".to_string())),
     };
-    unsafe {
-        gl.ShaderSource(name, 1,
-            &(data.as_ptr() as *const gl::types::GLchar),
-            &(data.len() as gl::types::GLint));
-        gl.CompileShader(name);
+    let name = unsafe { gl.CreateShader(target) };
+    match code {
+        Glsl(data) => unsafe {
+            gl.ShaderSource(name, 1,
+                &(data.as_ptr() as *const gl::types::GLchar),
+                &(data.len() as gl::types::GLint));
+            gl.CompileShader(name);
+        },
+        SpirV(data) => match specialize_shader {
+            Some(specialize_shader) => unsafe {
+                gl.ShaderBinary(1, &name, SHADER_BINARY_FORMAT_SPIR_V,
+                    data.as_ptr() as *const gl::types::GLvoid,
+                    data.len() as gl::types::GLsizei);
+                specialize_shader(name, "main\0".as_ptr() as *const gl::types::GLchar,
+                    0, ::std::ptr::null(), ::std::ptr::null());
+            },
+            None => unreachable!(),
+        },
     }
     info!("\tCompiled shader {}", name);
 
@@ -58,8 +121,9 @@ pub fn create_shader(gl: &gl::Gl, stage: s::Stage, data: s::ShaderSource, lang:
 
     let name = if status != 0 {
         Ok(name)
-    }else {
-        Err(s::ShaderCompilationFailed)
+    } else {
+        Err(s::ShaderCompilationFailed(s::ShaderLog::parse(
+            log.as_ref().map_or("", |log| log.as_slice()))))
     };
 
     (name, log)
@@ -77,9 +141,11 @@ fn get_program_iv(gl: &gl::Gl, program: super::Program, query: gl::types::GLenum
     iv
 }
 
+#[deriving(PartialEq, Show)]
 enum StorageType {
     Var(s::BaseType, s::ContainerType),
     Sampler(s::BaseType, s::SamplerType),
+    Image(s::BaseType, s::SamplerType),
     Unknown,
 }
 
@@ -116,7 +182,20 @@ impl StorageType {
             gl::FLOAT_MAT4x2                 => Var(s::BaseF32, s::Matrix(s::ColumnMajor, 4, 2)),
             gl::FLOAT_MAT4x3                 => Var(s::BaseF32, s::Matrix(s::ColumnMajor, 4, 3)),
 
-            // TODO: double matrices
+            gl::DOUBLE                       => Var(s::BaseF64, s::Single),
+            gl::DOUBLE_VEC2                  => Var(s::BaseF64, s::Vector(2)),
+            gl::DOUBLE_VEC3                  => Var(s::BaseF64, s::Vector(3)),
+            gl::DOUBLE_VEC4                  => Var(s::BaseF64, s::Vector(4)),
+
+            gl::DOUBLE_MAT2                  => Var(s::BaseF64, s::Matrix(s::ColumnMajor, 2, 2)),
+            gl::DOUBLE_MAT3                  => Var(s::BaseF64, s::Matrix(s::ColumnMajor, 3, 3)),
+            gl::DOUBLE_MAT4                  => Var(s::BaseF64, s::Matrix(s::ColumnMajor, 4, 4)),
+            gl::DOUBLE_MAT2x3                => Var(s::BaseF64, s::Matrix(s::ColumnMajor, 2, 3)),
+            gl::DOUBLE_MAT2x4                => Var(s::BaseF64, s::Matrix(s::ColumnMajor, 2, 4)),
+            gl::DOUBLE_MAT3x2                => Var(s::BaseF64, s::Matrix(s::ColumnMajor, 3, 2)),
+            gl::DOUBLE_MAT3x4                => Var(s::BaseF64, s::Matrix(s::ColumnMajor, 3, 4)),
+            gl::DOUBLE_MAT4x2                => Var(s::BaseF64, s::Matrix(s::ColumnMajor, 4, 2)),
+            gl::DOUBLE_MAT4x3                => Var(s::BaseF64, s::Matrix(s::ColumnMajor, 4, 3)),
 
             gl::SAMPLER_1D                   => Sampler(s::BaseF32, s::Sampler1D(s::NoArray, s::NoShadow)),
             gl::SAMPLER_1D_ARRAY             => Sampler(s::BaseF32, s::Sampler1D(s::Array,   s::NoShadow)),
@@ -135,10 +214,62 @@ impl StorageType {
             gl::SAMPLER_3D                   => Sampler(s::BaseF32, s::Sampler3D),
             gl::SAMPLER_CUBE                 => Sampler(s::BaseF32, s::SamplerCube(s::NoShadow)),
             gl::SAMPLER_CUBE_SHADOW          => Sampler(s::BaseF32, s::SamplerCube(s::Shadow)),
-
-            // TODO: int samplers
-
-            // TODO: unsigned samplers
+            gl::SAMPLER_BUFFER               => Sampler(s::BaseF32, s::SamplerBuffer),
+
+            gl::INT_SAMPLER_1D                   => Sampler(s::BaseI32, s::Sampler1D(s::NoArray, s::NoShadow)),
+            gl::INT_SAMPLER_1D_ARRAY             => Sampler(s::BaseI32, s::Sampler1D(s::Array,   s::NoShadow)),
+            gl::INT_SAMPLER_2D                   => Sampler(s::BaseI32, s::Sampler2D(s::NoArray, s::NoShadow, s::NoMultiSample, s::NoRect)),
+            gl::INT_SAMPLER_2D_ARRAY             => Sampler(s::BaseI32, s::Sampler2D(s::Array,   s::NoShadow, s::NoMultiSample, s::NoRect)),
+            gl::INT_SAMPLER_2D_MULTISAMPLE       => Sampler(s::BaseI32, s::Sampler2D(s::NoArray, s::NoShadow, s::MultiSample,   s::NoRect)),
+            gl::INT_SAMPLER_2D_MULTISAMPLE_ARRAY => Sampler(s::BaseI32, s::Sampler2D(s::Array,   s::NoShadow, s::MultiSample,   s::NoRect)),
+            gl::INT_SAMPLER_2D_RECT              => Sampler(s::BaseI32, s::Sampler2D(s::NoArray, s::NoShadow, s::NoMultiSample, s::Rect)),
+            gl::INT_SAMPLER_3D                   => Sampler(s::BaseI32, s::Sampler3D),
+            gl::INT_SAMPLER_CUBE                 => Sampler(s::BaseI32, s::SamplerCube(s::NoShadow)),
+            gl::INT_SAMPLER_BUFFER               => Sampler(s::BaseI32, s::SamplerBuffer),
+
+            gl::UNSIGNED_INT_SAMPLER_1D                   => Sampler(s::BaseU32, s::Sampler1D(s::NoArray, s::NoShadow)),
+            gl::UNSIGNED_INT_SAMPLER_1D_ARRAY             => Sampler(s::BaseU32, s::Sampler1D(s::Array,   s::NoShadow)),
+            gl::UNSIGNED_INT_SAMPLER_2D                   => Sampler(s::BaseU32, s::Sampler2D(s::NoArray, s::NoShadow, s::NoMultiSample, s::NoRect)),
+            gl::UNSIGNED_INT_SAMPLER_2D_ARRAY             => Sampler(s::BaseU32, s::Sampler2D(s::Array,   s::NoShadow, s::NoMultiSample, s::NoRect)),
+            gl::UNSIGNED_INT_SAMPLER_2D_MULTISAMPLE       => Sampler(s::BaseU32, s::Sampler2D(s::NoArray, s::NoShadow, s::MultiSample,   s::NoRect)),
+            gl::UNSIGNED_INT_SAMPLER_2D_MULTISAMPLE_ARRAY => Sampler(s::BaseU32, s::Sampler2D(s::Array,   s::NoShadow, s::MultiSample,   s::NoRect)),
+            gl::UNSIGNED_INT_SAMPLER_2D_RECT              => Sampler(s::BaseU32, s::Sampler2D(s::NoArray, s::NoShadow, s::NoMultiSample, s::Rect)),
+            gl::UNSIGNED_INT_SAMPLER_3D                   => Sampler(s::BaseU32, s::Sampler3D),
+            gl::UNSIGNED_INT_SAMPLER_CUBE                 => Sampler(s::BaseU32, s::SamplerCube(s::NoShadow)),
+            gl::UNSIGNED_INT_SAMPLER_BUFFER               => Sampler(s::BaseU32, s::SamplerBuffer),
+
+            gl::IMAGE_1D                   => Image(s::BaseF32, s::Sampler1D(s::NoArray, s::NoShadow)),
+            gl::IMAGE_1D_ARRAY             => Image(s::BaseF32, s::Sampler1D(s::Array,   s::NoShadow)),
+            gl::IMAGE_2D                   => Image(s::BaseF32, s::Sampler2D(s::NoArray, s::NoShadow, s::NoMultiSample, s::NoRect)),
+            gl::IMAGE_2D_ARRAY             => Image(s::BaseF32, s::Sampler2D(s::Array,   s::NoShadow, s::NoMultiSample, s::NoRect)),
+            gl::IMAGE_2D_MULTISAMPLE       => Image(s::BaseF32, s::Sampler2D(s::NoArray, s::NoShadow, s::MultiSample,   s::NoRect)),
+            gl::IMAGE_2D_MULTISAMPLE_ARRAY => Image(s::BaseF32, s::Sampler2D(s::Array,   s::NoShadow, s::MultiSample,   s::NoRect)),
+            gl::IMAGE_2D_RECT              => Image(s::BaseF32, s::Sampler2D(s::NoArray, s::NoShadow, s::NoMultiSample, s::Rect)),
+            gl::IMAGE_3D                   => Image(s::BaseF32, s::Sampler3D),
+            gl::IMAGE_CUBE                 => Image(s::BaseF32, s::SamplerCube(s::NoShadow)),
+            gl::IMAGE_BUFFER               => Image(s::BaseF32, s::SamplerBuffer),
+
+            gl::INT_IMAGE_1D                   => Image(s::BaseI32, s::Sampler1D(s::NoArray, s::NoShadow)),
+            gl::INT_IMAGE_1D_ARRAY             => Image(s::BaseI32, s::Sampler1D(s::Array,   s::NoShadow)),
+            gl::INT_IMAGE_2D                   => Image(s::BaseI32, s::Sampler2D(s::NoArray, s::NoShadow, s::NoMultiSample, s::NoRect)),
+            gl::INT_IMAGE_2D_ARRAY             => Image(s::BaseI32, s::Sampler2D(s::Array,   s::NoShadow, s::NoMultiSample, s::NoRect)),
+            gl::INT_IMAGE_2D_MULTISAMPLE       => Image(s::BaseI32, s::Sampler2D(s::NoArray, s::NoShadow, s::MultiSample,   s::NoRect)),
+            gl::INT_IMAGE_2D_MULTISAMPLE_ARRAY => Image(s::BaseI32, s::Sampler2D(s::Array,   s::NoShadow, s::MultiSample,   s::NoRect)),
+            gl::INT_IMAGE_2D_RECT              => Image(s::BaseI32, s::Sampler2D(s::NoArray, s::NoShadow, s::NoMultiSample, s::Rect)),
+            gl::INT_IMAGE_3D                   => Image(s::BaseI32, s::Sampler3D),
+            gl::INT_IMAGE_CUBE                 => Image(s::BaseI32, s::SamplerCube(s::NoShadow)),
+            gl::INT_IMAGE_BUFFER               => Image(s::BaseI32, s::SamplerBuffer),
+
+            gl::UNSIGNED_INT_IMAGE_1D                   => Image(s::BaseU32, s::Sampler1D(s::NoArray, s::NoShadow)),
+            gl::UNSIGNED_INT_IMAGE_1D_ARRAY             => Image(s::BaseU32, s::Sampler1D(s::Array,   s::NoShadow)),
+            gl::UNSIGNED_INT_IMAGE_2D                   => Image(s::BaseU32, s::Sampler2D(s::NoArray, s::NoShadow, s::NoMultiSample, s::NoRect)),
+            gl::UNSIGNED_INT_IMAGE_2D_ARRAY             => Image(s::BaseU32, s::Sampler2D(s::Array,   s::NoShadow, s::NoMultiSample, s::NoRect)),
+            gl::UNSIGNED_INT_IMAGE_2D_MULTISAMPLE       => Image(s::BaseU32, s::Sampler2D(s::NoArray, s::NoShadow, s::MultiSample,   s::NoRect)),
+            gl::UNSIGNED_INT_IMAGE_2D_MULTISAMPLE_ARRAY => Image(s::BaseU32, s::Sampler2D(s::Array,   s::NoShadow, s::MultiSample,   s::NoRect)),
+            gl::UNSIGNED_INT_IMAGE_2D_RECT              => Image(s::BaseU32, s::Sampler2D(s::NoArray, s::NoShadow, s::NoMultiSample, s::Rect)),
+            gl::UNSIGNED_INT_IMAGE_3D                   => Image(s::BaseU32, s::Sampler3D),
+            gl::UNSIGNED_INT_IMAGE_CUBE                 => Image(s::BaseU32, s::SamplerCube(s::NoShadow)),
+            gl::UNSIGNED_INT_IMAGE_BUFFER               => Image(s::BaseU32, s::SamplerBuffer),
 
             _ => Unknown,
         }
@@ -210,13 +341,120 @@ fn query_blocks(gl: &gl::Gl, caps: &::Capabilities, prog: super::Program) -> Vec
             name: name,
             size: size as uint,
             usage: usage,
+            members: query_block_members(gl, prog, i),
         }
     }).collect()
 }
 
-fn query_parameters(gl: &gl::Gl, caps: &::Capabilities, prog: super::Program) -> (Vec<s::UniformVar>, Vec<s::SamplerVar>) {
+fn query_block_members(gl: &gl::Gl, prog: super::Program, block: gl::types::GLuint)
+                       -> Vec<s::BlockMember> {
+    let mut num = 0;
+    unsafe {
+        gl.GetActiveUniformBlockiv(prog, block, gl::UNIFORM_BLOCK_ACTIVE_UNIFORMS, &mut num);
+    }
+    let mut indices = Vec::from_elem(num as uint, 0 as gl::types::GLint);
+    unsafe {
+        gl.GetActiveUniformBlockiv(prog, block, gl::UNIFORM_BLOCK_ACTIVE_UNIFORM_INDICES,
+                                   indices.as_mut_slice().as_mut_ptr());
+    }
+    let indices: Vec<gl::types::GLuint> = indices.iter().map(|&i| i as gl::types::GLuint).collect();
+    let get = |query| {
+        let mut values = Vec::from_elem(num as uint, 0 as gl::types::GLint);
+        unsafe {
+            gl.GetActiveUniformsiv(prog, num, indices.as_slice().as_ptr(), query,
+                                   values.as_mut_slice().as_mut_ptr());
+        }
+        values
+    };
+    let types = get(gl::UNIFORM_TYPE);
+    let sizes = get(gl::UNIFORM_SIZE);
+    let name_lengths = get(gl::UNIFORM_NAME_LENGTH);
+    let offsets = get(gl::UNIFORM_OFFSET);
+    let array_strides = get(gl::UNIFORM_ARRAY_STRIDE);
+    let matrix_strides = get(gl::UNIFORM_MATRIX_STRIDE);
+    let row_major = get(gl::UNIFORM_IS_ROW_MAJOR);
+    indices.iter().enumerate().filter_map(|(k, &i)| {
+        let mut name = String::with_capacity(name_lengths[k] as uint); //includes terminating null
+        name.grow(name_lengths[k] as uint, '\0');
+        let mut length = 0;
+        unsafe {
+            gl.GetActiveUniformName(prog, i, name_lengths[k], &mut length,
+                name.as_slice().as_ptr() as *mut gl::types::GLchar);
+        }
+        name.truncate(length as uint);
+        if name.as_slice().ends_with("[0]") {
+            let n = name.len() - 3;
+            name.truncate(n);
+        }
+        let (base, container) = match StorageType::new(types[k] as gl::types::GLenum) {
+            Var(b, s::Matrix(_, cols, rows)) if row_major[k] != 0 =>
+                (b, s::Matrix(s::RowMajor, cols, rows)),
+            Var(b, c) => (b, c),
+            _ => {
+                error!("Unrecognized block member storage: {}", types[k]);
+                return None
+            },
+        };
+        info!("\t\t\tMember '{}' at offset {}\t{}\t{}", name, offsets[k], base, container);
+        Some(s::BlockMember {
+            name: name,
+            offset: offsets[k] as uint,
+            base_type: base,
+            container: container,
+            count: sizes[k] as uint,
+            array_stride: array_strides[k] as uint,
+            matrix_stride: matrix_strides[k] as uint,
+        })
+    }).collect()
+}
+
+fn query_storage_blocks(gl: &gl::Gl, caps: &::Capabilities, prog: super::Program) -> Vec<s::StorageBlockVar> {
+    let (num, max_len) = if caps.storage_buffer_supported {
+        let mut num = 0;
+        let mut max_len = 0;
+        unsafe {
+            gl.GetProgramInterfaceiv(prog, gl::SHADER_STORAGE_BLOCK,
+                                     gl::ACTIVE_RESOURCES, &mut num);
+            gl.GetProgramInterfaceiv(prog, gl::SHADER_STORAGE_BLOCK,
+                                     gl::MAX_NAME_LENGTH, &mut max_len);
+        }
+        (num, max_len)
+    } else {
+        (0, 0)
+    };
+    let props = [gl::BUFFER_DATA_SIZE, gl::REFERENCED_BY_VERTEX_SHADER,
+                 gl::REFERENCED_BY_FRAGMENT_SHADER];
+    range(0, num as gl::types::GLuint).map(|i| {
+        let mut name = String::with_capacity(max_len as uint); //includes terminating null
+        name.grow(max_len as uint, '\0');
+        let mut actual_name_size = 0;
+        let mut values = [0 as gl::types::GLint, ..3];
+        unsafe {
+            gl.GetProgramResourceName(prog, gl::SHADER_STORAGE_BLOCK, i, max_len,
+                &mut actual_name_size, name.as_slice().as_ptr() as *mut gl::types::GLchar);
+            gl.GetProgramResourceiv(prog, gl::SHADER_STORAGE_BLOCK, i,
+                props.len() as gl::types::GLsizei, props.as_ptr(),
+                values.len() as gl::types::GLsizei, &mut 0, values.as_mut_ptr());
+        }
+        name.truncate(actual_name_size as uint);
+        let mut usage = 0;
+        for (stage, &referenced) in values.slice_from(1).iter().enumerate() {
+            if referenced != 0 {usage |= 1<<stage;}
+        }
+        info!("\t\tStorage block '{}' of size {}", name, values[0]);
+        s::StorageBlockVar {
+            name: name,
+            size: values[0] as uint,
+            usage: usage,
+        }
+    }).collect()
+}
+
+fn query_parameters(gl: &gl::Gl, caps: &::Capabilities, prog: super::Program)
+                    -> (Vec<s::UniformVar>, Vec<s::SamplerVar>, Vec<s::ImageVar>) {
     let mut uniforms = Vec::new();
     let mut textures = Vec::new();
+    let mut images = Vec::new();
     let total_num = get_program_iv(gl, prog, gl::ACTIVE_UNIFORMS);
     let indices: Vec<_> = range(0, total_num as gl::types::GLuint).collect();
     let mut block_indices = Vec::from_elem(total_num as uint, -1 as gl::types::GLint);
@@ -226,7 +464,6 @@ fn query_parameters(gl: &gl::Gl, caps: &::Capabilities, prog: super::Program) ->
                 indices.as_slice().as_ptr(), gl::UNIFORM_BLOCK_INDEX,
                 block_indices.as_mut_slice().as_mut_ptr());
         }
-        //TODO: UNIFORM_IS_ROW_MAJOR
     }
     // prepare the name string
     let max_len = get_program_iv(gl, prog, gl::ACTIVE_UNIFORM_MAX_LENGTH);
@@ -237,49 +474,139 @@ fn query_parameters(gl: &gl::Gl, caps: &::Capabilities, prog: super::Program) ->
         let mut length = 0;
         let mut size = 0;
         let mut storage = 0;
-        let loc = unsafe {
+        unsafe {
             let raw = name.as_slice().as_ptr() as *mut gl::types::GLchar;
             gl.GetActiveUniform(prog, i, max_len, &mut length, &mut size, &mut storage, raw);
-            gl.GetUniformLocation(prog, raw as *const gl::types::GLchar)
-        };
-        let real_name = name.as_slice().slice_to(length as uint).to_string();
-        match StorageType::new(storage) {
-            Var(base, container) => {
-                info!("\t\tUniform[{}] = '{}'\t{}\t{}", loc, real_name, base, container);
-                uniforms.push(s::UniformVar {
-                    name: real_name,
-                    location: loc as uint,
-                    count: size as uint,
-                    base_type: base,
-                    container: container,
-                });
-            },
-            Sampler(base, sam_type) => {
-                info!("\t\tSampler[{}] = '{}'\t{}\t{}", loc, real_name, base, sam_type);
-                textures.push(s::SamplerVar {
-                    name: real_name,
-                    location: loc as uint,
-                    base_type: base,
-                    sampler_type: sam_type,
-                });
-            },
-            Unknown => {
-                error!("Unrecognized uniform storage: {}", storage);
-            },
+        }
+        let real_name = name.as_slice().slice_to(length as uint);
+        for (elem_name, loc) in expand_array(gl, prog, real_name, size as uint).into_iter() {
+            add_parameter(elem_name, loc, storage, &mut uniforms, &mut textures, &mut images);
         }
     }
-    (uniforms, textures)
+    (uniforms, textures, images)
+}
+
+/// Split an array uniform into its elements, returning their names and
+/// locations. Arrays are reported with a `[0]` suffix and the number of
+/// elements, while the members of structures are reported one by one.
+fn expand_array(gl: &gl::Gl, prog: super::Program, name: &str, size: uint)
+                -> Vec<(String, gl::types::GLint)> {
+    let get_location = |name: &str| {
+        let c_name = format!("{}\0", name);
+        unsafe { gl.GetUniformLocation(prog, c_name.as_slice().as_ptr() as *const gl::types::GLchar) }
+    };
+    array_elements(name, size).into_iter().map(|elem_name| {
+        let loc = get_location(elem_name.as_slice());
+        (elem_name, loc)
+    }).collect()
+}
+
+/// The names of the elements of an array uniform reported as `name`.
+fn array_elements(name: &str, size: uint) -> Vec<String> {
+    if name.ends_with("[0]") {
+        let base = name.slice_to(name.len() - 3);
+        range(0, size).map(|k| format!("{}[{}]", base, k)).collect()
+    } else {
+        vec![name.to_string()]
+    }
+}
+
+fn add_parameter(real_name: String, loc: gl::types::GLint, storage: gl::types::GLenum,
+                 uniforms: &mut Vec<s::UniformVar>,
+                 textures: &mut Vec<s::SamplerVar>, images: &mut Vec<s::ImageVar>) {
+    match StorageType::new(storage) {
+        Var(base, container) => {
+            info!("\t\tUniform[{}] = '{}'\t{}\t{}", loc, real_name, base, container);
+            uniforms.push(s::UniformVar {
+                name: real_name,
+                location: loc as uint,
+                count: 1,
+                base_type: base,
+                container: container,
+            });
+        },
+        Sampler(base, sam_type) => {
+            info!("\t\tSampler[{}] = '{}'\t{}\t{}", loc, real_name, base, sam_type);
+            textures.push(s::SamplerVar {
+                name: real_name,
+                location: loc as uint,
+                base_type: base,
+                sampler_type: sam_type,
+            });
+        },
+        Image(base, image_type) => {
+            info!("\t\tImage[{}] = '{}'\t{}\t{}", loc, real_name, base, image_type);
+            images.push(s::ImageVar {
+                name: real_name,
+                location: loc as uint,
+                base_type: base,
+                image_type: image_type,
+            });
+        },
+        Unknown => {
+            error!("Unrecognized uniform storage: {}", storage);
+        },
+    }
 }
 
 pub fn create_program(gl: &gl::Gl, caps: &::Capabilities, shaders: &[::ShaderHandle])
-        -> (Result<::ProgramHandle, ()>, Option<String>) {
+        -> (Result<::ProgramHandle, s::ShaderLog>, Option<String>) {
     let name = unsafe { gl.CreateProgram() };
     for sh in shaders.iter() {
         unsafe { gl.AttachShader(name, sh.get_name()) };
     }
+    if caps.program_binary_supported {
+        unsafe {
+            gl.ProgramParameteri(name, gl::PROGRAM_BINARY_RETRIEVABLE_HINT,
+                                 gl::TRUE as gl::types::GLint);
+        }
+    }
     unsafe { gl.LinkProgram(name) };
     info!("\tLinked program {}", name);
 
+    query_program(gl, caps, name)
+}
+
+pub fn create_program_binary(gl: &gl::Gl, caps: &::Capabilities, binary: &s::ProgramBinary)
+        -> (Result<::ProgramHandle, s::ShaderLog>, Option<String>) {
+    let name = unsafe { gl.CreateProgram() };
+    unsafe {
+        gl.ProgramBinary(name, binary.format as gl::types::GLenum,
+                         binary.data.as_ptr() as *const gl::types::GLvoid,
+                         binary.data.len() as gl::types::GLsizei);
+    }
+    info!("\tLoaded program {} from binary", name);
+
+    let (prog, log) = query_program(gl, caps, name);
+    if prog.is_err() {
+        // rejected binaries are expected after driver updates, don't leak them
+        unsafe { gl.DeleteProgram(name) };
+    }
+    (prog, log)
+}
+
+pub fn get_program_binary(gl: &gl::Gl, name: super::Program) -> Option<s::ProgramBinary> {
+    let length = get_program_iv(gl, name, gl::PROGRAM_BINARY_LENGTH);
+    if length <= 0 {
+        return None
+    }
+    let mut data = Vec::from_elem(length as uint, 0u8);
+    let mut written = 0 as gl::types::GLsizei;
+    let mut format = 0 as gl::types::GLenum;
+    unsafe {
+        gl.GetProgramBinary(name, length, &mut written, &mut format,
+                            data.as_mut_ptr() as *mut gl::types::GLvoid);
+    }
+    data.truncate(written as uint);
+    Some(s::ProgramBinary {
+        format: format as u32,
+        data: data,
+    })
+}
+
+/// Check the link status of a program, and query its parameters on success.
+fn query_program(gl: &gl::Gl, caps: &::Capabilities, name: super::Program)
+        -> (Result<::ProgramHandle, s::ShaderLog>, Option<String>) {
     // get info message
     let status = get_program_iv(gl, name, gl::LINK_STATUS);
     let mut length  = get_program_iv(gl, name, gl::INFO_LOG_LENGTH);
@@ -297,36 +624,136 @@ pub fn create_program(gl: &gl::Gl, caps: &::Capabilities, shaders: &[::ShaderHan
     };
 
     let prog = if status != 0 {
-        let (uniforms, textures) = query_parameters(gl, caps, name);
+        let (uniforms, textures, images) = query_parameters(gl, caps, name);
         let info = s::ProgramInfo {
             attributes: query_attributes(gl, name),
             uniforms: uniforms,
             blocks: query_blocks(gl, caps, name),
             textures: textures,
+            images: images,
+            storage_blocks: query_storage_blocks(gl, caps, name),
         };
         Ok(::Handle(name, info))
     } else {
-        Err(())
+        Err(s::ShaderLog::parse(log.as_ref().map_or("", |log| log.as_slice())))
     };
 
     (prog, log)
 }
 
 pub fn bind_uniform(gl: &gl::Gl, loc: gl::types::GLint, uniform: s::UniformValue) {
+    // booleans are uploaded as integers
+    fn to_int(v: &[bool]) -> [gl::types::GLint, ..4] {
+        let mut out = [0, ..4];
+        for (o, &b) in out.iter_mut().zip(v.iter()) {
+            *o = b as gl::types::GLint;
+        }
+        out
+    }
     match uniform {
         s::ValueI32(val) => unsafe { gl.Uniform1i(loc, val) },
+        s::ValueU32(val) => unsafe { gl.Uniform1ui(loc, val) },
         s::ValueF32(val) => unsafe { gl.Uniform1f(loc, val) },
+        s::ValueF64(val) => unsafe { gl.Uniform1d(loc, val) },
+        s::ValueBool(val) => unsafe { gl.Uniform1i(loc, val as gl::types::GLint) },
 
         s::ValueI32Vector2(val) => unsafe { gl.Uniform2iv(loc, 1, val.as_ptr()) },
         s::ValueI32Vector3(val) => unsafe { gl.Uniform3iv(loc, 1, val.as_ptr()) },
         s::ValueI32Vector4(val) => unsafe { gl.Uniform4iv(loc, 1, val.as_ptr()) },
 
+        s::ValueU32Vector2(val) => unsafe { gl.Uniform2uiv(loc, 1, val.as_ptr()) },
+        s::ValueU32Vector3(val) => unsafe { gl.Uniform3uiv(loc, 1, val.as_ptr()) },
+        s::ValueU32Vector4(val) => unsafe { gl.Uniform4uiv(loc, 1, val.as_ptr()) },
+
         s::ValueF32Vector2(val) => unsafe { gl.Uniform2fv(loc, 1, val.as_ptr()) },
         s::ValueF32Vector3(val) => unsafe { gl.Uniform3fv(loc, 1, val.as_ptr()) },
         s::ValueF32Vector4(val) => unsafe { gl.Uniform4fv(loc, 1, val.as_ptr()) },
 
+        s::ValueF64Vector2(val) => unsafe { gl.Uniform2dv(loc, 1, val.as_ptr()) },
+        s::ValueF64Vector3(val) => unsafe { gl.Uniform3dv(loc, 1, val.as_ptr()) },
+        s::ValueF64Vector4(val) => unsafe { gl.Uniform4dv(loc, 1, val.as_ptr()) },
+
+        s::ValueBoolVector2(val) => unsafe { gl.Uniform2iv(loc, 1, to_int(val.as_slice()).as_ptr()) },
+        s::ValueBoolVector3(val) => unsafe { gl.Uniform3iv(loc, 1, to_int(val.as_slice()).as_ptr()) },
+        s::ValueBoolVector4(val) => unsafe { gl.Uniform4iv(loc, 1, to_int(val.as_slice()).as_ptr()) },
+
         s::ValueF32Matrix2(val) => unsafe{ gl.UniformMatrix2fv(loc, 1, gl::FALSE, val[0].as_ptr()) },
         s::ValueF32Matrix3(val) => unsafe{ gl.UniformMatrix3fv(loc, 1, gl::FALSE, val[0].as_ptr()) },
         s::ValueF32Matrix4(val) => unsafe{ gl.UniformMatrix4fv(loc, 1, gl::FALSE, val[0].as_ptr()) },
+
+        s::ValueF32Matrix2x3(val) => unsafe{ gl.UniformMatrix2x3fv(loc, 1, gl::FALSE, val[0].as_ptr()) },
+        s::ValueF32Matrix2x4(val) => unsafe{ gl.UniformMatrix2x4fv(loc, 1, gl::FALSE, val[0].as_ptr()) },
+        s::ValueF32Matrix3x2(val) => unsafe{ gl.UniformMatrix3x2fv(loc, 1, gl::FALSE, val[0].as_ptr()) },
+        s::ValueF32Matrix3x4(val) => unsafe{ gl.UniformMatrix3x4fv(loc, 1, gl::FALSE, val[0].as_ptr()) },
+        s::ValueF32Matrix4x2(val) => unsafe{ gl.UniformMatrix4x2fv(loc, 1, gl::FALSE, val[0].as_ptr()) },
+        s::ValueF32Matrix4x3(val) => unsafe{ gl.UniformMatrix4x3fv(loc, 1, gl::FALSE, val[0].as_ptr()) },
+
+        s::ValueF64Matrix2(val) => unsafe{ gl.UniformMatrix2dv(loc, 1, gl::FALSE, val[0].as_ptr()) },
+        s::ValueF64Matrix3(val) => unsafe{ gl.UniformMatrix3dv(loc, 1, gl::FALSE, val[0].as_ptr()) },
+        s::ValueF64Matrix4(val) => unsafe{ gl.UniformMatrix4dv(loc, 1, gl::FALSE, val[0].as_ptr()) },
+
+        s::ValueF64Matrix2x3(val) => unsafe{ gl.UniformMatrix2x3dv(loc, 1, gl::FALSE, val[0].as_ptr()) },
+        s::ValueF64Matrix2x4(val) => unsafe{ gl.UniformMatrix2x4dv(loc, 1, gl::FALSE, val[0].as_ptr()) },
+        s::ValueF64Matrix3x2(val) => unsafe{ gl.UniformMatrix3x2dv(loc, 1, gl::FALSE, val[0].as_ptr()) },
+        s::ValueF64Matrix3x4(val) => unsafe{ gl.UniformMatrix3x4dv(loc, 1, gl::FALSE, val[0].as_ptr()) },
+        s::ValueF64Matrix4x2(val) => unsafe{ gl.UniformMatrix4x2dv(loc, 1, gl::FALSE, val[0].as_ptr()) },
+        s::ValueF64Matrix4x3(val) => unsafe{ gl.UniformMatrix4x3dv(loc, 1, gl::FALSE, val[0].as_ptr()) },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{select_source, Glsl, SpirV, StorageType, Var, Unknown, array_elements};
+    use super::super::info::Version;
+    use super::super::gl;
+    use shade as s;
+    use shade::ShaderSource;
+
+    static SOURCE: ShaderSource<'static> = ShaderSource {
+        glsl_100_es: Some(b"100 es"),
+        glsl_300_es: None,
+        glsl_120: Some(b"120"),
+        glsl_130: None,
+        glsl_140: None,
+        glsl_150: Some(b"150"),
+        glsl_330: None,
+        glsl_400: None,
+        glsl_410: Some(b"410"),
+        glsl_420: None,
+        glsl_430: None,
+        glsl_440: None,
+        glsl_450: None,
+        spirv: Some(b"spirv"),
+    };
+
+    #[test]
+    fn test_select_source() {
+        let v = |major, minor| Version::new(major, minor, None, "");
+        assert_eq!(select_source(&SOURCE, v(1, 10), false, false), None);
+        assert_eq!(select_source(&SOURCE, v(1, 10), false, true), Some(SpirV(b"spirv")));
+        assert_eq!(select_source(&SOURCE, v(1, 40), false, false), Some(Glsl(b"120")));
+        assert_eq!(select_source(&SOURCE, v(4, 0), false, false), Some(Glsl(b"150")));
+        assert_eq!(select_source(&SOURCE, v(4, 50), false, true), Some(Glsl(b"410")));
+        assert_eq!(select_source(&SOURCE, v(3, 0), true, false), Some(Glsl(b"100 es")));
+    }
+
+    #[test]
+    fn test_storage_type() {
+        assert_eq!(StorageType::new(gl::DOUBLE), Var(s::BaseF64, s::Single));
+        assert_eq!(StorageType::new(gl::DOUBLE_VEC3), Var(s::BaseF64, s::Vector(3)));
+        assert_eq!(StorageType::new(gl::DOUBLE_MAT2x3),
+                   Var(s::BaseF64, s::Matrix(s::ColumnMajor, 2, 3)));
+        assert_eq!(StorageType::new(gl::UNSIGNED_INT_VEC2), Var(s::BaseU32, s::Vector(2)));
+        assert_eq!(StorageType::new(gl::NONE), Unknown);
+    }
+
+    #[test]
+    fn test_array_elements() {
+        assert_eq!(array_elements("exposure", 1), vec!["exposure".to_string()]);
+        assert_eq!(array_elements("weights[0]", 3), vec!["weights[0]".to_string(),
+            "weights[1]".to_string(), "weights[2]".to_string()]);
+        assert_eq!(array_elements("lights[0].colour", 1), vec!["lights[0].colour".to_string()]);
+        assert_eq!(array_elements("lights[1].ranges[0]", 2), vec!["lights[1].ranges[0]".to_string(),
+            "lights[1].ranges[1]".to_string()]);
     }
 }
@@ -206,3 +206,11 @@ pub fn bind_color_mask(gl: &gl::Gl, mask: s::ColorMask) {
         if (mask & s::ALPHA).is_empty() {gl::FALSE} else {gl::TRUE}
     )};
 }
+
+pub fn bind_frame_buffer_srgb(gl: &gl::Gl, enable: bool) {
+    if enable {
+        unsafe { gl.Enable(gl::FRAMEBUFFER_SRGB) };
+    } else {
+        unsafe { gl.Disable(gl::FRAMEBUFFER_SRGB) };
+    }
+}
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::{gl, Surface, Texture, Sampler};
+use super::{gl, Buffer, Surface, Texture, Sampler};
 use super::gl::types::{GLenum, GLuint, GLint, GLfloat, GLsizei, GLvoid};
 use super::state;
 use tex;
@@ -22,7 +22,7 @@ use attrib;
 /// with a GL-compatibility sampler settings in `bind_sampler`
 pub struct BindAnchor(GLenum);
 
-fn create_kind_to_gl(kind: tex::TextureKind) -> GLenum {
+fn kind_to_gl(kind: tex::TextureKind) -> GLenum {
     match kind {
         tex::Texture1D => gl::TEXTURE_1D,
         tex::Texture1DArray => gl::TEXTURE_1D_ARRAY,
@@ -30,20 +30,22 @@ fn create_kind_to_gl(kind: tex::TextureKind) -> GLenum {
         tex::Texture2DArray => gl::TEXTURE_2D_ARRAY,
         tex::Texture2DMultiSample(_) => gl::TEXTURE_2D_MULTISAMPLE,
         tex::Texture2DMultiSampleArray(_) => gl::TEXTURE_2D_MULTISAMPLE_ARRAY,
-        tex::TextureCube(tex::PosZ) => gl::TEXTURE_CUBE_MAP_POSITIVE_Z,
-        tex::TextureCube(tex::NegZ) => gl::TEXTURE_CUBE_MAP_NEGATIVE_Z,
-        tex::TextureCube(tex::PosX) => gl::TEXTURE_CUBE_MAP_POSITIVE_X,
-        tex::TextureCube(tex::NegX) => gl::TEXTURE_CUBE_MAP_NEGATIVE_X,
-        tex::TextureCube(tex::PosY) => gl::TEXTURE_CUBE_MAP_POSITIVE_Y,
-        tex::TextureCube(tex::NegY) => gl::TEXTURE_CUBE_MAP_NEGATIVE_Y,
+        tex::TextureCube => gl::TEXTURE_CUBE_MAP,
+        tex::TextureCubeArray => gl::TEXTURE_CUBE_MAP_ARRAY,
         tex::Texture3D => gl::TEXTURE_3D,
+        tex::TextureBuffer(_) => gl::TEXTURE_BUFFER,
     }
 }
 
-fn bind_kind_to_gl(kind: tex::TextureKind) -> GLenum {
-    match kind {
-        tex::TextureCube(_) => gl::TEXTURE_CUBE_MAP,
-        other => create_kind_to_gl(other)
+/// Get the texture target of a single cube face.
+pub fn face_to_gl(face: tex::CubeFace) -> GLenum {
+    match face {
+        tex::PosZ => gl::TEXTURE_CUBE_MAP_POSITIVE_Z,
+        tex::NegZ => gl::TEXTURE_CUBE_MAP_NEGATIVE_Z,
+        tex::PosX => gl::TEXTURE_CUBE_MAP_POSITIVE_X,
+        tex::NegX => gl::TEXTURE_CUBE_MAP_NEGATIVE_X,
+        tex::PosY => gl::TEXTURE_CUBE_MAP_POSITIVE_Y,
+        tex::NegY => gl::TEXTURE_CUBE_MAP_NEGATIVE_Y,
     }
 }
 
@@ -87,7 +89,7 @@ fn format_to_gl(t: tex::Format) -> Result<GLenum, ()> {
         tex::Integer(tex::RGB, 32, attrib::IntRaw) => gl::RGB32I,
         tex::Integer(tex::RGBA, 32, attrib::IntRaw) => gl::RGBA32I,
 
-        tex::Integer(_, _, _) => unimplemented!(),
+        tex::Integer(_, _, _) => return Err(()),
 
         // unsigned normalized
         tex::Unsigned(tex::RGBA, 2, attrib::IntNormalized) => gl::RGBA2,
@@ -129,18 +131,74 @@ fn format_to_gl(t: tex::Format) -> Result<GLenum, ()> {
         tex::Unsigned(tex::RGB, 32, attrib::IntRaw) => gl::RGB32UI,
         tex::Unsigned(tex::RGBA, 32, attrib::IntRaw) => gl::RGBA32UI,
 
-        tex::Unsigned(_, _, _) => unimplemented!(),
+        tex::Unsigned(_, _, _) => return Err(()),
+
         // special
         tex::Compressed(tex::ETC2_RGB) => gl::COMPRESSED_RGB8_ETC2,
         tex::Compressed(tex::ETC2_SRGB) => gl::COMPRESSED_SRGB8_ETC2,
         tex::Compressed(tex::ETC2_EAC_RGBA8) => gl::COMPRESSED_RGBA8_ETC2_EAC,
+        tex::Compressed(tex::BC1_RGB) => gl::COMPRESSED_RGB_S3TC_DXT1_EXT,
+        tex::Compressed(tex::BC1_RGBA) => gl::COMPRESSED_RGBA_S3TC_DXT1_EXT,
+        tex::Compressed(tex::BC2_RGBA) => gl::COMPRESSED_RGBA_S3TC_DXT3_EXT,
+        tex::Compressed(tex::BC3_RGBA) => gl::COMPRESSED_RGBA_S3TC_DXT5_EXT,
+        tex::Compressed(tex::BC4_R) => gl::COMPRESSED_RED_RGTC1,
+        tex::Compressed(tex::BC4_R_SIGNED) => gl::COMPRESSED_SIGNED_RED_RGTC1,
+        tex::Compressed(tex::BC5_RG) => gl::COMPRESSED_RG_RGTC2,
+        tex::Compressed(tex::BC5_RG_SIGNED) => gl::COMPRESSED_SIGNED_RG_RGTC2,
+        tex::Compressed(tex::BC6H_RGB_UFLOAT) => gl::COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT,
+        tex::Compressed(tex::BC6H_RGB_SFLOAT) => gl::COMPRESSED_RGB_BPTC_SIGNED_FLOAT,
+        tex::Compressed(tex::BC7_RGBA) => gl::COMPRESSED_RGBA_BPTC_UNORM,
+        tex::Compressed(tex::BC7_SRGBA) => gl::COMPRESSED_SRGB_ALPHA_BPTC_UNORM,
+        tex::Compressed(tex::ASTC_RGBA(w, h)) => return astc_to_gl(w, h, false),
+        tex::Compressed(tex::ASTC_SRGBA(w, h)) => return astc_to_gl(w, h, true),
         tex::R3G3B2       => gl::R3_G3_B2,
         tex::RGB5A1       => gl::RGB5_A1,
         tex::RGB10A2      => gl::RGB10_A2,
         tex::RGB10A2UI    => gl::RGB10_A2UI,
         tex::R11FG11FB10F => gl::R11F_G11F_B10F,
         tex::RGB9E5       => gl::RGB9_E5,
+        tex::SRGB8        => gl::SRGB8,
+        tex::SRGB8_A8     => gl::SRGB8_ALPHA8,
+        tex::DEPTH16      => gl::DEPTH_COMPONENT16,
+        tex::DEPTH24      => gl::DEPTH_COMPONENT24,
+        tex::DEPTH32F     => gl::DEPTH_COMPONENT32F,
         tex::DEPTH24STENCIL8 => gl::DEPTH24_STENCIL8,
+        tex::DEPTH32F_STENCIL8 => gl::DEPTH32F_STENCIL8,
+        tex::STENCIL8     => gl::STENCIL_INDEX8,
+    })
+}
+
+fn astc_to_gl(w: u8, h: u8, srgb: bool) -> Result<GLenum, ()> {
+    Ok(match ((w, h), srgb) {
+        ((4, 4), false)   => gl::COMPRESSED_RGBA_ASTC_4x4_KHR,
+        ((5, 4), false)   => gl::COMPRESSED_RGBA_ASTC_5x4_KHR,
+        ((5, 5), false)   => gl::COMPRESSED_RGBA_ASTC_5x5_KHR,
+        ((6, 5), false)   => gl::COMPRESSED_RGBA_ASTC_6x5_KHR,
+        ((6, 6), false)   => gl::COMPRESSED_RGBA_ASTC_6x6_KHR,
+        ((8, 5), false)   => gl::COMPRESSED_RGBA_ASTC_8x5_KHR,
+        ((8, 6), false)   => gl::COMPRESSED_RGBA_ASTC_8x6_KHR,
+        ((8, 8), false)   => gl::COMPRESSED_RGBA_ASTC_8x8_KHR,
+        ((10, 5), false)  => gl::COMPRESSED_RGBA_ASTC_10x5_KHR,
+        ((10, 6), false)  => gl::COMPRESSED_RGBA_ASTC_10x6_KHR,
+        ((10, 8), false)  => gl::COMPRESSED_RGBA_ASTC_10x8_KHR,
+        ((10, 10), false) => gl::COMPRESSED_RGBA_ASTC_10x10_KHR,
+        ((12, 10), false) => gl::COMPRESSED_RGBA_ASTC_12x10_KHR,
+        ((12, 12), false) => gl::COMPRESSED_RGBA_ASTC_12x12_KHR,
+        ((4, 4), true)    => gl::COMPRESSED_SRGB8_ALPHA8_ASTC_4x4_KHR,
+        ((5, 4), true)    => gl::COMPRESSED_SRGB8_ALPHA8_ASTC_5x4_KHR,
+        ((5, 5), true)    => gl::COMPRESSED_SRGB8_ALPHA8_ASTC_5x5_KHR,
+        ((6, 5), true)    => gl::COMPRESSED_SRGB8_ALPHA8_ASTC_6x5_KHR,
+        ((6, 6), true)    => gl::COMPRESSED_SRGB8_ALPHA8_ASTC_6x6_KHR,
+        ((8, 5), true)    => gl::COMPRESSED_SRGB8_ALPHA8_ASTC_8x5_KHR,
+        ((8, 6), true)    => gl::COMPRESSED_SRGB8_ALPHA8_ASTC_8x6_KHR,
+        ((8, 8), true)    => gl::COMPRESSED_SRGB8_ALPHA8_ASTC_8x8_KHR,
+        ((10, 5), true)   => gl::COMPRESSED_SRGB8_ALPHA8_ASTC_10x5_KHR,
+        ((10, 6), true)   => gl::COMPRESSED_SRGB8_ALPHA8_ASTC_10x6_KHR,
+        ((10, 8), true)   => gl::COMPRESSED_SRGB8_ALPHA8_ASTC_10x8_KHR,
+        ((10, 10), true)  => gl::COMPRESSED_SRGB8_ALPHA8_ASTC_10x10_KHR,
+        ((12, 10), true)  => gl::COMPRESSED_SRGB8_ALPHA8_ASTC_12x10_KHR,
+        ((12, 12), true)  => gl::COMPRESSED_SRGB8_ALPHA8_ASTC_12x12_KHR,
+        _ => return Err(()),
     })
 }
 
@@ -153,29 +211,37 @@ fn components_to_glpixel(c: tex::Components) -> GLenum {
     }
 }
 
-fn components_to_count(c: tex::Components) -> uint {
+fn components_to_glpixel_integer(c: tex::Components) -> GLenum {
     match c {
-        tex::R    => 1,
-        tex::RG   => 2,
-        tex::RGB  => 3,
-        tex::RGBA => 4,
+        tex::R    => gl::RED_INTEGER,
+        tex::RG   => gl::RG_INTEGER,
+        tex::RGB  => gl::RGB_INTEGER,
+        tex::RGBA => gl::RGBA_INTEGER,
     }
 }
 
 fn format_to_glpixel(t: tex::Format) -> GLenum {
     match t {
         tex::Float(c, _)       => components_to_glpixel(c),
+        // raw integer data has to be provided with the *_INTEGER pixel formats
+        tex::Integer(c, _, attrib::IntRaw)  => components_to_glpixel_integer(c),
+        tex::Unsigned(c, _, attrib::IntRaw) => components_to_glpixel_integer(c),
         tex::Integer(c, _, _)  => components_to_glpixel(c),
         tex::Unsigned(c, _, _) => components_to_glpixel(c),
-        // this is wrong, but it's not used anyway
-        tex::Compressed(_)     => panic!("Tried to get components of a compressed texel!"),
+        // compressed data is uploaded as-is, this is only used to
+        // allocate the storage without any data
+        tex::Compressed(_)     => gl::RGBA,
         tex::R3G3B2       => gl::RGB,
         tex::RGB5A1       => gl::RGBA,
         tex::RGB10A2      => gl::RGBA,
-        tex::RGB10A2UI    => gl::RGBA,
+        tex::RGB10A2UI    => gl::RGBA_INTEGER,
         tex::R11FG11FB10F => gl::RGB,
         tex::RGB9E5       => gl::RGB,
-        tex::DEPTH24STENCIL8 => gl::DEPTH_STENCIL,
+        tex::SRGB8        => gl::RGB,
+        tex::SRGB8_A8     => gl::RGBA,
+        tex::DEPTH16 | tex::DEPTH24 | tex::DEPTH32F => gl::DEPTH_COMPONENT,
+        tex::DEPTH24STENCIL8 | tex::DEPTH32F_STENCIL8 => gl::DEPTH_STENCIL,
+        tex::STENCIL8     => gl::STENCIL_INDEX,
     }
 }
 
@@ -188,29 +254,19 @@ fn format_to_gltype(t: tex::Format) -> Result<GLenum, ()> {
         tex::Unsigned(_, 16, _) => Ok(gl::UNSIGNED_SHORT),
         tex::Integer(_, 32, _)  => Ok(gl::INT),
         tex::Unsigned(_, 32, _) => Ok(gl::UNSIGNED_INT),
+        tex::RGB10A2UI         => Ok(gl::UNSIGNED_INT_2_10_10_10_REV),
+        tex::SRGB8 | tex::SRGB8_A8 => Ok(gl::UNSIGNED_BYTE),
+        tex::DEPTH16           => Ok(gl::UNSIGNED_SHORT),
+        tex::DEPTH24           => Ok(gl::UNSIGNED_INT),
+        tex::DEPTH32F          => Ok(gl::FLOAT),
         tex::DEPTH24STENCIL8   => Ok(gl::UNSIGNED_INT_24_8),
+        tex::DEPTH32F_STENCIL8 => Ok(gl::FLOAT_32_UNSIGNED_INT_24_8_REV),
+        tex::STENCIL8          => Ok(gl::UNSIGNED_BYTE),
+        tex::Compressed(_)     => Ok(gl::UNSIGNED_BYTE),
         _ => Err(()),
     }
 }
 
-fn format_to_size(t: tex::Format) -> uint {
-    match t {
-        tex::Float(c, attrib::F16) => 2 * components_to_count(c),
-        tex::Float(c, attrib::F32) => 4 * components_to_count(c),
-        tex::Float(c, attrib::F64) => 8 * components_to_count(c),
-        tex::Integer(c, bits, _)  => bits as uint * components_to_count(c) >> 3,
-        tex::Unsigned(c, bits, _) => bits as uint * components_to_count(c) >> 3,
-        tex::Compressed(_) => panic!("Tried to get size of a compressed texel!"),
-        tex::R3G3B2       => 1,
-        tex::RGB5A1       => 2,
-        tex::RGB10A2      => 4,
-        tex::RGB10A2UI    => 4,
-        tex::R11FG11FB10F => 4,
-        tex::RGB9E5       => 4,
-        tex::DEPTH24STENCIL8 => 4,
-    }
-}
-
 fn set_mipmap_range(gl: &gl::Gl, target: GLenum, (base, max): (u8, u8)) { unsafe {
     gl.TexParameteri(target, gl::TEXTURE_BASE_LEVEL, base as GLint);
     gl.TexParameteri(target, gl::TEXTURE_MAX_LEVEL, max as GLint);
@@ -324,12 +380,10 @@ pub fn make_without_storage(gl: &gl::Gl, info: &tex::TextureInfo) ->
                 fixed_sample_locations
             );
         }},
-        tex::TextureCube(_) =>
-            for &target in [gl::TEXTURE_CUBE_MAP_POSITIVE_X, gl::TEXTURE_CUBE_MAP_NEGATIVE_X,
-                    gl::TEXTURE_CUBE_MAP_POSITIVE_Y, gl::TEXTURE_CUBE_MAP_NEGATIVE_Y,
-                    gl::TEXTURE_CUBE_MAP_POSITIVE_Z, gl::TEXTURE_CUBE_MAP_NEGATIVE_Z].iter() {
+        tex::TextureCube =>
+            for &face in tex::CUBE_FACES.iter() {
                 unsafe { gl.TexImage2D(
-                    target,
+                    face_to_gl(face),
                     0,
                     fmt,
                     info.width as GLsizei,
@@ -354,6 +408,21 @@ pub fn make_without_storage(gl: &gl::Gl, info: &tex::TextureInfo) ->
                 ::std::ptr::null()
             );
         },
+        tex::TextureCubeArray => unsafe {
+            // the depth counts all the faces of all the cubes
+            gl.TexImage3D(
+                target,
+                0,
+                fmt,
+                info.width as GLsizei,
+                info.height as GLsizei,
+                info.depth as GLsizei * 6,
+                0,
+                pix,
+                typ,
+                ::std::ptr::null()
+            );
+        },
         tex::Texture2DMultiSampleArray(tex::Msaa(samples)) => { unsafe {
             gl.TexImage3DMultisample(
                 target,
@@ -422,13 +491,9 @@ pub fn make_with_storage(gl: &gl::Gl, info: &tex::TextureInfo) ->
                 info.height as GLsizei
             );
         }},
-        tex::Texture2D | tex::TextureCube(_) => { unsafe {
+        tex::Texture2D | tex::TextureCube => { unsafe {
             gl.TexStorage2D(
-                // to create storage for a texture cube, we don't do individual faces
-                match info.kind {
-                    tex::TextureCube(_) => gl::TEXTURE_CUBE_MAP,
-                    _ => target
-                },
+                target,
                 min(info.levels, mip_level2(info.width, info.height)),
                 fmt,
                 info.width as GLsizei,
@@ -445,6 +510,17 @@ pub fn make_with_storage(gl: &gl::Gl, info: &tex::TextureInfo) ->
                 info.depth as GLsizei
             );
         }},
+        tex::TextureCubeArray => { unsafe {
+            // the depth counts all the faces of all the cubes
+            gl.TexStorage3D(
+                target,
+                min(info.levels, mip_level2(info.width, info.height)),
+                fmt,
+                info.width as GLsizei,
+                info.height as GLsizei,
+                info.depth as GLsizei * 6
+            );
+        }},
         tex::Texture2DMultiSample(tex::Msaa(samples)) => { unsafe {
             gl.TexStorage2DMultisample(
                 target,
@@ -487,7 +563,7 @@ pub fn make_with_storage(gl: &gl::Gl, info: &tex::TextureInfo) ->
 /// Bind a texture to the specified slot
 pub fn bind_texture(gl: &gl::Gl, slot: GLenum, kind: tex::TextureKind,
                     name: Texture) -> BindAnchor {
-    let target = bind_kind_to_gl(kind);
+    let target = kind_to_gl(kind);
     unsafe {
         gl.ActiveTexture(slot);
         gl.BindTexture(target, name);
@@ -515,6 +591,9 @@ pub fn bind_sampler(gl: &gl::Gl, anchor: BindAnchor, info: &tex::SamplerInfo) {
     gl.TexParameteri(target, gl::TEXTURE_WRAP_T, wrap_to_gl(t) as GLint);
     gl.TexParameteri(target, gl::TEXTURE_WRAP_R, wrap_to_gl(r) as GLint);
 
+    let (r, g, b, a) = info.border;
+    gl.TexParameterfv(target, gl::TEXTURE_BORDER_COLOR, [r, g, b, a].as_ptr());
+
     gl.TexParameterf(target, gl::TEXTURE_LOD_BIAS, info.lod_bias);
 
     let (min, max) = info.lod_range;
@@ -530,32 +609,53 @@ pub fn bind_sampler(gl: &gl::Gl, anchor: BindAnchor, info: &tex::SamplerInfo) {
     }
 }}
 
+/// Get the layer and the data offset of the selected face in each cube
+/// updated by `img`, for a cube array. The face is not contiguous between
+/// cubes, so each of them is updated separately.
+fn cube_face_layers(img: &tex::ImageInfo, size: uint) -> Vec<(GLint, uint)> {
+    let face = img.face.unwrap();
+    let cube_size = size / img.depth as uint;
+    range(0, img.depth).map(|i| {
+        (face.to_layer(img.zoffset + i) as GLint, i as uint * cube_size)
+    }).collect()
+}
+
 pub fn update_texture(gl: &gl::Gl, kind: tex::TextureKind, name: Texture,
                       img: &tex::ImageInfo, address: *const u8, size: uint)
                       -> Result<(), tex::TextureError> {
-    if !img.format.is_compressed() {
-        // TODO: can we compute the expected size for compressed formats?
-        let expected_size = img.width as uint * img.height as uint *
-                            img.depth as uint * format_to_size(img.format);
-        if size != expected_size {
-            return Err(tex::IncorrectTextureSize(expected_size));
-        }
+    let expected_size = img.get_data_size();
+    if size != expected_size {
+        return Err(tex::IncorrectTextureSize(expected_size));
+    }
+
+    let is_cube = match kind {
+        tex::TextureCube | tex::TextureCubeArray => true,
+        _ => false,
+    };
+    if is_cube != img.face.is_some() {
+        return Err(tex::InvalidCubeFace);
     }
 
     let data = address as *const GLvoid;
+    let target = kind_to_gl(kind);
+
+    if img.format.is_compressed() {
+        let fmt = match format_to_gl(img.format) {
+            Ok(f) => f,
+            Err(_) => return Err(tex::UnsupportedTextureFormat),
+        };
+        unsafe { gl.BindTexture(target, name) };
+        return compressed_update(gl, kind, target, img, data, fmt, size as GLint);
+    }
+
     let pix = format_to_glpixel(img.format);
     let typ = match format_to_gltype(img.format) {
         Ok(t) => t,
         Err(_) => return Err(tex::UnsupportedTextureFormat),
     };
-    let target = bind_kind_to_gl(kind);
 
     unsafe { gl.BindTexture(target, name) };
 
-    if img.format.is_compressed() {
-        return compressed_update(gl, kind, target, img, data, typ, size as GLint);
-    }
-
     unsafe {
         match kind {
             tex::Texture1D => {
@@ -582,11 +682,9 @@ pub fn update_texture(gl: &gl::Gl, kind: tex::TextureKind, name: Texture,
                     data
                 );
             },
-            tex::TextureCube(_) => {
-                // get specific face target
-                let target = create_kind_to_gl(kind);
+            tex::TextureCube => {
                 gl.TexSubImage2D(
-                    target,
+                    face_to_gl(img.face.unwrap()),
                     img.mipmap as GLint,
                     img.xoffset as GLint,
                     img.yoffset as GLint,
@@ -597,6 +695,23 @@ pub fn update_texture(gl: &gl::Gl, kind: tex::TextureKind, name: Texture,
                     data
                 );
             },
+            tex::TextureCubeArray => {
+                for &(layer, offset) in cube_face_layers(img, size).iter() {
+                    gl.TexSubImage3D(
+                        target,
+                        img.mipmap as GLint,
+                        img.xoffset as GLint,
+                        img.yoffset as GLint,
+                        layer,
+                        img.width as GLint,
+                        img.height as GLint,
+                        1,
+                        pix,
+                        typ,
+                        address.offset(offset as int) as *const GLvoid
+                    );
+                }
+            },
             tex::Texture2DArray | tex::Texture3D => {
                 gl.TexSubImage3D(
                     target,
@@ -614,6 +729,8 @@ pub fn update_texture(gl: &gl::Gl, kind: tex::TextureKind, name: Texture,
             },
             tex::Texture2DMultiSample(_) | tex::Texture2DMultiSampleArray(_) =>
                 return Err(tex::UnsupportedTextureSampling),
+            // the texels of a buffer texture are updated through its buffer
+            tex::TextureBuffer(_) => return Err(tex::UnsupportedTextureBuffer),
         }
     }
 
@@ -621,7 +738,7 @@ pub fn update_texture(gl: &gl::Gl, kind: tex::TextureKind, name: Texture,
 }
 
 pub fn compressed_update(gl: &gl::Gl, kind: tex::TextureKind, target: GLenum, img: &tex::ImageInfo,
-                         data: *const GLvoid, typ: GLenum, size: GLint)
+                         data: *const GLvoid, fmt: GLenum, size: GLint)
                          -> Result<(), tex::TextureError> {
     unsafe {
         match kind {
@@ -631,7 +748,7 @@ pub fn compressed_update(gl: &gl::Gl, kind: tex::TextureKind, target: GLenum, im
                     img.mipmap as GLint,
                     img.xoffset as GLint,
                     img.width as GLint,
-                    typ,
+                    fmt,
                     size as GLint,
                     data
                 );
@@ -644,26 +761,42 @@ pub fn compressed_update(gl: &gl::Gl, kind: tex::TextureKind, target: GLenum, im
                     img.yoffset as GLint,
                     img.width as GLint,
                     img.height as GLint,
-                    typ,
+                    fmt,
                     size as GLint,
                     data
                 );
             },
-            tex::TextureCube(_) => {
-                // get specific face target
-                let target = create_kind_to_gl(kind);
+            tex::TextureCube => {
                 gl.CompressedTexSubImage2D(
-                    target,
+                    face_to_gl(img.face.unwrap()),
                     img.mipmap as GLint,
                     img.xoffset as GLint,
                     img.yoffset as GLint,
                     img.width as GLint,
                     img.height as GLint,
-                    typ,
+                    fmt,
                     size as GLint,
                     data
                 );
             },
+            tex::TextureCubeArray => {
+                let cube_size = size / img.depth as GLint;
+                for &(layer, offset) in cube_face_layers(img, size as uint).iter() {
+                    gl.CompressedTexSubImage3D(
+                        target,
+                        img.mipmap as GLint,
+                        img.xoffset as GLint,
+                        img.yoffset as GLint,
+                        layer,
+                        img.width as GLint,
+                        img.height as GLint,
+                        1,
+                        fmt,
+                        cube_size,
+                        (data as *const u8).offset(offset as int) as *const GLvoid
+                    );
+                }
+            },
             tex::Texture2DArray | tex::Texture3D => {
                 gl.CompressedTexSubImage3D(
                     target,
@@ -674,18 +807,21 @@ pub fn compressed_update(gl: &gl::Gl, kind: tex::TextureKind, target: GLenum, im
                     img.width as GLint,
                     img.height as GLint,
                     img.depth as GLint,
-                    typ,
+                    fmt,
                     size as GLint,
                     data
                 );
             },
             tex::Texture2DMultiSample(_) | tex::Texture2DMultiSampleArray(_) =>
                 return Err(tex::UnsupportedTextureSampling),
+            // the texels of a buffer texture are updated through its buffer
+            tex::TextureBuffer(_) => return Err(tex::UnsupportedTextureBuffer),
         }
     }
 
     Ok(())
 }
+
 /// Common texture creation routine, just creates and binds.
 fn make_texture(gl: &gl::Gl, info: &tex::TextureInfo) -> (Texture, GLuint) {
     let mut name = 0 as GLuint;
@@ -693,16 +829,137 @@ fn make_texture(gl: &gl::Gl, info: &tex::TextureInfo) -> (Texture, GLuint) {
         gl.GenTextures(1, &mut name);
     }
 
-    let k = bind_kind_to_gl(info.kind);
+    let k = kind_to_gl(info.kind);
     unsafe { gl.BindTexture(k, name) };
+    if info.swizzle != tex::IDENTITY_SWIZZLE {
+        set_swizzle(gl, k, info.swizzle);
+    }
     (name, k)
 }
 
+fn swizzle_to_gl(s: tex::ComponentSwizzle) -> GLenum {
+    match s {
+        tex::SwizzleR    => gl::RED,
+        tex::SwizzleG    => gl::GREEN,
+        tex::SwizzleB    => gl::BLUE,
+        tex::SwizzleA    => gl::ALPHA,
+        tex::SwizzleZero => gl::ZERO,
+        tex::SwizzleOne  => gl::ONE,
+    }
+}
+
+fn set_swizzle(gl: &gl::Gl, target: GLenum, (r, g, b, a): tex::Swizzle) { unsafe {
+    gl.TexParameteri(target, gl::TEXTURE_SWIZZLE_R, swizzle_to_gl(r) as GLint);
+    gl.TexParameteri(target, gl::TEXTURE_SWIZZLE_G, swizzle_to_gl(g) as GLint);
+    gl.TexParameteri(target, gl::TEXTURE_SWIZZLE_B, swizzle_to_gl(b) as GLint);
+    gl.TexParameteri(target, gl::TEXTURE_SWIZZLE_A, swizzle_to_gl(a) as GLint);
+}}
+
+fn image_access_to_gl(access: tex::ImageAccess) -> GLenum {
+    match access {
+        tex::ReadOnly => gl::READ_ONLY,
+        tex::WriteOnly => gl::WRITE_ONLY,
+        tex::ReadWrite => gl::READ_WRITE,
+    }
+}
+
+/// Bind a level of the texture to the specified image unit. Without a
+/// layer, all the layers of an array, cube or 3D texture are bound.
+pub fn bind_image(gl: &gl::Gl, unit: GLuint, name: Texture, level: u8, layer: Option<u16>,
+                  access: tex::ImageAccess, format: tex::Format) ->
+                  Result<(), tex::TextureError> {
+    let fmt = match format_to_gl(format) {
+        Ok(f) if !format.is_compressed() => f,
+        _ => return Err(tex::UnsupportedTextureFormat),
+    };
+    let (layered, layer) = match layer {
+        Some(l) => (gl::FALSE, l as GLint),
+        None => (gl::TRUE, 0),
+    };
+    unsafe {
+        gl.BindImageTexture(unit, name, level as GLint, layered, layer,
+                            image_access_to_gl(access), fmt);
+    }
+    Ok(())
+}
+
+/// Check if the format can be used for the texels of a buffer texture.
+fn is_buffer_format(t: tex::Format) -> bool {
+    match t {
+        tex::Float(tex::RGB, attrib::F32) |
+        tex::Integer(tex::RGB, 32, attrib::IntRaw) |
+        tex::Unsigned(tex::RGB, 32, attrib::IntRaw) => true,
+        tex::Float(tex::RGB, _) | tex::Integer(tex::RGB, _, _) |
+        tex::Unsigned(tex::RGB, _, _) => false,
+        tex::Float(_, attrib::F16) | tex::Float(_, attrib::F32) => true,
+        tex::Integer(_, 8, attrib::IntRaw) | tex::Integer(_, 16, attrib::IntRaw) |
+        tex::Integer(_, 32, attrib::IntRaw) => true,
+        tex::Unsigned(_, 8, attrib::IntRaw) | tex::Unsigned(_, 16, attrib::IntRaw) |
+        tex::Unsigned(_, 32, attrib::IntRaw) => true,
+        tex::Unsigned(_, 8, attrib::IntNormalized) |
+        tex::Unsigned(_, 16, attrib::IntNormalized) => true,
+        _ => false,
+    }
+}
+
+/// Create a buffer texture that reads its texels from the given buffer.
+pub fn make_buffer_texture(gl: &gl::Gl, buffer: Buffer, format: tex::Format) ->
+                           Result<Texture, tex::TextureError> {
+    if !is_buffer_format(format) {
+        return Err(tex::UnsupportedTextureFormat)
+    }
+    let fmt = match format_to_gl(format) {
+        Ok(f) => f,
+        Err(_) => return Err(tex::UnsupportedTextureFormat),
+    };
+
+    let mut name = 0 as GLuint;
+    unsafe {
+        gl.GenTextures(1, &mut name);
+        gl.BindTexture(gl::TEXTURE_BUFFER, name);
+        gl.TexBuffer(gl::TEXTURE_BUFFER, fmt, buffer);
+    }
+
+    Ok(name)
+}
+
+/// Create a view into the storage of the `source` texture.
+pub fn make_view(gl: &gl::Gl, source: Texture, view: &tex::ViewInfo) ->
+                 Result<Texture, tex::TextureError> {
+    let fmt = match format_to_gl(view.format) {
+        Ok(f) => f,
+        Err(_) => return Err(tex::UnsupportedTextureFormat),
+    };
+    let target = kind_to_gl(view.kind);
+
+    let mut name = 0 as GLuint;
+    unsafe {
+        gl.GenTextures(1, &mut name);
+        gl.TextureView(
+            name,
+            target,
+            source,
+            fmt,
+            view.min_level as GLuint,
+            view.levels as GLuint,
+            view.min_layer as GLuint,
+            view.layers as GLuint
+        );
+        gl.BindTexture(target, name);
+    }
+    if view.swizzle != tex::IDENTITY_SWIZZLE {
+        set_swizzle(gl, target, view.swizzle);
+    }
+
+    Ok(name)
+}
+
 fn wrap_to_gl(w: tex::WrapMode) -> GLenum {
     match w {
         tex::Tile   => gl::REPEAT,
         tex::Mirror => gl::MIRRORED_REPEAT,
         tex::Clamp  => gl::CLAMP_TO_EDGE,
+        tex::Border => gl::CLAMP_TO_BORDER,
     }
 }
 
@@ -713,6 +970,129 @@ fn filter_to_gl(f: tex::FilterMethod) -> (GLenum, GLenum) {
         tex::Bilinear => (gl::LINEAR, gl::LINEAR),
         tex::Trilinear => (gl::LINEAR_MIPMAP_LINEAR, gl::LINEAR),
         tex::Anisotropic(..) => (gl::LINEAR_MIPMAP_LINEAR, gl::LINEAR),
+        tex::Separate(min, mag, mip) => (
+            match (min, mip) {
+                (tex::Nearest, None) => gl::NEAREST,
+                (tex::Linear,  None) => gl::LINEAR,
+                (tex::Nearest, Some(tex::Nearest)) => gl::NEAREST_MIPMAP_NEAREST,
+                (tex::Nearest, Some(tex::Linear))  => gl::NEAREST_MIPMAP_LINEAR,
+                (tex::Linear,  Some(tex::Nearest)) => gl::LINEAR_MIPMAP_NEAREST,
+                (tex::Linear,  Some(tex::Linear))  => gl::LINEAR_MIPMAP_LINEAR,
+            },
+            match mag {
+                tex::Nearest => gl::NEAREST,
+                tex::Linear  => gl::LINEAR,
+            }
+        ),
+    }
+}
+
+struct CachedSampler {
+    info: tex::SamplerInfo,
+    name: Sampler,
+    refs: uint,
+}
+
+/// Samplers shared between equal descriptions, with the number of handles
+/// referring to each of them.
+pub struct SamplerCache {
+    entries: Vec<CachedSampler>,
+}
+
+impl SamplerCache {
+    pub fn new() -> SamplerCache {
+        SamplerCache {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Find the sampler with an equal description and add a reference to it.
+    pub fn acquire(&mut self, info: &tex::SamplerInfo) -> Option<Sampler> {
+        self.entries.iter_mut().find(|e| e.info == *info).map(|e| {
+            e.refs += 1;
+            e.name
+        })
+    }
+
+    /// Add a newly created sampler, with a single reference to it.
+    pub fn insert(&mut self, info: tex::SamplerInfo, name: Sampler) {
+        self.entries.push(CachedSampler {
+            info: info,
+            name: name,
+            refs: 1,
+        });
+    }
+
+    /// Remove a reference to the sampler with the given description. Returns
+    /// the sampler if it is no longer referenced and needs to be deleted.
+    pub fn release(&mut self, info: &tex::SamplerInfo) -> Option<Sampler> {
+        let pos = match self.entries.iter().position(|e| e.info == *info) {
+            Some(pos) => pos,
+            None => {
+                error!("Released a sampler that is not in the cache: {}", info);
+                return None
+            },
+        };
+        let unused = {
+            let entry = self.entries.get_mut(pos).unwrap();
+            entry.refs -= 1;
+            entry.refs == 0
+        };
+        if unused {
+            self.entries.swap_remove(pos).map(|e| e.name)
+        } else {
+            None
+        }
+    }
+}
+
+struct AppliedSampler {
+    texture: Texture,
+    info: tex::SamplerInfo,
+}
+
+/// The sampler descriptions last applied to the parameters of each texture,
+/// so that `bind_sampler` can be skipped when sampler objects are missing.
+pub struct AppliedSamplers {
+    entries: Vec<AppliedSampler>,
+}
+
+impl AppliedSamplers {
+    pub fn new() -> AppliedSamplers {
+        AppliedSamplers {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Record the description applied to the texture. Returns false if it
+    /// is equal to the last one, so the parameters are already set.
+    pub fn apply(&mut self, texture: Texture, info: &tex::SamplerInfo) -> bool {
+        match self.entries.iter_mut().find(|e| e.texture == texture) {
+            Some(e) => {
+                if e.info == *info {
+                    return false
+                }
+                e.info = *info;
+                return true
+            },
+            None => (),
+        }
+        self.entries.push(AppliedSampler {
+            texture: texture,
+            info: *info,
+        });
+        true
+    }
+
+    /// Forget the parameters of a deleted texture, as its name can be reused.
+    pub fn forget(&mut self, texture: Texture) {
+        self.entries.retain(|e| e.texture != texture);
+    }
+
+    /// Forget the parameters of all textures, after they may have been changed
+    /// outside of the device.
+    pub fn clear(&mut self) {
+        self.entries.clear();
     }
 }
 
@@ -736,6 +1116,9 @@ pub fn make_sampler(gl: &gl::Gl, info: &tex::SamplerInfo) -> Sampler { unsafe {
     gl.SamplerParameteri(name, gl::TEXTURE_WRAP_T, wrap_to_gl(t) as GLint);
     gl.SamplerParameteri(name, gl::TEXTURE_WRAP_R, wrap_to_gl(r) as GLint);
 
+    let (r, g, b, a) = info.border;
+    gl.SamplerParameterfv(name, gl::TEXTURE_BORDER_COLOR, [r, g, b, a].as_ptr());
+
     gl.SamplerParameterf(name, gl::TEXTURE_LOD_BIAS, info.lod_bias);
 
     let (min, max) = info.lod_range;
@@ -756,7 +1139,71 @@ pub fn make_sampler(gl: &gl::Gl, info: &tex::SamplerInfo) -> Sampler { unsafe {
 pub fn generate_mipmap(gl: &gl::Gl, kind: tex::TextureKind, name: Texture) { unsafe {
     //can't fail here, but we need to check for integer formats too
     debug_assert!(kind.get_aa_mode().is_none());
-    let target = bind_kind_to_gl(kind);
+    debug_assert!(match kind { tex::TextureBuffer(_) => false, _ => true });
+    let target = kind_to_gl(kind);
     gl.BindTexture(target, name);
     gl.GenerateMipmap(target);
 }}
+
+#[cfg(test)]
+mod test {
+    use super::{SamplerCache, AppliedSamplers, cube_face_layers, face_to_gl};
+    use super::super::gl;
+    use super::super::gl::types::GLenum;
+    use tex;
+
+    #[test]
+    fn test_face_to_gl() {
+        // the GL face targets follow the layer order of the faces
+        for (i, &face) in tex::CUBE_FACES.iter().enumerate() {
+            assert_eq!(face_to_gl(face), gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as GLenum);
+        }
+    }
+
+    #[test]
+    fn test_cube_face_layers() {
+        let img = tex::ImageInfo {
+            width: 4,
+            height: 4,
+            zoffset: 1,
+            depth: 3,
+            face: Some(tex::NegY),
+            ..tex::ImageInfo::new()
+        };
+        let size = img.get_data_size();
+        assert_eq!(cube_face_layers(&img, size), vec![(9, 0), (15, 64), (21, 128)]);
+    }
+
+    #[test]
+    fn test_sampler_cache() {
+        let bilinear = tex::SamplerInfo::new(tex::Bilinear, tex::Clamp);
+        let border = tex::SamplerInfo { border: (0.0, 0.0, 0.0, 1.0), ..bilinear };
+        let mut cache = SamplerCache::new();
+        assert_eq!(cache.acquire(&bilinear), None);
+        cache.insert(bilinear, 1);
+        assert_eq!(cache.acquire(&border), None);
+        cache.insert(border, 2);
+        assert_eq!(cache.acquire(&bilinear), Some(1));
+        assert_eq!(cache.release(&bilinear), None);
+        assert_eq!(cache.release(&bilinear), Some(1));
+        assert_eq!(cache.acquire(&bilinear), None);
+        assert_eq!(cache.release(&border), Some(2));
+    }
+
+    #[test]
+    fn test_applied_samplers() {
+        let bilinear = tex::SamplerInfo::new(tex::Bilinear, tex::Clamp);
+        let trilinear = tex::SamplerInfo::new(tex::Trilinear, tex::Clamp);
+        let mut applied = AppliedSamplers::new();
+        assert!(applied.apply(1, &bilinear));
+        assert!(!applied.apply(1, &bilinear));
+        assert!(applied.apply(2, &bilinear));
+        assert!(applied.apply(1, &trilinear));
+        assert!(!applied.apply(1, &trilinear));
+        applied.forget(1);
+        assert!(applied.apply(1, &trilinear));
+        assert!(!applied.apply(2, &bilinear));
+        applied.clear();
+        assert!(applied.apply(2, &bilinear));
+    }
+}
@@ -17,8 +17,9 @@
 //! `RefBatch` and `OwnedBatch` implementations.
 
 use std::fmt;
-use std::num::from_uint;
-use device::ProgramHandle;
+use std::mem;
+use std::num::{Bounded, from_uint};
+use device::{AttributeSlot, ProgramHandle};
 use device::shade::ProgramInfo;
 use mesh;
 use mesh::ToSlice;
@@ -48,10 +49,17 @@ pub enum BatchError {
 }
 
 /// Match mesh attributes against shader inputs, produce a mesh link.
+/// The shader inputs have to be located below `max_attributes`, which is
+/// the `max_vertex_attributes` of the device.
 /// Exposed to public to allow external `Batch` implementations to use it.
-pub fn link_mesh(mesh: &mesh::Mesh, pinfo: &ProgramInfo) -> Result<mesh::Link, MeshError> {
+pub fn link_mesh(mesh: &mesh::Mesh, pinfo: &ProgramInfo, max_attributes: uint)
+                 -> Result<mesh::Link, MeshError> {
+    let max_slot: AttributeSlot = Bounded::max_value();
     let mut indices = Vec::new();
     for sat in pinfo.attributes.iter() {
+        if sat.location >= max_attributes || sat.location > max_slot as uint {
+            return Err(ErrorMeshLink(mesh::ErrorShaderInput(sat.location)))
+        }
         match mesh.attributes.iter().enumerate()
                   .find(|&(_, a)| a.name.as_slice() == sat.name.as_slice()) {
             Some((attrib_id, vat)) => match vat.format.elem_type.is_compatible(sat.base_type) {
@@ -83,20 +91,22 @@ pub struct OwnedBatch<L, T> {
     pub param: T,
     program: ProgramHandle,
     param_link: L,
+    max_attributes: uint,
     /// Draw state
     pub state: DrawState,
 }
 
 impl<L, T: ShaderParam<L>> OwnedBatch<L, T> {
-    /// Create a new owned batch
-    pub fn new(mesh: mesh::Mesh, program: ProgramHandle, param: T)
+    /// Create a new owned batch, for a device supporting `max_attributes`
+    /// vertex attributes.
+    pub fn new(mesh: mesh::Mesh, program: ProgramHandle, param: T, max_attributes: uint)
            -> Result<OwnedBatch<L, T>, BatchError> {
         let slice = mesh.to_slice(::device::TriangleList);
-        let mesh_link = match link_mesh(&mesh, program.get_info()) {
+        let mesh_link = match link_mesh(&mesh, program.get_info(), max_attributes) {
             Ok(l) => l,
             Err(e) => return Err(ErrorMesh(e)),
         };
-        let param_link = match ShaderParam::create_link(None::<&T>, program.get_info()) {
+        let param_link = match ShaderParam::create_link(Some(&param), program.get_info()) {
             Ok(l) => l,
             Err(e) => return Err(ErrorParameters(e)),
         };
@@ -107,9 +117,30 @@ impl<L, T: ShaderParam<L>> OwnedBatch<L, T> {
             program: program,
             param: param,
             param_link: param_link,
+            max_attributes: max_attributes,
             state: DrawState::new(),
         })
     }
+
+    /// Switch to another program, such as a reloaded version of the current
+    /// one. The batch is left unchanged if the program does not match it.
+    /// Returns the previous program, which the caller deletes with
+    /// `Device::delete_program` once nothing else uses it. Renderers notice
+    /// the deletion and drop whatever they cached for that program.
+    pub fn set_program(&mut self, program: ProgramHandle)
+                       -> Result<ProgramHandle, BatchError> {
+        let mesh_link = match link_mesh(&self.mesh, program.get_info(), self.max_attributes) {
+            Ok(l) => l,
+            Err(e) => return Err(ErrorMesh(e)),
+        };
+        let param_link = match ShaderParam::create_link(Some(&self.param), program.get_info()) {
+            Ok(l) => l,
+            Err(e) => return Err(ErrorParameters(e)),
+        };
+        self.mesh_link = mesh_link;
+        self.param_link = param_link;
+        Ok(mem::replace(&mut self.program, program))
+    }
 }
 
 impl<L, T: ShaderParam<L>> Batch for OwnedBatch<L, T> {
@@ -208,6 +239,41 @@ pub struct RefBatch<L, T> {
     state_id: Id<DrawState>,
 }
 
+/// The links of a ref batch against a replacement program, produced by
+/// `RefBatch::link_program` and applied with `RefBatch::set_links`.
+pub struct ProgramLinks<L> {
+    mesh_link: mesh::Link,
+    param_link: L,
+}
+
+impl<L, T: ShaderParam<L>> RefBatch<L, T> {
+    /// Link against another program, such as a reloaded version of the
+    /// current one, leaving the batch unchanged. All the batches using the
+    /// program need to be linked before calling `Context::replace_program`.
+    pub fn link_program(&self, context: &Context, program: &ProgramHandle)
+                        -> Result<ProgramLinks<L>, BatchError> {
+        let mesh_link = match link_mesh(context.meshes.get(self.mesh_id), program.get_info(),
+                                        context.max_attributes) {
+            Ok(l) => l,
+            Err(e) => return Err(ErrorMesh(e)),
+        };
+        let param_link = match ShaderParam::create_link(None::<&T>, program.get_info()) {
+            Ok(l) => l,
+            Err(e) => return Err(ErrorParameters(e)),
+        };
+        Ok(ProgramLinks {
+            mesh_link: mesh_link,
+            param_link: param_link,
+        })
+    }
+
+    /// Use the links to the program that replaced the one of the batch.
+    pub fn set_links(&mut self, links: ProgramLinks<L>) {
+        self.mesh_link = links.mesh_link;
+        self.param_link = links.param_link;
+    }
+}
+
 impl<L, T> fmt::Show for RefBatch<L, T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "RefBatch(mesh: {}, slice: {}, program: {}, state: {})",
@@ -243,15 +309,33 @@ pub struct Context {
     meshes: Array<mesh::Mesh>,
     programs: Array<ProgramHandle>,
     states: Array<DrawState>,
+    max_attributes: uint,
 }
 
 impl Context {
-    /// Create a new empty `Context`
-    pub fn new() -> Context {
+    /// Create a new empty `Context`, for a device supporting `max_attributes`
+    /// vertex attributes.
+    pub fn new(max_attributes: uint) -> Context {
         Context {
             meshes: Array::new(),
             programs: Array::new(),
             states: Array::new(),
+            max_attributes: max_attributes,
+        }
+    }
+
+    /// Replace a program used by the batches of this context, such as with
+    /// a reloaded version of it. Returns the replaced program, to be deleted
+    /// with `Device::delete_program` by the caller, or `None` if the old
+    /// program is unknown. The affected batches have to be linked beforehand
+    /// with `RefBatch::link_program`, so that the program is only replaced if
+    /// all of them accept it, and then given their links with
+    /// `RefBatch::set_links`.
+    pub fn replace_program(&mut self, old: &ProgramHandle, new: ProgramHandle)
+                           -> Option<ProgramHandle> {
+        match self.programs.data.iter().position(|p| p == old) {
+            Some(i) => Some(mem::replace(self.programs.data.get_mut(i).unwrap(), new)),
+            None => None,
         }
     }
 }
@@ -261,7 +345,7 @@ impl Context {
     pub fn batch<L, T: ShaderParam<L>>(&mut self, mesh: &mesh::Mesh,
                 slice: mesh::Slice, program: &ProgramHandle, state: &DrawState)
                 -> Result<RefBatch<L, T>, BatchError> {
-        let mesh_link = match link_mesh(mesh, program.get_info()) {
+        let mesh_link = match link_mesh(mesh, program.get_info(), self.max_attributes) {
             Ok(l) => l,
             Err(e) => return Err(ErrorMesh(e)),
         };
@@ -308,3 +392,82 @@ impl<'a, L, T: ShaderParam<L>> Batch for (&'a RefBatch<L, T>, &'a T, &'a Context
         data.fill_params(&b.param_link, values);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use device;
+    use device::ProgramHandle;
+    use device::shade::{Attribute, BaseF32, ProgramInfo, Vector};
+    use mesh::{Mesh, ToSlice, ErrorShaderInput};
+    use state::DrawState;
+    use super::{Context, ErrorAttributeMissing, ErrorMesh, ErrorMeshLink, OwnedBatch, RefBatch};
+    use super::link_mesh;
+
+    fn program(name: device::back::Program, attributes: &[&str]) -> ProgramHandle {
+        device::make_fake_program(name, program_info(attributes))
+    }
+
+    fn program_info(attributes: &[&str]) -> ProgramInfo {
+        ProgramInfo {
+            attributes: attributes.iter().enumerate().map(|(i, n)| Attribute {
+                name: n.to_string(),
+                location: i,
+                count: 1,
+                base_type: BaseF32,
+                container: Vector(4),
+            }).collect(),
+            uniforms: Vec::new(),
+            blocks: Vec::new(),
+            textures: Vec::new(),
+            images: Vec::new(),
+            storage_blocks: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_link_mesh_limit() {
+        let mut info = program_info(&["a_Pos"]);
+        info.attributes.get_mut(0).unwrap().location = 16;
+        match link_mesh(&Mesh::new(3), &info, 16) {
+            Err(ErrorMeshLink(ErrorShaderInput(16))) => (),
+            _ => panic!("the attribute location should be out of bounds"),
+        }
+        match link_mesh(&Mesh::new(3), &info, 17) {
+            Err(ErrorAttributeMissing(_)) => (),
+            _ => panic!("the attribute location should be in bounds"),
+        }
+    }
+
+    #[test]
+    fn test_set_program() {
+        let (old, new) = (program(1, &[]), program(2, &[]));
+        let mut batch: OwnedBatch<(), ()> = OwnedBatch::new(Mesh::new(3), old.clone(), (), 16)
+                                                       .unwrap();
+        assert_eq!(batch.set_program(new.clone()).unwrap(), old);
+        assert_eq!(batch.program, new);
+        match batch.set_program(program(3, &["a_Pos"])) {
+            Err(ErrorMesh(ErrorAttributeMissing(ref name))) =>
+                assert_eq!(name.as_slice(), "a_Pos"),
+            _ => panic!("expected a missing attribute"),
+        }
+        assert_eq!(batch.program, new);
+    }
+
+    #[test]
+    fn test_replace_program() {
+        let mesh = Mesh::new(3);
+        let (old, new) = (program(1, &[]), program(2, &[]));
+        let mut context = Context::new(16);
+        let mut batch: RefBatch<(), ()> = context.batch(&mesh,
+            mesh.to_slice(device::TriangleList), &old, &DrawState::new()).unwrap();
+        match batch.link_program(&context, &program(3, &["a_Pos"])) {
+            Err(ErrorMesh(ErrorAttributeMissing(_))) => (),
+            _ => panic!("expected a missing attribute"),
+        }
+        let links = batch.link_program(&context, &new).unwrap();
+        assert_eq!(context.replace_program(&old, new.clone()), Some(old.clone()));
+        assert_eq!(context.replace_program(&old, new.clone()), None);
+        batch.set_links(links);
+        assert_eq!(*context.programs.get(batch.program_id), new);
+    }
+}
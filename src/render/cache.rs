@@ -0,0 +1,229 @@
+// Copyright 2014 The Gfx-rs Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! On-disk cache of linked program binaries. Programs are keyed by their
+//! shader sources and the driver, and are only compiled and linked when no
+//! binary is found or the driver rejects it.
+
+use std::cell::RefCell;
+use std::hash::hash;
+use std::io;
+use std::io::{File, IoResult, TempDir};
+use std::io::fs;
+
+use device;
+use device::draw::CommandBuffer;
+use device::shade::{ProgramBinary, ShaderSource};
+use {DeviceHelper, ProgramError};
+
+/// A directory of program binaries.
+pub struct ProgramCache {
+    dir: Path,
+    driver: String,
+}
+
+/// What a binary was made from, stored in the file header so that a hash
+/// collision or a renamed file can't load the wrong program.
+struct BinaryKey {
+    driver: String,
+    source: u64,
+}
+
+fn mismatch_error() -> io::IoError {
+    io::IoError {
+        kind: io::InvalidInput,
+        desc: "The program binary was made from other sources or driver",
+        detail: None,
+    }
+}
+
+fn read_binary(path: &Path, key: &BinaryKey) -> IoResult<ProgramBinary> {
+    let mut file = try!(File::open(path));
+    let driver_len = try!(file.read_le_u32()) as uint;
+    // don't trust the length of a corrupt header
+    if driver_len != key.driver.len() {
+        return Err(mismatch_error())
+    }
+    let driver = try!(file.read_exact(driver_len));
+    let source = try!(file.read_le_u64());
+    if driver.as_slice() != key.driver.as_bytes() || source != key.source {
+        return Err(mismatch_error())
+    }
+    let format = try!(file.read_le_u32());
+    let data = try!(file.read_to_end());
+    Ok(ProgramBinary {
+        format: format,
+        data: data,
+    })
+}
+
+/// Write the binary into a temporary file first, and then move it into
+/// place, so that an interrupted write never leaves a truncated binary. The
+/// temporary file is in a directory of its own, so that concurrent writers
+/// don't share it.
+fn write_binary(path: &Path, key: &BinaryKey, binary: &ProgramBinary) -> IoResult<()> {
+    let dir = path.dir_path();
+    try!(fs::mkdir_recursive(&dir, io::USER_RWX));
+    let temp_dir = try!(TempDir::new_in(&dir, "program"));
+    let temp = temp_dir.path().join("program.bin");
+    {
+        let mut file = try!(File::create(&temp));
+        try!(file.write_le_u32(key.driver.len() as u32));
+        try!(file.write_str(key.driver.as_slice()));
+        try!(file.write_le_u64(key.source));
+        try!(file.write_le_u32(binary.format));
+        try!(file.write(binary.data.as_slice()));
+    }
+    match fs::rename(&temp, path) {
+        Ok(()) => Ok(()),
+        // Windows doesn't rename over an existing file
+        Err(_) if fs::stat(path).is_ok() => {
+            try!(fs::unlink(path));
+            fs::rename(&temp, path)
+        },
+        Err(e) => Err(e),
+    }
+}
+
+/// Load the program from the binary at `path` with `create`, or `link` it
+/// when there is no binary or it is rejected, and store the binary given by
+/// `get_binary` for the next time.
+fn load_or_link<P, E>(path: &Path, key: &BinaryKey,
+                      create: |&ProgramBinary| -> Option<P>,
+                      link: || -> Result<P, E>,
+                      get_binary: |&P| -> Option<ProgramBinary>)
+                      -> Result<P, E> {
+    match read_binary(path, key) {
+        Ok(binary) => match create(&binary) {
+            Some(prog) => return Ok(prog),
+            None => info!("Program binary {} rejected, linking again", path.display()),
+        },
+        Err(_) => (),
+    }
+    let prog = try!(link());
+    match get_binary(&prog) {
+        Some(binary) => match write_binary(path, key, &binary) {
+            Ok(()) => (),
+            Err(e) => warn!("Unable to write program binary {}: {}", path.display(), e),
+        },
+        None => (),
+    }
+    Ok(prog)
+}
+
+impl ProgramCache {
+    /// Create a cache storing the binaries in `dir`. The `driver` string has
+    /// to change whenever the binaries may become incompatible, such as the
+    /// one returned by `gl_device::Info::get_driver_id`.
+    pub fn new(dir: Path, driver: String) -> ProgramCache {
+        ProgramCache {
+            dir: dir,
+            driver: driver,
+        }
+    }
+
+    fn get_key(&self, vs_src: &ShaderSource, fs_src: &ShaderSource) -> BinaryKey {
+        BinaryKey {
+            driver: self.driver.clone(),
+            source: hash(&(vs_src, fs_src)),
+        }
+    }
+
+    fn get_path(&self, key: &BinaryKey) -> Path {
+        let name = hash(&(key.driver.as_slice(), key.source));
+        self.dir.join(format!("{:016x}.bin", name))
+    }
+
+    /// Load the program from the cache, or link it from the given shaders
+    /// and store its binary for the next time.
+    pub fn link_program<C: CommandBuffer, D: device::Device<C>>(&self, device: &mut D,
+                        vs_src: ShaderSource, fs_src: ShaderSource)
+                        -> Result<device::ProgramHandle, ProgramError> {
+        let key = self.get_key(&vs_src, &fs_src);
+        let path = self.get_path(&key);
+        let device = RefCell::new(device);
+        load_or_link(&path, &key,
+            |binary| device.borrow_mut().create_program_binary(binary).ok(),
+            || device.borrow_mut().link_program(vs_src, fs_src),
+            |prog| device.borrow_mut().get_program_binary(prog))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{File, TempDir};
+    use std::io::fs;
+    use device::shade::ProgramBinary;
+    use super::{BinaryKey, read_binary, write_binary, load_or_link};
+
+    #[test]
+    fn test_binary_round_trip() {
+        let dir = TempDir::new("gfx_program_cache").unwrap();
+        let path = dir.path().join("programs").join("test.bin");
+        let key = BinaryKey {
+            driver: "driver 1.0".to_string(),
+            source: 0x0123456789abcdef,
+        };
+        let binary = ProgramBinary {
+            format: 7,
+            data: vec![1, 2, 3, 4, 5],
+        };
+        write_binary(&path, &key, &binary).unwrap();
+        assert_eq!(fs::readdir(&path.dir_path()).unwrap(), vec![path.clone()]);
+        assert_eq!(read_binary(&path, &key).unwrap(), binary);
+        // over the previous binary
+        let binary = ProgramBinary { format: 8, data: vec![6, 7] };
+        write_binary(&path, &key, &binary).unwrap();
+        assert_eq!(read_binary(&path, &key).unwrap(), binary);
+        let other_driver = BinaryKey { driver: "driver 1.1".to_string(), ..key };
+        assert!(read_binary(&path, &other_driver).is_err());
+        let other_source = BinaryKey { source: 0, driver: key.driver.clone() };
+        assert!(read_binary(&path, &other_source).is_err());
+    }
+
+    #[test]
+    fn test_link_fallback() {
+        let dir = TempDir::new("gfx_program_cache").unwrap();
+        let path = dir.path().join("test.bin");
+        let key = BinaryKey {
+            driver: "driver".to_string(),
+            source: 1,
+        };
+        let binary = ProgramBinary {
+            format: 7,
+            data: vec![1, 2, 3],
+        };
+        // a corrupt header claiming a huge driver string
+        File::create(&path).write(&[0xff, 0xff, 0xff, 0xff, 0]).unwrap();
+        let mut links = 0u;
+        let prog: Result<uint, ()> = load_or_link(&path, &key,
+            |_| panic!("A corrupt binary is loaded"),
+            || { links += 1; Ok(1) },
+            |_| Some(binary.clone()));
+        assert_eq!((prog, links), (Ok(1), 1));
+        assert_eq!(read_binary(&path, &key).unwrap(), binary);
+        // the driver rejects the binary
+        let prog: Result<uint, ()> = load_or_link(&path, &key,
+            |_| None,
+            || { links += 1; Ok(2) },
+            |_| None);
+        assert_eq!((prog, links), (Ok(2), 2));
+        // the binary is used
+        let prog: Result<uint, ()> = load_or_link(&path, &key,
+            |b| { assert_eq!(*b, binary); Some(3) },
+            || panic!("The program is linked again"),
+            |_| None);
+        assert_eq!(prog, Ok(3));
+    }
+}
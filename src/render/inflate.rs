@@ -0,0 +1,314 @@
+// Copyright 2014 The Gfx-rs Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decompression of zlib streams (RFC 1950 and 1951), as found in PNG images.
+
+static LENGTH_BASE: [u16, ..29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+static LENGTH_EXTRA: [u8, ..29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+static DISTANCE_BASE: [u16, ..30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+static DISTANCE_EXTRA: [u8, ..30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+/// The order in which the lengths of the code length code are stored.
+static CODE_LENGTH_ORDER: [uint, ..19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Reads the bits of a deflate stream, starting from the least significant.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: uint,
+    bit: uint,
+}
+
+impl<'a> BitReader<'a> {
+    fn bits(&mut self, count: uint) -> Result<uint, ()> {
+        let mut value = 0u;
+        for i in range(0, count) {
+            if self.pos >= self.data.len() {
+                return Err(())
+            }
+            value |= ((self.data[self.pos] >> self.bit) as uint & 1) << i;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.pos += 1;
+            }
+        }
+        Ok(value)
+    }
+
+    /// Skip to the next byte boundary, and return the byte offset.
+    fn align(&mut self) -> uint {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+        self.pos
+    }
+}
+
+/// A canonical Huffman code, described by the number of codes of each
+/// length and the symbols ordered by their code.
+struct Huffman {
+    counts: [u16, ..16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, ()> {
+        let mut counts = [0u16, ..16];
+        for &length in lengths.iter() {
+            counts[length as uint] += 1;
+        }
+        counts[0] = 0;
+        // an incomplete code is allowed, but not an over-subscribed one
+        let mut left = 1i;
+        for length in range(1u, 16) {
+            left = (left << 1) - counts[length] as int;
+            if left < 0 {
+                return Err(())
+            }
+        }
+        let mut offsets = [0u16, ..16];
+        for length in range(1u, 15) {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = Vec::from_elem(lengths.len(), 0u16);
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as uint] as uint] = symbol as u16;
+                offsets[length as uint] += 1;
+            }
+        }
+        Ok(Huffman {
+            counts: counts,
+            symbols: symbols,
+        })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<uint, ()> {
+        // codes of each length follow the ones of the previous length
+        let (mut code, mut first, mut index) = (0i, 0i, 0i);
+        for length in range(1u, 16) {
+            code |= try!(reader.bits(1)) as int;
+            let count = self.counts[length] as int;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as uint] as uint)
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(())
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8, ..288];
+    for (i, length) in lengths.iter_mut().enumerate() {
+        *length = match i {
+            0...143 => 8,
+            144...255 => 9,
+            256...279 => 7,
+            _ => 8,
+        };
+    }
+    (Huffman::new(lengths.as_slice()).unwrap(), Huffman::new(&[5u8, ..30]).unwrap())
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), ()> {
+    let literals = try!(reader.bits(5)) + 257;
+    let distances = try!(reader.bits(5)) + 1;
+    let code_lengths = try!(reader.bits(4)) + 4;
+    if literals > 286 || distances > 30 {
+        return Err(())
+    }
+    let mut lengths = [0u8, ..19];
+    for &i in CODE_LENGTH_ORDER.slice_to(code_lengths).iter() {
+        lengths[i] = try!(reader.bits(3)) as u8;
+    }
+    let code_length_code = try!(Huffman::new(lengths.as_slice()));
+
+    let mut lengths = Vec::with_capacity(literals + distances);
+    while lengths.len() < literals + distances {
+        let (length, repeat) = match try!(code_length_code.decode(reader)) {
+            symbol @ 0...15 => (symbol as u8, 1),
+            16 => match lengths.last() {
+                Some(&previous) => (previous, 3 + try!(reader.bits(2))),
+                None => return Err(()),
+            },
+            17 => (0, 3 + try!(reader.bits(3))),
+            _ => (0, 11 + try!(reader.bits(7))),
+        };
+        if lengths.len() + repeat > literals + distances {
+            return Err(())
+        }
+        lengths.grow(repeat, length);
+    }
+    if lengths[256] == 0 {
+        // there has to be a code for the end of the block
+        return Err(())
+    }
+    let literal_code = try!(Huffman::new(lengths.slice_to(literals)));
+    let distance_code = try!(Huffman::new(lengths.slice_from(literals)));
+    Ok((literal_code, distance_code))
+}
+
+fn inflate_codes(reader: &mut BitReader, out: &mut Vec<u8>,
+                 literal_code: &Huffman, distance_code: &Huffman) -> Result<(), ()> {
+    loop {
+        let symbol = try!(literal_code.decode(reader));
+        if symbol < 256 {
+            out.push(symbol as u8);
+            continue
+        }
+        if symbol == 256 {
+            return Ok(())
+        }
+        let symbol = symbol - 257;
+        if symbol >= LENGTH_BASE.len() {
+            return Err(())
+        }
+        let length = LENGTH_BASE[symbol] as uint +
+            try!(reader.bits(LENGTH_EXTRA[symbol] as uint));
+        let symbol = try!(distance_code.decode(reader));
+        if symbol >= DISTANCE_BASE.len() {
+            return Err(())
+        }
+        let distance = DISTANCE_BASE[symbol] as uint +
+            try!(reader.bits(DISTANCE_EXTRA[symbol] as uint));
+        if distance > out.len() {
+            return Err(())
+        }
+        // the copy may overlap with the bytes it produces
+        for _ in range(0, length) {
+            let byte = out[out.len() - distance];
+            out.push(byte);
+        }
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &x in data.iter() {
+        a = (a + x as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn inflate(data: &[u8]) -> Result<Vec<u8>, ()> {
+    if data.len() < 6 {
+        return Err(())
+    }
+    let (cmf, flags) = (data[0] as uint, data[1] as uint);
+    // only deflate without a preset dictionary is used by PNG
+    if cmf & 0x0F != 8 || cmf >> 4 > 7 || (cmf << 8 | flags) % 31 != 0 || flags & 0x20 != 0 {
+        return Err(())
+    }
+
+    let mut reader = BitReader {
+        data: data,
+        pos: 2,
+        bit: 0,
+    };
+    let mut out = Vec::new();
+    loop {
+        let last = try!(reader.bits(1)) == 1;
+        match try!(reader.bits(2)) {
+            0 => {
+                let pos = reader.align();
+                if pos + 4 > data.len() {
+                    return Err(())
+                }
+                let length = data[pos] as uint | data[pos + 1] as uint << 8;
+                let complement = data[pos + 2] as uint | data[pos + 3] as uint << 8;
+                if length != !complement & 0xFFFF || pos + 4 + length > data.len() {
+                    return Err(())
+                }
+                out.push_all(data.slice(pos + 4, pos + 4 + length));
+                reader.pos = pos + 4 + length;
+            },
+            1 => {
+                let (literal_code, distance_code) = fixed_codes();
+                try!(inflate_codes(&mut reader, &mut out, &literal_code, &distance_code));
+            },
+            2 => {
+                let (literal_code, distance_code) = try!(dynamic_codes(&mut reader));
+                try!(inflate_codes(&mut reader, &mut out, &literal_code, &distance_code));
+            },
+            _ => return Err(()),
+        }
+        if last {
+            break
+        }
+    }
+
+    let pos = reader.align();
+    if pos + 4 > data.len() {
+        return Err(())
+    }
+    let checksum = data.slice(pos, pos + 4).iter().fold(0u32, |sum, &x| sum << 8 | x as u32);
+    if checksum != adler32(out.as_slice()) {
+        return Err(())
+    }
+    Ok(out)
+}
+
+/// Decompress a zlib stream, returning `None` if it is corrupt or truncated.
+pub fn inflate_zlib(data: &[u8]) -> Option<Vec<u8>> {
+    inflate(data).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::inflate_zlib;
+
+    #[test]
+    fn test_stored() {
+        let data = [0x78u8, 0x01, 0x01, 3, 0, 0xFC, 0xFF, 1, 2, 3, 0, 0x0D, 0, 0x07];
+        assert_eq!(inflate_zlib(&data), Some(vec![1u8, 2, 3]));
+        assert_eq!(inflate_zlib(data.slice_to(10)), None);
+    }
+
+    #[test]
+    fn test_fixed() {
+        let data = [0x78u8, 0xDA, 0xCB, 0x48, 0xCD, 0xC9, 0xC9, 0x57, 0xC8, 0x40, 0x27, 0x01,
+                    0x68, 0x03, 0x08, 0xB1];
+        assert_eq!(inflate_zlib(&data), Some(b"hello hello hello hello".to_vec()));
+        // the checksum doesn't match
+        let mut corrupt = data.to_vec();
+        corrupt[15] = 0;
+        assert_eq!(inflate_zlib(corrupt.as_slice()), None);
+    }
+
+    #[test]
+    fn test_dynamic() {
+        let data = [0x78u8, 0xDA, 0x05, 0xC1, 0x01, 0x01, 0x00, 0x00, 0x00, 0x82, 0xA0, 0xAD,
+                    0x6A, 0xFF, 0x37, 0x04, 0x02, 0x23, 0x24, 0x17, 0x0D, 0x3D, 0x50, 0x6E,
+                    0x07, 0xAB];
+        assert_eq!(inflate_zlib(&data), Some(b"baaadacabacbdcacdabb".to_vec()));
+    }
+}
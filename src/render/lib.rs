@@ -25,25 +25,41 @@
 extern crate device;
 
 use std::mem;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUint, SeqCst};
 
 use device::attrib;
 use device::draw::CommandBuffer;
 use device::shade::{ProgramInfo, UniformValue, ShaderSource};
-use device::shade::{Vertex, Fragment, CreateShaderError};
+use device::shade::{Vertex, Fragment, CreateShaderError, ShaderLog};
 use device::target::{Rect, ClearData, Mask, Access, Draw, Read,
     Target, TargetColor, TargetDepth, TargetStencil};
 use batch::Batch;
 
 /// Batches
 pub mod batch;
+/// Program binary cache
+pub mod cache;
+/// Zlib decompression
+mod inflate;
 /// Meshes
 pub mod mesh;
+/// Mipmap generation
+pub mod mipmap;
+/// Shader preprocessing
+pub mod preprocess;
 /// Shaders
 pub mod shade;
+/// Preprocessed shader source
+pub mod source;
 /// Draw state
 pub mod state;
 /// Render targets
 pub mod target;
+/// Texture containers
+pub mod texture;
+/// Shader hot reloading
+pub mod watch;
 
 /// Program linking error
 #[deriving(Clone, PartialEq, Show)]
@@ -53,11 +69,30 @@ pub enum ProgramError {
     /// Unable to compile the fragment shader
     ErrorFragment(CreateShaderError),
     /// Unable to link
-    ErrorLink(()),
+    ErrorLink(ShaderLog),
 }
 
-const TRACKED_ATTRIBUTES: uint = 8;
 type CachedAttribute = (device::RawBufferHandle, attrib::Format);
+type CachedTexture = (device::back::Texture, Option<device::back::Sampler>);
+
+/// The values last uploaded to the uniforms of a program, which keeps them
+/// across program switches.
+struct ProgramState {
+    name: device::back::Program,
+    uniforms: Vec<Option<UniformValue>>,
+    texture_units: Vec<Option<UniformValue>>,
+    image_units: Vec<Option<UniformValue>>,
+}
+
+/// Check if the value is already in the cache, storing it otherwise.
+fn is_cached<T: Clone + PartialEq>(cache: &mut Vec<Option<T>>, index: uint, value: T) -> bool {
+    match cache.as_slice().get(index) {
+        Some(&Some(ref v)) if *v == value => return true,
+        _ => (),
+    }
+    shade::set_value(cache, index, Some(value));
+    false
+}
 
 /// The internal state of the renderer. This is used as a cache to eliminate
 /// redundant state changes.
@@ -67,29 +102,65 @@ struct RenderState {
     is_array_buffer_set: bool,
     program_name: device::back::Program,
     index: Option<device::RawBufferHandle>,
-    attributes: [Option<CachedAttribute>, .. TRACKED_ATTRIBUTES],
+    attributes: Vec<Option<CachedAttribute>>,
     draw: state::DrawState,
+    programs: Vec<ProgramState>,
+    textures: Vec<Option<CachedTexture>>,
+    deletions: uint,
 }
 
 impl RenderState {
-    /// Generate the initial state matching `Device::reset_state`
-    fn new() -> RenderState {
+    /// Generate the initial state matching `Device::reset_state`, with
+    /// room for the given number of vertex attributes
+    fn new(max_attributes: uint) -> RenderState {
         RenderState {
             is_frame_buffer_set: false,
             frame: target::Frame::new(0,0),
             is_array_buffer_set: false,
             program_name: 0,
             index: None,
-            attributes: [None, ..TRACKED_ATTRIBUTES],
+            attributes: Vec::from_elem(max_attributes, None),
             draw: state::DrawState::new(),
+            programs: Vec::new(),
+            textures: Vec::new(),
+            deletions: 0,
+        }
+    }
+
+    /// Drop the state of the programs and textures when some of them have
+    /// been deleted since, as their names can be reused
+    fn check_deletions(&mut self, deletions: uint) {
+        if self.deletions != deletions {
+            self.program_name = 0;
+            self.programs.clear();
+            self.textures.clear();
+            self.deletions = deletions;
+        }
+    }
+
+    /// Get the index of the program state, adding a new one if needed
+    fn find_program(&mut self, name: device::back::Program) -> uint {
+        match self.programs.iter().position(|p| p.name == name) {
+            Some(i) => i,
+            None => {
+                self.programs.push(ProgramState {
+                    name: name,
+                    uniforms: Vec::new(),
+                    texture_units: Vec::new(),
+                    image_units: Vec::new(),
+                });
+                self.programs.len() - 1
+            },
         }
     }
 }
 
 struct ParamStorage {
-    uniforms: Vec<UniformValue>,
-    blocks  : Vec<device::RawBufferHandle>,
-    textures: Vec<shade::TextureParam>,
+    uniforms: Vec<Option<UniformValue>>,
+    blocks  : Vec<Option<device::RawBufferHandle>>,
+    textures: Vec<Option<shade::TextureParam>>,
+    images  : Vec<Option<shade::ImageParam>>,
+    storage_blocks: Vec<Option<shade::StorageBufferParam>>,
 }
 
 impl ParamStorage{
@@ -98,6 +169,8 @@ impl ParamStorage{
             uniforms: Vec::new(),
             blocks: Vec::new(),
             textures: Vec::new(),
+            images: Vec::new(),
+            storage_blocks: Vec::new(),
         }
     }
 
@@ -105,10 +178,14 @@ impl ParamStorage{
         self.uniforms.truncate(0);
         self.blocks.truncate(0);
         self.textures.truncate(0);
+        self.images.truncate(0);
+        self.storage_blocks.truncate(0);
         shade::ParamValues {
             uniforms: &mut self.uniforms,
             blocks: &mut self.blocks,
             textures: &mut self.textures,
+            images: &mut self.images,
+            storage_blocks: &mut self.storage_blocks,
         }
     }
 }
@@ -128,7 +205,8 @@ impl<C: CommandBuffer> CommandBufferHelper for C {
             Some(&target::PlaneSurface(ref suf)) =>
                 self.bind_target_surface(access, to, suf.get_name()),
             Some(&target::PlaneTexture(ref tex, level, layer)) =>
-                self.bind_target_texture(access, to, tex.get_name(), level, layer),
+                self.bind_target_texture(access, to, tex.get_name(),
+                                         tex.get_info().kind, level, layer),
         }
     }
 }
@@ -143,6 +221,8 @@ pub struct Renderer<C: CommandBuffer> {
     default_frame_buffer: device::FrameBufferHandle,
     render_state: RenderState,
     parameters: ParamStorage,
+    sampler_objects_supported: bool,
+    deletions: Arc<AtomicUint>,
 }
 
 impl<C: CommandBuffer> Renderer<C> {
@@ -150,7 +230,7 @@ impl<C: CommandBuffer> Renderer<C> {
     pub fn reset(&mut self) {
         self.command_buffer.clear();
         self.data_buffer.clear();
-        self.render_state = RenderState::new();
+        self.render_state = RenderState::new(self.render_state.attributes.len());
     }
 
     /// Get a command buffer to be submitted to the device.
@@ -167,8 +247,10 @@ impl<C: CommandBuffer> Renderer<C> {
             draw_frame_buffer: self.draw_frame_buffer,
             read_frame_buffer: self.read_frame_buffer,
             default_frame_buffer: self.default_frame_buffer,
-            render_state: RenderState::new(),
+            render_state: RenderState::new(self.render_state.attributes.len()),
             parameters: ParamStorage::new(),
+            sampler_objects_supported: self.sampler_objects_supported,
+            deletions: self.deletions.clone(),
         }
     }
 
@@ -224,6 +306,12 @@ impl<C: CommandBuffer> Renderer<C> {
         self.command_buffer.call_blit(source_rect, dest_rect, mask);
     }
 
+    /// Make the results of the shader writes to images and storage buffers,
+    /// issued by the previous draw calls, visible to the given accesses.
+    pub fn memory_barrier(&mut self, barrier: device::Barrier) {
+        self.command_buffer.memory_barrier(barrier);
+    }
+
     /// Update a buffer with data from a vector.
     pub fn update_buffer_vec<T: Copy>(&mut self, buf: device::BufferHandle<T>,
                              data: &[T], offset_elements: uint) {
@@ -248,6 +336,8 @@ impl<C: CommandBuffer> Renderer<C> {
         debug_assert!(tex.get_info().contains(&img));
         let pointer = self.data_buffer.add_vec(data);
         self.command_buffer.update_texture(tex.get_info().kind, tex.get_name(), img, pointer);
+        // the update binds the texture to the active unit
+        self.render_state.textures.clear();
     }
 
     fn bind_frame(&mut self, frame: &target::Frame) {
@@ -262,6 +352,10 @@ impl<C: CommandBuffer> Renderer<C> {
             self.render_state.frame.width = frame.width;
             self.render_state.frame.height = frame.height;
         }
+        if self.render_state.frame.srgb != frame.srgb {
+            self.command_buffer.set_frame_buffer_srgb(frame.srgb);
+            self.render_state.frame.srgb = frame.srgb;
+        }
         if frame.is_default() {
             if self.render_state.is_frame_buffer_set {
                 // binding the default FBO, not touching our common one
@@ -344,7 +438,7 @@ impl<C: CommandBuffer> Renderer<C> {
     }
 
     fn bind_program<B: Batch>(&mut self, batch: &B, program: &device::ProgramHandle) {
-        //Warning: this is not protected against deleted resources in single-threaded mode
+        self.render_state.check_deletions(self.deletions.load(SeqCst));
         if self.render_state.program_name != program.get_name() {
             self.command_buffer.bind_program(program.get_name());
             self.render_state.program_name = program.get_name();
@@ -357,22 +451,39 @@ impl<C: CommandBuffer> Renderer<C> {
         let info = program.get_info();
         if self.parameters.uniforms.len() != info.uniforms.len() ||
             self.parameters.blocks.len() != info.blocks.len() ||
-            self.parameters.textures.len() != info.textures.len() {
-            error!("Mismatching number of uniforms ({}), blocks ({}), or \
-                    textures ({}) in `upload_parameters` for program: {}",
+            self.parameters.textures.len() != info.textures.len() ||
+            self.parameters.images.len() != info.images.len() ||
+            self.parameters.storage_blocks.len() != info.storage_blocks.len() {
+            error!("Mismatching number of uniforms ({}), blocks ({}), textures ({}), \
+                    images ({}), or storage blocks ({}) in `upload_parameters` \
+                    for program: {}",
                     self.parameters.uniforms.len(),
                     self.parameters.blocks.len(),
                     self.parameters.textures.len(),
+                    self.parameters.images.len(),
+                    self.parameters.storage_blocks.len(),
                     info);
         }
+        let program_id = self.render_state.find_program(program.get_name());
+        let cache = self.render_state.programs.get_mut(program_id).unwrap();
         // bind uniforms
-        for (var, value) in info.uniforms.iter()
-            .zip(self.parameters.uniforms.iter()) {
-            self.command_buffer.bind_uniform(var.location, *value);
+        for (i, (var, value)) in info.uniforms.iter()
+            .zip(self.parameters.uniforms.iter()).enumerate() {
+            let value = match *value {
+                Some(v) => v,
+                None => continue,
+            };
+            if !is_cached(&mut cache.uniforms, i, value) {
+                self.command_buffer.bind_uniform(var.location, value);
+            }
         }
         // bind uniform blocks
         for (i, (_, buf)) in info.blocks.iter()
             .zip(self.parameters.blocks.iter()).enumerate() {
+            let buf = match *buf {
+                Some(ref buf) => buf,
+                None => continue,
+            };
             self.command_buffer.bind_uniform_block(
                 program.get_name(),
                 i as device::UniformBufferSlot,
@@ -381,14 +492,83 @@ impl<C: CommandBuffer> Renderer<C> {
             );
         }
         // bind textures and samplers
-        for (i, (var, &(tex, sampler))) in info.textures.iter()
+        for (i, (var, param)) in info.textures.iter()
             .zip(self.parameters.textures.iter()).enumerate() {
+            let (tex, sampler) = match *param {
+                Some(param) => param,
+                None => continue,
+            };
             if sampler.is_some() && tex.get_info().kind.get_aa_mode().is_some() {
                 error!("A sampler provided for an AA texture: {}", var.name.clone());
             }
-            self.command_buffer.bind_uniform(var.location, device::shade::ValueI32(i as i32));
-            self.command_buffer.bind_texture(i as device::TextureSlot,
-                tex.get_info().kind, tex.get_name(), sampler);
+            if tex.get_info().format.is_integer() == (var.base_type == device::shade::BaseF32) {
+                error!("Texture format {} does not match the sampler type of {}",
+                    tex.get_info().format, var.name.clone());
+            }
+            if var.sampler_type.is_shadow() {
+                let is_comparing = match sampler {
+                    Some(ref s) => s.get_info().comparison != device::tex::NoComparsion,
+                    None => false,
+                };
+                if !tex.get_info().format.has_depth() || !is_comparing {
+                    error!("A shadow sampler {} requires a depth texture with a comparison sampler",
+                        var.name.clone());
+                }
+            }
+            let unit = device::shade::ValueI32(i as i32);
+            if !is_cached(&mut cache.texture_units, i, unit) {
+                self.command_buffer.bind_uniform(var.location, unit);
+            }
+            let bound = (tex.get_name(), sampler.map(|s| s.get_name()));
+            // without sampler objects, the sampler state belongs to the texture
+            // and may have been changed by binding it with another sampler
+            let cached = if sampler.is_some() && !self.sampler_objects_supported {
+                shade::set_value(&mut self.render_state.textures, i, None);
+                false
+            } else {
+                is_cached(&mut self.render_state.textures, i, bound)
+            };
+            if !cached {
+                self.command_buffer.bind_texture(i as device::TextureSlot,
+                    tex.get_info().kind, tex.get_name(), sampler);
+            }
+        }
+        // bind images
+        for (i, (var, image)) in info.images.iter()
+            .zip(self.parameters.images.iter()).enumerate() {
+            let image = match *image {
+                Some(ref image) => image,
+                None => continue,
+            };
+            let tex_info = image.texture.get_info();
+            if image.format.get_size() != tex_info.format.get_size() {
+                error!("Image format {} does not match the size of the texture format {} for {}",
+                    image.format, tex_info.format, var.name.clone());
+            }
+            if image.format.is_integer() == (var.base_type == device::shade::BaseF32) {
+                error!("Image format {} does not match the image type of {}",
+                    image.format, var.name.clone());
+            }
+            let unit = device::shade::ValueI32(i as i32);
+            if !is_cached(&mut cache.image_units, i, unit) {
+                self.command_buffer.bind_uniform(var.location, unit);
+            }
+            self.command_buffer.bind_image(i as device::ImageSlot, image.texture.get_name(),
+                image.level, image.layer, image.access, image.format);
+        }
+        // bind storage blocks
+        for (i, (_, buf)) in info.storage_blocks.iter()
+            .zip(self.parameters.storage_blocks.iter()).enumerate() {
+            let buf = match *buf {
+                Some(ref buf) => buf,
+                None => continue,
+            };
+            self.command_buffer.bind_storage_block(
+                program.get_name(),
+                i as device::StorageBufferSlot,
+                i as device::StorageBlockIndex,
+                buf.get_name()
+            );
         }
     }
 
@@ -403,17 +583,14 @@ impl<C: CommandBuffer> Renderer<C> {
         for (attr_index, sat) in link.attribute_indices().zip(info.attributes.iter()) {
             let vat = &mesh.attributes[attr_index];
             let loc = sat.location as uint;
-            let need_update = loc >= self.render_state.attributes.len() ||
-                match self.render_state.attributes[loc] {
-                    Some((buf, fmt)) => buf != vat.buffer || fmt != vat.format,
-                    None => true,
-                };
-            if need_update {
+            // `link_mesh` rejects these, but other batches may not use it
+            if loc >= self.render_state.attributes.len() {
+                error!("Attribute location {} is not supported by the device", loc);
+                continue
+            }
+            if !is_cached(&mut self.render_state.attributes, loc, (vat.buffer, vat.format)) {
                 self.command_buffer.bind_attribute(loc as device::AttributeSlot,
                     vat.buffer.get_name(), vat.format);
-                if loc < self.render_state.attributes.len() {
-                    self.render_state.attributes[loc] = Some((vat.buffer, vat.format));
-                }
             }
         }
     }
@@ -458,10 +635,19 @@ pub trait DeviceHelper<C: CommandBuffer> {
     /// Create a simple program given a vertex shader with a fragment one.
     fn link_program(&mut self, vs_src: ShaderSource, fs_src: ShaderSource)
                     -> Result<device::ProgramHandle, ProgramError>;
+    /// Create a texture and upload all the images of the given data into it.
+    /// Mipmap levels that are described but have no images are generated.
+    /// Convenience function around `create_texture` and `update_texture`.
+    fn create_texture_with_data(&mut self, data: &texture::TextureData)
+                                -> Result<device::TextureHandle, device::tex::TextureError>;
 }
 
 impl<D: device::Device<C>, C: CommandBuffer> DeviceHelper<C> for D {
     fn create_renderer(&mut self) -> Renderer<C> {
+        let (max_attributes, sampler_objects_supported) = {
+            let caps = self.get_capabilities();
+            (caps.max_vertex_attributes, caps.sampler_objects_supported)
+        };
         Renderer {
             command_buffer: CommandBuffer::new(),
             data_buffer: device::draw::DataBuffer::new(),
@@ -469,8 +655,10 @@ impl<D: device::Device<C>, C: CommandBuffer> DeviceHelper<C> for D {
             draw_frame_buffer: self.create_frame_buffer(),
             read_frame_buffer: self.create_frame_buffer(),
             default_frame_buffer: device::get_main_frame_buffer(),
-            render_state: RenderState::new(),
+            render_state: RenderState::new(max_attributes),
             parameters: ParamStorage::new(),
+            sampler_objects_supported: sampler_objects_supported,
+            deletions: self.get_deletions(),
         }
     }
 
@@ -497,4 +685,43 @@ impl<D: device::Device<C>, C: CommandBuffer> DeviceHelper<C> for D {
         };
         self.create_program([vs, fs]).map_err(|e| ErrorLink(e))
     }
+
+    fn create_texture_with_data(&mut self, data: &texture::TextureData)
+                                -> Result<device::TextureHandle, device::tex::TextureError> {
+        let tex = try!(self.create_texture(data.info));
+        for image in data.images.iter() {
+            match self.update_texture_raw(&tex, &image.info, image.data.as_slice()) {
+                Ok(()) => (),
+                Err(e) => {
+                    self.delete_texture(tex);
+                    return Err(e)
+                },
+            }
+        }
+        if data.get_provided_levels() < data.info.levels {
+            self.generate_mipmap(&tex);
+        }
+        Ok(tex)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use device::shade::ValueF32;
+    use super::{RenderState, is_cached};
+
+    #[test]
+    fn test_check_deletions() {
+        let mut state = RenderState::new(4);
+        state.program_name = 3;
+        let id = state.find_program(3);
+        assert!(!is_cached(&mut state.programs.get_mut(id).unwrap().uniforms, 0, ValueF32(1.0)));
+        assert!(is_cached(&mut state.programs.get_mut(id).unwrap().uniforms, 0, ValueF32(1.0)));
+        state.textures.push(Some((5, None)));
+        state.check_deletions(0);
+        assert_eq!((state.program_name, state.programs.len(), state.textures.len()), (3, 1, 1));
+        // a program or texture has been deleted, its name may be reused
+        state.check_deletions(1);
+        assert_eq!((state.program_name, state.programs.len(), state.textures.len()), (0, 0, 0));
+    }
 }
@@ -20,6 +20,7 @@
 //! create a mesh is to use the `#[vertex_format]` attribute on a struct, upload them into a
 //! `Buffer`, and then use `Mesh::from`.
 
+use std::slice;
 use device;
 use device::{PrimitiveType, BufferHandle, VertexCount};
 use device::attrib;
@@ -188,44 +189,38 @@ impl ToSlice for BufferHandle<u32> {
 pub enum LinkError {
     /// An attribute index is out of supported bounds
     ErrorMeshAttribute(uint),
-    /// An input index is out of supported bounds
+    /// A shader input location exceeds the attribute slots of the device
     ErrorShaderInput(uint),
 }
 
-const BITS_PER_ATTRIBUTE: uint = 4;
-const MAX_SHADER_INPUTS: uint = 64 / BITS_PER_ATTRIBUTE;
-const MESH_ATTRIBUTE_MASK: uint = (1u << BITS_PER_ATTRIBUTE) - 1;
+type AttributeIndex = u8;
+const MAX_MESH_ATTRIBUTES: uint = ::std::u8::MAX as uint + 1;
 
 /// An iterator over mesh attributes.
-pub struct AttributeIndices {
-    value: u64,
+pub struct AttributeIndices<'a> {
+    iter: slice::Items<'a, AttributeIndex>,
 }
 
-impl Iterator<uint> for AttributeIndices {
+impl<'a> Iterator<uint> for AttributeIndices<'a> {
     fn next(&mut self) -> Option<uint> {
-        let id = (self.value as uint) & MESH_ATTRIBUTE_MASK;
-        self.value >>= BITS_PER_ATTRIBUTE;
-        Some(id)
+        self.iter.next().map(|&id| id as uint)
     }
 }
 
 /// Holds a remapping table from shader inputs to mesh attributes.
 pub struct Link {
-    table: u64,
+    table: Vec<AttributeIndex>,
 }
 
 impl Link {
     /// Construct a new link from an iterator over attribute indices.
     pub fn from_iter<I: Iterator<uint>>(iter: I) -> Result<Link, LinkError> {
-        let mut table = 0u64;
-        for (input, attrib) in iter.enumerate() {
-            if input >= MAX_SHADER_INPUTS {
-                return Err(ErrorShaderInput(input))
-            } else if attrib > MESH_ATTRIBUTE_MASK {
+        let mut table = Vec::new();
+        for attrib in iter {
+            if attrib >= MAX_MESH_ATTRIBUTES {
                 return Err(ErrorMeshAttribute(attrib))
-            } else {
-                table |= attrib as u64 << (input * BITS_PER_ATTRIBUTE);
             }
+            table.push(attrib as AttributeIndex);
         }
         Ok(Link {
             table: table,
@@ -235,7 +230,23 @@ impl Link {
     /// Convert to an iterator returning attribute indices
     pub fn attribute_indices(&self) -> AttributeIndices {
         AttributeIndices {
-            value: self.table,
+            iter: self.table.iter(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Link, ErrorMeshAttribute};
+
+    #[test]
+    fn test_link() {
+        let indices = Vec::from_fn(40, |i| (i * 7) % 40);
+        let link = Link::from_iter(indices.clone().into_iter()).unwrap();
+        assert_eq!(link.attribute_indices().collect::<Vec<uint>>(), indices);
+        match Link::from_iter(vec![0u, 300].into_iter()) {
+            Err(ErrorMeshAttribute(300)) => (),
+            _ => panic!("The attribute index should be out of bounds"),
         }
     }
 }
@@ -0,0 +1,389 @@
+// Copyright 2014 The Gfx-rs Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! CPU mipmap generation. Unlike `Device::generate_mipmap`, the result does
+//! not depend on the driver, and is ready to be uploaded with
+//! `update_texture`.
+
+use std::cmp::{max, min};
+use std::f32::consts::PI;
+use std::mem;
+
+use device::attrib::{F16, F32, IntNormalized};
+use device::tex;
+use texture::{Image, TextureData};
+
+/// The filter used to compute each texel of a mipmap level.
+#[deriving(Clone, PartialEq, Show)]
+pub enum Filter {
+    /// Average of the covered texels.
+    BoxFilter,
+    /// Kaiser-windowed sinc, which keeps more detail than the box filter.
+    KaiserFilter,
+}
+
+/// An error occurring while generating mipmaps.
+#[deriving(Clone, PartialEq, Show)]
+pub enum MipmapError {
+    /// The texel format can not be filtered on the CPU.
+    UnsupportedFormat(tex::Format),
+    /// The data provided for an image has a different size than expected.
+    IncorrectSize(uint),
+}
+
+/// How the components of a texel are stored.
+#[deriving(Clone, PartialEq, Show)]
+enum Encoding {
+    Unorm8,
+    Unorm16,
+    Half,
+    Single,
+}
+
+impl Encoding {
+    fn get_size(&self) -> uint {
+        match *self {
+            Unorm8 => 1,
+            Unorm16 | Half => 2,
+            Single => 4,
+        }
+    }
+}
+
+fn get_encoding(format: tex::Format) -> Result<(uint, Encoding, bool), MipmapError> {
+    fn count(c: tex::Components) -> uint {
+        match c {
+            tex::R => 1,
+            tex::RG => 2,
+            tex::RGB => 3,
+            tex::RGBA => 4,
+        }
+    }
+    match format {
+        tex::Unsigned(c, 8, IntNormalized) => Ok((count(c), Unorm8, false)),
+        tex::Unsigned(c, 16, IntNormalized) => Ok((count(c), Unorm16, false)),
+        tex::Float(c, F16) => Ok((count(c), Half, false)),
+        tex::Float(c, F32) => Ok((count(c), Single, false)),
+        tex::SRGB8 => Ok((3, Unorm8, true)),
+        tex::SRGB8_A8 => Ok((4, Unorm8, true)),
+        f => Err(UnsupportedFormat(f)),
+    }
+}
+
+fn half_to_f32(h: u16) -> f32 {
+    let sign = if h & 0x8000 != 0 { -1.0f32 } else { 1.0 };
+    let exp = ((h >> 10) & 0x1F) as i32;
+    let mant = (h & 0x3FF) as f32;
+    sign * match exp {
+        0 => mant * 2.0f32.powi(-24),
+        0x1F if mant == 0.0 => ::std::f32::INFINITY,
+        0x1F => ::std::f32::NAN,
+        _ => (1.0 + mant / 1024.0) * 2.0f32.powi(exp - 15),
+    }
+}
+
+fn f32_to_half(v: f32) -> u16 {
+    if v.is_nan() {
+        return 0x7E00
+    }
+    let sign = if v.is_sign_negative() { 0x8000u16 } else { 0 };
+    let a = v.abs();
+    if a >= 65520.0 {
+        sign | 0x7C00
+    } else if a < 2.0f32.powi(-14) {
+        sign | (a / 2.0f32.powi(-24)).round() as u16
+    } else {
+        let mut exp = a.log2().floor() as i32;
+        let mut mant = ((a / 2.0f32.powi(exp) - 1.0) * 1024.0).round() as u16;
+        if mant >= 1024 {
+            exp += 1;
+            mant = 0;
+        }
+        sign | ((exp + 15) as u16) << 10 | mant
+    }
+}
+
+fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn decode(data: &[u8], encoding: Encoding) -> Vec<f32> {
+    data.chunks(encoding.get_size()).map(|b| match encoding {
+        Unorm8 => b[0] as f32 / 255.0,
+        Unorm16 => {
+            let v: u16 = unsafe { mem::transmute([b[0], b[1]]) };
+            v as f32 / 65535.0
+        },
+        Half => half_to_f32(unsafe { mem::transmute([b[0], b[1]]) }),
+        Single => unsafe { mem::transmute([b[0], b[1], b[2], b[3]]) },
+    }).collect()
+}
+
+fn encode(values: &[f32], encoding: Encoding) -> Vec<u8> {
+    let mut data = Vec::with_capacity(values.len() * encoding.get_size());
+    for &v in values.iter() {
+        let clamped = v.max(0.0).min(1.0);
+        match encoding {
+            Unorm8 => data.push((clamped * 255.0 + 0.5) as u8),
+            Unorm16 => {
+                let b: [u8, ..2] = unsafe { mem::transmute((clamped * 65535.0 + 0.5) as u16) };
+                data.push_all(b.as_slice());
+            },
+            Half => {
+                let b: [u8, ..2] = unsafe { mem::transmute(f32_to_half(v)) };
+                data.push_all(b.as_slice());
+            },
+            Single => {
+                let b: [u8, ..4] = unsafe { mem::transmute(v) };
+                data.push_all(b.as_slice());
+            },
+        }
+    }
+    data
+}
+
+/// Zeroth order modified Bessel function of the first kind.
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0f32;
+    let mut term = 1.0f32;
+    let mut k = 1.0f32;
+    while term > sum * 1e-6 {
+        term *= (x / (2.0 * k)) * (x / (2.0 * k));
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+impl Filter {
+    /// Radius of the filter, in destination texels.
+    fn get_radius(&self) -> f32 {
+        match *self {
+            BoxFilter => 0.5,
+            KaiserFilter => 3.0,
+        }
+    }
+
+    fn evaluate(&self, x: f32) -> f32 {
+        match *self {
+            BoxFilter => if x.abs() <= 0.5 { 1.0 } else { 0.0 },
+            KaiserFilter => {
+                static ALPHA: f32 = 4.0;
+                let t = x / self.get_radius();
+                if t.abs() >= 1.0 {
+                    return 0.0
+                }
+                let sinc = if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+                sinc * bessel_i0(ALPHA * (1.0 - t * t).sqrt()) / bessel_i0(ALPHA)
+            },
+        }
+    }
+
+    /// Compute the normalized source texel weights for each destination texel.
+    fn get_weights(&self, src: uint, dst: uint) -> Vec<Vec<(uint, f32)>> {
+        let scale = src as f32 / dst as f32;
+        let radius = self.get_radius() * scale;
+        range(0, dst).map(|i| {
+            let center = (i as f32 + 0.5) * scale;
+            let first = (center - radius).floor() as int;
+            let last = (center + radius).ceil() as int;
+            let mut taps: Vec<(uint, f32)> = Vec::new();
+            for j in range(first, last + 1) {
+                let w = self.evaluate((j as f32 + 0.5 - center) / scale);
+                if w != 0.0 {
+                    // clamp to the edge
+                    let index = min(max(j, 0), src as int - 1) as uint;
+                    taps.push((index, w));
+                }
+            }
+            let total = taps.iter().fold(0.0, |s, &(_, w)| s + w);
+            taps.into_iter().map(|(j, w)| (j, w / total)).collect()
+        }).collect()
+    }
+}
+
+/// Filter `buf` along one axis, reducing its size on that axis to `size`.
+fn resample(buf: &[f32], dims: [uint, ..3], channels: uint, axis: uint,
+            size: uint, filter: Filter) -> Vec<f32> {
+    let mut out_dims = dims;
+    out_dims[axis] = size;
+    let strides = [channels, dims[0] * channels, dims[0] * dims[1] * channels];
+    let weights = filter.get_weights(dims[axis], size);
+    let mut out = Vec::from_elem(out_dims[0] * out_dims[1] * out_dims[2] * channels, 0.0f32);
+    let mut dst = 0u;
+    for z in range(0, out_dims[2]) {
+        for y in range(0, out_dims[1]) {
+            for x in range(0, out_dims[0]) {
+                let pos = [x, y, z];
+                let base = x * strides[0] + y * strides[1] + z * strides[2] -
+                           pos[axis] * strides[axis];
+                for &(j, w) in weights[pos[axis]].iter() {
+                    let src = base + j * strides[axis];
+                    for c in range(0, channels) {
+                        out[dst + c] += buf[src + c] * w;
+                    }
+                }
+                dst += channels;
+            }
+        }
+    }
+    out
+}
+
+/// Compute the next mipmap level of a single image of a texture of `kind`.
+/// Only the axes holding texels are reduced, the layers of 1D and 2D arrays
+/// (stored in the height and depth respectively) are filtered separately.
+pub fn downsample(img: &tex::ImageInfo, data: &[u8], kind: tex::TextureKind, filter: Filter,
+                  gamma_correct: bool) -> Result<(tex::ImageInfo, Vec<u8>), MipmapError> {
+    let (channels, encoding, srgb) = try!(get_encoding(img.format));
+    let expected = img.get_data_size();
+    if data.len() != expected {
+        return Err(IncorrectSize(expected))
+    }
+    // alpha is never gamma-corrected
+    let color_channels = if channels == 4 { 3 } else { channels };
+    let linearize = srgb || gamma_correct;
+
+    let mut values = decode(data, encoding);
+    if linearize {
+        for texel in values.as_mut_slice().chunks_mut(channels) {
+            for v in texel.slice_to_mut(color_channels).iter_mut() {
+                *v = srgb_to_linear(*v);
+            }
+        }
+    }
+
+    let mut dims = [img.width as uint, img.height as uint, img.depth as uint];
+    let axes = match kind {
+        tex::Texture1D | tex::Texture1DArray => 1,
+        tex::Texture3D => 3,
+        _ => 2,
+    };
+    for axis in range(0, axes) {
+        if dims[axis] > 1 {
+            let size = dims[axis] / 2;
+            values = resample(values.as_slice(), dims, channels, axis, size, filter);
+            dims[axis] = size;
+        }
+    }
+
+    if linearize {
+        for texel in values.as_mut_slice().chunks_mut(channels) {
+            for v in texel.slice_to_mut(color_channels).iter_mut() {
+                *v = linear_to_srgb(*v);
+            }
+        }
+    }
+
+    let info = tex::ImageInfo {
+        width: dims[0] as u16,
+        height: dims[1] as u16,
+        depth: dims[2] as u16,
+        mipmap: img.mipmap + 1,
+        ..*img
+    };
+    Ok((info, encode(values.as_slice(), encoding)))
+}
+
+/// Generate all the mipmap levels described by `data.info` from the images
+/// of the first level. Images of other levels are replaced.
+pub fn generate(data: &mut TextureData, filter: Filter, gamma_correct: bool)
+                -> Result<(), MipmapError> {
+    let levels = min(data.info.levels, data.info.get_max_levels());
+    data.info.levels = levels;
+    data.images.retain(|im| im.info.mipmap == 0);
+
+    let mut generated = Vec::new();
+    for base in data.images.iter() {
+        let mut img = base.info;
+        let mut bytes = base.data.clone();
+        for _ in range(1, levels) {
+            let (next, next_bytes) = try!(downsample(&img, bytes.as_slice(),
+                                                     data.info.kind, filter, gamma_correct));
+            generated.push(Image {
+                info: next,
+                data: next_bytes.clone(),
+            });
+            img = next;
+            bytes = next_bytes;
+        }
+    }
+    data.images.push_all(generated.as_slice());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use device::tex;
+    use super::{downsample, BoxFilter, KaiserFilter};
+
+    #[test]
+    fn test_box_gamma() {
+        let img = tex::ImageInfo {
+            width: 2,
+            height: 2,
+            ..tex::ImageInfo::new()
+        };
+        let data = [0u8, 0, 0, 0, 255, 255, 255, 255,
+                    0, 0, 0, 0, 255, 255, 255, 255];
+        let (info, linear) = downsample(&img, data.as_slice(), tex::Texture2D,
+                                        BoxFilter, false).unwrap();
+        assert_eq!((info.width, info.height, info.mipmap), (1, 1, 1));
+        assert_eq!(linear, vec![128, 128, 128, 128]);
+        let (_, gamma) = downsample(&img, data.as_slice(), tex::Texture2D,
+                                    BoxFilter, true).unwrap();
+        assert_eq!(gamma, vec![188, 188, 188, 128]);
+    }
+
+    #[test]
+    fn test_kaiser_constant() {
+        let img = tex::ImageInfo {
+            width: 7,
+            height: 3,
+            ..tex::ImageInfo::new()
+        };
+        let data = Vec::from_elem(7 * 3 * 4, 100u8);
+        let (info, out) = downsample(&img, data.as_slice(), tex::Texture2D,
+                                     KaiserFilter, false).unwrap();
+        assert_eq!((info.width, info.height), (3, 1));
+        assert_eq!(out, Vec::from_elem(3 * 4, 100u8));
+    }
+
+    #[test]
+    fn test_box_layers() {
+        let img = tex::ImageInfo {
+            width: 2,
+            height: 2,
+            ..tex::ImageInfo::new()
+        };
+        let data = [0u8, 0, 0, 0, 64, 64, 64, 64,
+                    128, 128, 128, 128, 192, 192, 192, 192];
+        let (info, out) = downsample(&img, data.as_slice(), tex::Texture1DArray,
+                                     BoxFilter, false).unwrap();
+        assert_eq!((info.width, info.height), (1, 2));
+        assert_eq!(out, vec![32, 32, 32, 32, 160, 160, 160, 160]);
+    }
+}
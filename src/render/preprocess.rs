@@ -0,0 +1,312 @@
+// Copyright 2014 The Gfx-rs Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Shader source preprocessing. Resolves `#include` directives against a
+//! `FileSystem` and injects `#define`s right after the `#version` line, so
+//! that common code can be shared between shaders.
+//!
+//! `#line` directives keep the driver diagnostics pointing at the original
+//! lines. The source string number of a `Diagnostic` is 0 for the processed
+//! source, and the included files are numbered from 1 in the order they are
+//! first included.
+//!
+//! The `preprocess!` macro compiles this module into `gfx_macros` as well, so
+//! it only depends on `std`.
+
+use std::io::File;
+use std::str;
+
+/// The name used in errors for the top-level shader source.
+pub const ROOT_NAME: &'static str = "<source>";
+
+/// An error occurring while preprocessing shader source.
+#[deriving(Clone, PartialEq, Show)]
+pub enum PreprocessError {
+    /// The file is not valid UTF-8.
+    InvalidEncoding(String),
+    /// The `#include` directive on the given line of the file is malformed.
+    InvalidInclude(String, uint),
+    /// The included file does not exist.
+    MissingInclude(String),
+    /// The file includes itself, directly or through other files.
+    RecursiveInclude(String),
+}
+
+/// A source of files for `#include` directives.
+pub trait FileSystem {
+    /// Read the contents of the file at `path`, if it exists.
+    fn read(&self, path: &str) -> Option<Vec<u8>>;
+}
+
+/// A table of named files, typically filled with `include_bin!`.
+impl<'a> FileSystem for &'a [(&'a str, &'a [u8])] {
+    fn read(&self, path: &str) -> Option<Vec<u8>> {
+        self.iter().find(|&&(name, _)| name == path)
+                   .map(|&(_, data)| data.to_vec())
+    }
+}
+
+/// A directory on disk, with the paths relative to it.
+impl FileSystem for Path {
+    fn read(&self, path: &str) -> Option<Vec<u8>> {
+        File::open(&self.join(path)).read_to_end().ok()
+    }
+}
+
+/// Parse a line as an `#include` directive. Returns `None` if the line is
+/// not one, and `Some(None)` if it is malformed.
+fn parse_include<'a>(line: &'a str) -> Option<Option<&'a str>> {
+    let line = line.trim_left();
+    if !line.starts_with("#") {
+        return None
+    }
+    let directive = line.slice_from(1).trim_left();
+    if !directive.starts_with("include") {
+        return None
+    }
+    let arg = directive.slice_from("include".len()).trim();
+    let n = arg.len();
+    if n > 2 && ((arg.starts_with("\"") && arg.ends_with("\"")) ||
+                 (arg.starts_with("<") && arg.ends_with(">"))) {
+        Some(Some(arg.slice(1, n - 1)))
+    } else {
+        Some(None)
+    }
+}
+
+/// Split the source after its `#version` line, if it has one. Only blank
+/// lines and comments may precede it.
+fn split_version<'a>(text: &'a str) -> (&'a str, &'a str) {
+    let mut rest = text;
+    loop {
+        rest = rest.trim_left();
+        if rest.starts_with("//") {
+            rest = match rest.find('\n') {
+                Some(i) => rest.slice_from(i + 1),
+                None => "",
+            };
+        } else if rest.starts_with("/*") {
+            rest = match rest.find_str("*/") {
+                Some(i) => rest.slice_from(i + 2),
+                None => return ("", text),
+            };
+        } else {
+            break
+        }
+    }
+    if !rest.starts_with("#version") {
+        return ("", text)
+    }
+    let end = text.len() - rest.len() + match rest.find('\n') {
+        Some(i) => i + 1,
+        None => rest.len(),
+    };
+    (text.slice_to(end), text.slice_from(end))
+}
+
+/// Whether `#line N` sets the number of the next line to `N`, rather than
+/// `N + 1` as in GLSL before 3.30 and GLSL ES 1.00.
+fn is_line_next(head: &str) -> bool {
+    // the `#version` directive ends the head, after any comments
+    let line = head.lines_any().last().unwrap_or("");
+    let mut words = line.words().skip_while(|&w| w != "#version");
+    match (words.next(), words.next().and_then(|v| from_str::<uint>(v)), words.next()) {
+        (Some("#version"), Some(version), profile) =>
+            version >= 330 || (version == 300 && profile == Some("es")),
+        _ => false,
+    }
+}
+
+/// Tracks the source string numbers of the files, and how to number lines.
+struct LineState {
+    files: Vec<String>,
+    line_next: bool,
+}
+
+impl LineState {
+    /// Write a `#line` directive making the next line `line` of `name`.
+    fn write(&mut self, out: &mut Vec<u8>, name: &str, line: uint) {
+        let file = match self.files.iter().position(|f| f.as_slice() == name) {
+            Some(i) => i,
+            None => {
+                self.files.push(name.to_string());
+                self.files.len() - 1
+            },
+        };
+        let line = if self.line_next { line } else { line - 1 };
+        out.push_all(format!("#line {} {}\n", line, file).as_bytes());
+    }
+}
+
+fn to_str<'a>(name: &str, data: &'a [u8]) -> Result<&'a str, PreprocessError> {
+    match str::from_utf8(data) {
+        Some(text) => Ok(text),
+        None => Err(InvalidEncoding(name.to_string())),
+    }
+}
+
+/// Resolves includes and injects defines into shader source.
+pub struct Preprocessor<F> {
+    fs: F,
+    defines: Vec<(String, String)>,
+}
+
+impl<F: FileSystem> Preprocessor<F> {
+    /// Create a new preprocessor resolving includes against `fs`.
+    pub fn new(fs: F) -> Preprocessor<F> {
+        Preprocessor {
+            fs: fs,
+            defines: Vec::new(),
+        }
+    }
+
+    /// Define a macro for all the processed shaders, replacing any previous
+    /// definition of the same name.
+    pub fn define(&mut self, name: &str, value: &str) {
+        match self.defines.iter_mut().find(|&&(ref n, _)| n.as_slice() == name) {
+            Some(&(_, ref mut v)) => {
+                *v = value.to_string();
+                return
+            },
+            None => (),
+        }
+        self.defines.push((name.to_string(), value.to_string()));
+    }
+
+    /// Remove the definition of a macro.
+    pub fn undefine(&mut self, name: &str) {
+        self.defines.retain(|&(ref n, _)| n.as_slice() != name);
+    }
+
+    /// Process the shader code of a single target.
+    pub fn process(&self, src: &[u8]) -> Result<Vec<u8>, PreprocessError> {
+        let text = try!(to_str(ROOT_NAME, src));
+        let (head, body) = split_version(text);
+        let mut out = Vec::with_capacity(src.len());
+        out.push_all(head.as_bytes());
+        if !head.is_empty() && !head.ends_with("\n") {
+            out.push(b'\n');
+        }
+        for &(ref name, ref value) in self.defines.iter() {
+            out.push_all(format!("#define {} {}\n", name, value).as_bytes());
+        }
+        let first_line = head.lines_any().count();
+        let mut lines = LineState {
+            files: vec![ROOT_NAME.to_string()],
+            line_next: is_line_next(head),
+        };
+        if !self.defines.is_empty() {
+            lines.write(&mut out, ROOT_NAME, first_line + 1);
+        }
+        let mut stack = Vec::new();
+        try!(self.include(&mut out, ROOT_NAME, body, first_line, &mut stack, &mut lines));
+        Ok(out)
+    }
+
+    fn include(&self, out: &mut Vec<u8>, name: &str, text: &str, first_line: uint,
+               stack: &mut Vec<String>, lines: &mut LineState)
+               -> Result<(), PreprocessError> {
+        for (i, line) in text.lines_any().enumerate() {
+            let path = match parse_include(line) {
+                None => {
+                    out.push_all(line.as_bytes());
+                    out.push(b'\n');
+                    continue
+                },
+                Some(Some(path)) => path,
+                Some(None) => return Err(InvalidInclude(name.to_string(), first_line + i + 1)),
+            };
+            if path == name || stack.iter().any(|p| p.as_slice() == path) {
+                return Err(RecursiveInclude(path.to_string()))
+            }
+            let data = match self.fs.read(path) {
+                Some(data) => data,
+                None => return Err(MissingInclude(path.to_string())),
+            };
+            stack.push(path.to_string());
+            let text = try!(to_str(path, data.as_slice()));
+            lines.write(out, path, 1);
+            try!(self.include(out, path, text, 0, stack, lines));
+            lines.write(out, name, first_line + i + 2);
+            stack.pop();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Preprocessor, FileSystem, MissingInclude, RecursiveInclude, InvalidInclude};
+
+    static FILES: &'static [(&'static str, &'static [u8])] = &[
+        ("light.glsl", b"#include \"common.glsl\"\nvec3 light() { return COLOR; }"),
+        ("common.glsl", b"#define COLOR vec3(1.0)"),
+        ("loop.glsl", b"#include <loop.glsl>"),
+    ];
+
+    fn preprocessor() -> Preprocessor<&'static [(&'static str, &'static [u8])]> {
+        Preprocessor::new(FILES)
+    }
+
+    #[test]
+    fn test_file_system() {
+        assert_eq!(FILES.read("common.glsl"), Some(b"#define COLOR vec3(1.0)".to_vec()));
+        assert_eq!(FILES.read("missing.glsl"), None);
+    }
+
+    #[test]
+    fn test_include() {
+        let mut pp = preprocessor();
+        pp.define("SHADOWS", "1");
+        let out = pp.process(b"#version 150\n#include \"light.glsl\"\nvoid main() {}");
+        assert_eq!(out, Ok(b"#version 150\n#define SHADOWS 1\n#line 1 0\n\
+                             #line 0 1\n#line 0 2\n#define COLOR vec3(1.0)\n#line 1 1\n\
+                             vec3 light() { return COLOR; }\n#line 2 0\n\
+                             void main() {}\n".to_vec()));
+    }
+
+    #[test]
+    fn test_line_numbers() {
+        let pp = preprocessor();
+        let out = pp.process(b"#version 330 core\n\n#include \"common.glsl\"\nvoid main() {}");
+        assert_eq!(out, Ok(b"#version 330 core\n\n#line 1 1\n#define COLOR vec3(1.0)\n\
+                             #line 4 0\nvoid main() {}\n".to_vec()));
+        assert!(super::is_line_next("#version 300 es\n"));
+        assert!(!super::is_line_next("#version 100\n"));
+        assert!(!super::is_line_next(""));
+    }
+
+    #[test]
+    fn test_version_after_comments() {
+        let mut pp = preprocessor();
+        pp.define("SHADOWS", "1");
+        let out = pp.process(b"// lighting\n\n/* shared\n   code */ #version 330\nvoid main() {}");
+        assert_eq!(out, Ok(b"// lighting\n\n/* shared\n   code */ #version 330\n#define SHADOWS 1\n\
+                             #line 5 0\nvoid main() {}\n".to_vec()));
+        // the first directive is not `#version`
+        assert_eq!(super::split_version("/* a */\n#define A\n#version 150\n"),
+                   ("", "/* a */\n#define A\n#version 150\n"));
+        assert_eq!(super::split_version("/* unterminated #version 150\n"),
+                   ("", "/* unterminated #version 150\n"));
+    }
+
+    #[test]
+    fn test_include_errors() {
+        let pp = preprocessor();
+        assert_eq!(pp.process(b"#include \"none.glsl\""), Err(MissingInclude("none.glsl".to_string())));
+        assert_eq!(pp.process(b"#include \"loop.glsl\""), Err(RecursiveInclude("loop.glsl".to_string())));
+        assert_eq!(pp.process(b"#version 120\n\n# include light.glsl"),
+                   Err(InvalidInclude(super::ROOT_NAME.to_string(), 3)));
+    }
+}
@@ -16,9 +16,13 @@
 
 use std::cell::Cell;
 use device::shade;
-use device::{RawBufferHandle, TextureHandle, SamplerHandle};
+use device::target::{Layer, Level};
+use device::tex;
+use device::{BufferHandle, RawBufferHandle, TextureHandle, SamplerHandle};
 
 pub use device::shade::{Stage, CreateShaderError, Vertex, Geometry, Fragment};
+pub use device::shade::{ShaderLog, Diagnostic, DiagnosticLevel, LevelError, LevelWarning, LevelNote};
+pub use device::shade::{BlockVar, BlockMember};
 
 /// Helper trait to transform base types into their corresponding uniforms
 pub trait ToUniform {
@@ -37,41 +41,491 @@ macro_rules! impl_ToUniform(
 )
 
 impl_ToUniform!(i32, shade::ValueI32)
+impl_ToUniform!(u32, shade::ValueU32)
 impl_ToUniform!(f32, shade::ValueF32)
+impl_ToUniform!(f64, shade::ValueF64)
+impl_ToUniform!(bool, shade::ValueBool)
 
 impl_ToUniform!([i32, ..2], shade::ValueI32Vector2)
 impl_ToUniform!([i32, ..3], shade::ValueI32Vector3)
 impl_ToUniform!([i32, ..4], shade::ValueI32Vector4)
 
+impl_ToUniform!([u32, ..2], shade::ValueU32Vector2)
+impl_ToUniform!([u32, ..3], shade::ValueU32Vector3)
+impl_ToUniform!([u32, ..4], shade::ValueU32Vector4)
+
 impl_ToUniform!([f32, ..2], shade::ValueF32Vector2)
 impl_ToUniform!([f32, ..3], shade::ValueF32Vector3)
 impl_ToUniform!([f32, ..4], shade::ValueF32Vector4)
 
+impl_ToUniform!([f64, ..2], shade::ValueF64Vector2)
+impl_ToUniform!([f64, ..3], shade::ValueF64Vector3)
+impl_ToUniform!([f64, ..4], shade::ValueF64Vector4)
+
+impl_ToUniform!([bool, ..2], shade::ValueBoolVector2)
+impl_ToUniform!([bool, ..3], shade::ValueBoolVector3)
+impl_ToUniform!([bool, ..4], shade::ValueBoolVector4)
+
 impl_ToUniform!([[f32, ..2], ..2], shade::ValueF32Matrix2)
 impl_ToUniform!([[f32, ..3], ..3], shade::ValueF32Matrix3)
 impl_ToUniform!([[f32, ..4], ..4], shade::ValueF32Matrix4)
 
+impl_ToUniform!([[f32, ..3], ..2], shade::ValueF32Matrix2x3)
+impl_ToUniform!([[f32, ..4], ..2], shade::ValueF32Matrix2x4)
+impl_ToUniform!([[f32, ..2], ..3], shade::ValueF32Matrix3x2)
+impl_ToUniform!([[f32, ..4], ..3], shade::ValueF32Matrix3x4)
+impl_ToUniform!([[f32, ..2], ..4], shade::ValueF32Matrix4x2)
+impl_ToUniform!([[f32, ..3], ..4], shade::ValueF32Matrix4x3)
+
+impl_ToUniform!([[f64, ..2], ..2], shade::ValueF64Matrix2)
+impl_ToUniform!([[f64, ..3], ..3], shade::ValueF64Matrix3)
+impl_ToUniform!([[f64, ..4], ..4], shade::ValueF64Matrix4)
+
+impl_ToUniform!([[f64, ..3], ..2], shade::ValueF64Matrix2x3)
+impl_ToUniform!([[f64, ..4], ..2], shade::ValueF64Matrix2x4)
+impl_ToUniform!([[f64, ..2], ..3], shade::ValueF64Matrix3x2)
+impl_ToUniform!([[f64, ..4], ..3], shade::ValueF64Matrix3x4)
+impl_ToUniform!([[f64, ..2], ..4], shade::ValueF64Matrix4x2)
+impl_ToUniform!([[f64, ..3], ..4], shade::ValueF64Matrix4x3)
+
 /// Variable index of a uniform.
 pub type VarUniform = u16;
 
+/// Link of a uniform array field, which the program may declare either as
+/// an array or, for arrays of scalars and vectors, as a single vector or
+/// matrix variable of the same name.
+#[deriving(Clone, PartialEq, Show)]
+pub enum VarUniformArray {
+    /// Variable index of the first element of a uniform array, and the
+    /// number of elements used by the program.
+    UniformElements(VarUniform, VarUniform),
+    /// Variable index of a vector or matrix, whose components or columns are
+    /// the elements of the field.
+    UniformComposite(VarUniform),
+}
+
+/// Link the uniform variable `var`, at index `index` in the program, to an
+/// array field of `len` elements. The variable is either the next element of
+/// a uniform array, or a vector or matrix with as many components or columns
+/// as the field has elements.
+pub fn link_uniform_array(link: Option<VarUniformArray>, var: &shade::UniformVar,
+                          index: uint, len: uint) -> Result<VarUniformArray, ParameterError> {
+    match (link, var.name.as_slice().ends_with("]")) {
+        (None, false) => match var.container {
+            shade::Vector(n) if n as uint == len => Ok(UniformComposite(index as VarUniform)),
+            shade::Matrix(_, cols, _) if cols as uint == len =>
+                Ok(UniformComposite(index as VarUniform)),
+            _ => Err(ArraySizeMismatch(var.name.clone())),
+        },
+        (None, true) if len > 0 => Ok(UniformElements(index as VarUniform, 1)),
+        (Some(UniformElements(first, count)), true) if (count as uint) < len =>
+            Ok(UniformElements(first, count + 1)),
+        _ => Err(ArraySizeMismatch(var.name.clone())),
+    }
+}
+
+/// Get the uniform values of an array field, using its link.
+pub fn fill_uniform_array<T: ToUniform>(values: &[T], link: VarUniformArray,
+                                        uniforms: &mut Vec<Option<shade::UniformValue>>) {
+    match link {
+        UniformElements(first, count) => {
+            for (k, v) in values.iter().take(count as uint).enumerate() {
+                set_value(uniforms, first as uint + k, Some(v.to_uniform()));
+            }
+        },
+        UniformComposite(id) => {
+            let elements: Vec<shade::UniformValue> = values.iter().map(|v| v.to_uniform()).collect();
+            set_value(uniforms, id as uint, compose_uniform(elements.as_slice()));
+        },
+    }
+}
+
+/// Make a vector out of scalars, or a matrix out of column vectors. Returns
+/// `None` if there is no uniform type of that shape.
+pub fn compose_uniform(elements: &[shade::UniformValue]) -> Option<shade::UniformValue> {
+    macro_rules! compose(
+        ($element:ident, $n:expr, $zero:expr, $composite:ident) => ({
+            let mut out = [$zero, ..$n];
+            for (o, e) in out.iter_mut().zip(elements.iter()) {
+                match *e {
+                    shade::$element(v) => *o = v,
+                    _ => return None,
+                }
+            }
+            Some(shade::$composite(out))
+        })
+    )
+    match (elements.head(), elements.len()) {
+        (Some(&shade::ValueI32(_)), 2) => compose!(ValueI32, 2, 0, ValueI32Vector2),
+        (Some(&shade::ValueI32(_)), 3) => compose!(ValueI32, 3, 0, ValueI32Vector3),
+        (Some(&shade::ValueI32(_)), 4) => compose!(ValueI32, 4, 0, ValueI32Vector4),
+
+        (Some(&shade::ValueU32(_)), 2) => compose!(ValueU32, 2, 0, ValueU32Vector2),
+        (Some(&shade::ValueU32(_)), 3) => compose!(ValueU32, 3, 0, ValueU32Vector3),
+        (Some(&shade::ValueU32(_)), 4) => compose!(ValueU32, 4, 0, ValueU32Vector4),
+
+        (Some(&shade::ValueF32(_)), 2) => compose!(ValueF32, 2, 0.0, ValueF32Vector2),
+        (Some(&shade::ValueF32(_)), 3) => compose!(ValueF32, 3, 0.0, ValueF32Vector3),
+        (Some(&shade::ValueF32(_)), 4) => compose!(ValueF32, 4, 0.0, ValueF32Vector4),
+
+        (Some(&shade::ValueF64(_)), 2) => compose!(ValueF64, 2, 0.0, ValueF64Vector2),
+        (Some(&shade::ValueF64(_)), 3) => compose!(ValueF64, 3, 0.0, ValueF64Vector3),
+        (Some(&shade::ValueF64(_)), 4) => compose!(ValueF64, 4, 0.0, ValueF64Vector4),
+
+        (Some(&shade::ValueBool(_)), 2) => compose!(ValueBool, 2, false, ValueBoolVector2),
+        (Some(&shade::ValueBool(_)), 3) => compose!(ValueBool, 3, false, ValueBoolVector3),
+        (Some(&shade::ValueBool(_)), 4) => compose!(ValueBool, 4, false, ValueBoolVector4),
+
+        (Some(&shade::ValueF32Vector2(_)), 2) => compose!(ValueF32Vector2, 2, [0.0, ..2], ValueF32Matrix2),
+        (Some(&shade::ValueF32Vector3(_)), 2) => compose!(ValueF32Vector3, 2, [0.0, ..3], ValueF32Matrix2x3),
+        (Some(&shade::ValueF32Vector4(_)), 2) => compose!(ValueF32Vector4, 2, [0.0, ..4], ValueF32Matrix2x4),
+        (Some(&shade::ValueF32Vector2(_)), 3) => compose!(ValueF32Vector2, 3, [0.0, ..2], ValueF32Matrix3x2),
+        (Some(&shade::ValueF32Vector3(_)), 3) => compose!(ValueF32Vector3, 3, [0.0, ..3], ValueF32Matrix3),
+        (Some(&shade::ValueF32Vector4(_)), 3) => compose!(ValueF32Vector4, 3, [0.0, ..4], ValueF32Matrix3x4),
+        (Some(&shade::ValueF32Vector2(_)), 4) => compose!(ValueF32Vector2, 4, [0.0, ..2], ValueF32Matrix4x2),
+        (Some(&shade::ValueF32Vector3(_)), 4) => compose!(ValueF32Vector3, 4, [0.0, ..3], ValueF32Matrix4x3),
+        (Some(&shade::ValueF32Vector4(_)), 4) => compose!(ValueF32Vector4, 4, [0.0, ..4], ValueF32Matrix4),
+
+        (Some(&shade::ValueF64Vector2(_)), 2) => compose!(ValueF64Vector2, 2, [0.0, ..2], ValueF64Matrix2),
+        (Some(&shade::ValueF64Vector3(_)), 2) => compose!(ValueF64Vector3, 2, [0.0, ..3], ValueF64Matrix2x3),
+        (Some(&shade::ValueF64Vector4(_)), 2) => compose!(ValueF64Vector4, 2, [0.0, ..4], ValueF64Matrix2x4),
+        (Some(&shade::ValueF64Vector2(_)), 3) => compose!(ValueF64Vector2, 3, [0.0, ..2], ValueF64Matrix3x2),
+        (Some(&shade::ValueF64Vector3(_)), 3) => compose!(ValueF64Vector3, 3, [0.0, ..3], ValueF64Matrix3),
+        (Some(&shade::ValueF64Vector4(_)), 3) => compose!(ValueF64Vector4, 3, [0.0, ..4], ValueF64Matrix3x4),
+        (Some(&shade::ValueF64Vector2(_)), 4) => compose!(ValueF64Vector2, 4, [0.0, ..2], ValueF64Matrix4x2),
+        (Some(&shade::ValueF64Vector3(_)), 4) => compose!(ValueF64Vector3, 4, [0.0, ..3], ValueF64Matrix4x3),
+        (Some(&shade::ValueF64Vector4(_)), 4) => compose!(ValueF64Vector4, 4, [0.0, ..4], ValueF64Matrix4),
+
+        _ => None,
+    }
+}
+
+/// Return true if `name` is the one of an element of the array `base`, such
+/// as `lights[3]` for `lights`.
+pub fn is_array_element(name: &str, base: &str) -> bool {
+    name.len() > base.len() + 2 && name.starts_with(base) &&
+        name.slice_from(base.len()).starts_with("[") && name.ends_with("]") &&
+        name.slice(base.len() + 1, name.len() - 1).chars().all(|c| c >= '0' && c <= '9')
+}
+
 /// Variable index of a uniform block.
 pub type VarBlock = u8;
 
+/// Variable index of the first block of an array, and the number of blocks.
+pub type VarBlockArray = (VarBlock, VarBlock);
+
+/// Helper trait to describe the types that can be members of a uniform block
+pub trait BlockElement {
+    /// Get the shader type of this value, self is passed as a workaround for
+    /// Rust to not be lost in generics
+    fn element_type(Option<&Self>) -> (shade::BaseType, shade::ContainerType);
+    /// Get the distance in bytes between the columns of a matrix, 0 for
+    /// other values
+    fn matrix_stride(_: Option<&Self>) -> uint { 0 }
+}
+
+macro_rules! impl_BlockElement(
+    ($srcty:ty, $base:expr, $container:expr) => (
+        impl BlockElement for $srcty {
+            fn element_type(_: Option<&$srcty>) -> (shade::BaseType, shade::ContainerType) {
+                ($base, $container)
+            }
+        }
+    );
+)
+
+macro_rules! impl_BlockMatrix(
+    ($srcty:ty, $column:ty, $base:expr, $cols:expr, $rows:expr) => (
+        impl BlockElement for $srcty {
+            fn element_type(_: Option<&$srcty>) -> (shade::BaseType, shade::ContainerType) {
+                ($base, shade::Matrix(shade::ColumnMajor, $cols, $rows))
+            }
+            fn matrix_stride(_: Option<&$srcty>) -> uint {
+                ::std::mem::size_of::<$column>()
+            }
+        }
+    );
+)
+
+impl_BlockElement!(i32, shade::BaseI32, shade::Single)
+impl_BlockElement!(u32, shade::BaseU32, shade::Single)
+impl_BlockElement!(f32, shade::BaseF32, shade::Single)
+impl_BlockElement!(f64, shade::BaseF64, shade::Single)
+
+impl_BlockElement!([i32, ..2], shade::BaseI32, shade::Vector(2))
+impl_BlockElement!([i32, ..3], shade::BaseI32, shade::Vector(3))
+impl_BlockElement!([i32, ..4], shade::BaseI32, shade::Vector(4))
+
+impl_BlockElement!([u32, ..2], shade::BaseU32, shade::Vector(2))
+impl_BlockElement!([u32, ..3], shade::BaseU32, shade::Vector(3))
+impl_BlockElement!([u32, ..4], shade::BaseU32, shade::Vector(4))
+
+impl_BlockElement!([f32, ..2], shade::BaseF32, shade::Vector(2))
+impl_BlockElement!([f32, ..3], shade::BaseF32, shade::Vector(3))
+impl_BlockElement!([f32, ..4], shade::BaseF32, shade::Vector(4))
+
+impl_BlockElement!([f64, ..2], shade::BaseF64, shade::Vector(2))
+impl_BlockElement!([f64, ..3], shade::BaseF64, shade::Vector(3))
+impl_BlockElement!([f64, ..4], shade::BaseF64, shade::Vector(4))
+
+// matrices whose columns are 16 bytes apart, as std140 requires
+impl_BlockMatrix!([[f32, ..4], ..2], [f32, ..4], shade::BaseF32, 2, 4)
+impl_BlockMatrix!([[f32, ..4], ..3], [f32, ..4], shade::BaseF32, 3, 4)
+impl_BlockMatrix!([[f32, ..4], ..4], [f32, ..4], shade::BaseF32, 4, 4)
+
+impl_BlockMatrix!([[f64, ..2], ..2], [f64, ..2], shade::BaseF64, 2, 2)
+impl_BlockMatrix!([[f64, ..2], ..3], [f64, ..2], shade::BaseF64, 3, 2)
+impl_BlockMatrix!([[f64, ..2], ..4], [f64, ..2], shade::BaseF64, 4, 2)
+impl_BlockMatrix!([[f64, ..4], ..2], [f64, ..4], shade::BaseF64, 2, 4)
+impl_BlockMatrix!([[f64, ..4], ..3], [f64, ..4], shade::BaseF64, 3, 4)
+impl_BlockMatrix!([[f64, ..4], ..4], [f64, ..4], shade::BaseF64, 4, 4)
+
+/// A vector of three components padded to the size of four, as std140
+/// lays out the elements of `vec3` arrays and the columns of `mat3`.
+#[repr(C)]
+#[deriving(Clone, PartialEq, Show)]
+pub struct PaddedVec3<T> {
+    /// Components of the vector
+    pub value: [T, ..3],
+    /// Unused padding
+    pub padding: T,
+}
+
+impl<T: Default> PaddedVec3<T> {
+    /// Pad the given vector.
+    pub fn new(value: [T, ..3]) -> PaddedVec3<T> {
+        PaddedVec3 {
+            value: value,
+            padding: Default::default(),
+        }
+    }
+}
+
+impl_BlockElement!(PaddedVec3<i32>, shade::BaseI32, shade::Vector(3))
+impl_BlockElement!(PaddedVec3<u32>, shade::BaseU32, shade::Vector(3))
+impl_BlockElement!(PaddedVec3<f32>, shade::BaseF32, shade::Vector(3))
+impl_BlockElement!(PaddedVec3<f64>, shade::BaseF64, shade::Vector(3))
+
+impl_BlockMatrix!([PaddedVec3<f32>, ..2], PaddedVec3<f32>, shade::BaseF32, 2, 3)
+impl_BlockMatrix!([PaddedVec3<f32>, ..3], PaddedVec3<f32>, shade::BaseF32, 3, 3)
+impl_BlockMatrix!([PaddedVec3<f32>, ..4], PaddedVec3<f32>, shade::BaseF32, 4, 3)
+
+impl_BlockMatrix!([PaddedVec3<f64>, ..2], PaddedVec3<f64>, shade::BaseF64, 2, 3)
+impl_BlockMatrix!([PaddedVec3<f64>, ..3], PaddedVec3<f64>, shade::BaseF64, 3, 3)
+impl_BlockMatrix!([PaddedVec3<f64>, ..4], PaddedVec3<f64>, shade::BaseF64, 4, 3)
+
+/// Abstracts the structure stored in a uniform buffer, generated by the
+/// `uniform_block` attribute. The std140 layout requires vectors of three
+/// components to be padded to four in arrays and matrices, so a `mat3` is
+/// `[PaddedVec3<f32>, ..3]` and a `vec3` array is `[PaddedVec3<f32>, ..N]`.
+pub trait UniformBlock {
+    /// Describe the fields as laid out in memory, self is passed as a
+    /// workaround for Rust to not be lost in generics
+    fn get_members(Option<&Self>) -> Vec<shade::BlockMember>;
+}
+
+/// Describe a field of type `T` at the given `offset` in memory, as an array
+/// if `array_len` is given.
+pub fn describe_member<T: BlockElement>(name: &str, offset: uint, _: Option<&T>,
+                                        array_len: Option<uint>) -> shade::BlockMember {
+    let (base, container) = BlockElement::element_type(None::<&T>);
+    let size = ::std::mem::size_of::<T>();
+    shade::BlockMember {
+        name: name.to_string(),
+        offset: offset,
+        base_type: base,
+        container: container,
+        count: array_len.unwrap_or(1),
+        array_stride: if array_len.is_some() {size} else {0},
+        matrix_stride: BlockElement::matrix_stride(None::<&T>),
+    }
+}
+
+fn round_up(value: uint, alignment: uint) -> uint {
+    (value + alignment - 1) / alignment * alignment
+}
+
+/// Compute the std140 layout of the given members, in order. The types and
+/// counts are kept, while the offsets and strides are replaced. Members with
+/// a non-zero `array_stride` are laid out as arrays.
+pub fn std140_layout(members: &[shade::BlockMember]) -> Vec<shade::BlockMember> {
+    let mut cursor = 0u;
+    members.iter().map(|m| {
+        let scalar = match m.base_type {
+            shade::BaseF64 => 8u,
+            _ => 4u,
+        };
+        let vector_align = |n: shade::Dimension| match n {
+            1 => scalar,
+            2 => 2 * scalar,
+            _ => 4 * scalar,
+        };
+        let (size, align, matrix_stride) = match m.container {
+            shade::Single => (scalar, scalar, 0),
+            shade::Vector(n) => (n as uint * scalar, vector_align(n), 0),
+            shade::Matrix(format, cols, rows) => {
+                let (vectors, dim) = match format {
+                    shade::ColumnMajor => (cols, rows),
+                    shade::RowMajor => (rows, cols),
+                };
+                let stride = round_up(vector_align(dim), 16);
+                (vectors as uint * stride, stride, stride)
+            },
+        };
+        let is_array = m.array_stride != 0;
+        let (align, array_stride) = if is_array {
+            let align = round_up(align, 16);
+            (align, round_up(size, align))
+        } else {
+            (align, 0)
+        };
+        let offset = round_up(cursor, align);
+        cursor = offset + if is_array {m.count * array_stride} else {size};
+        shade::BlockMember {
+            offset: offset,
+            array_stride: array_stride,
+            matrix_stride: matrix_stride,
+            .. m.clone()
+        }
+    }).collect()
+}
+
+/// Describe a column-major matrix as the array of its columns, which std140
+/// lays out the same way.
+fn as_columns(m: &shade::BlockMember) -> shade::BlockMember {
+    match (m.container, m.count) {
+        (shade::Matrix(shade::ColumnMajor, cols, rows), 1) => shade::BlockMember {
+            container: shade::Vector(rows),
+            count: cols as uint,
+            array_stride: m.matrix_stride,
+            matrix_stride: 0,
+            .. m.clone()
+        },
+        _ => m.clone(),
+    }
+}
+
+/// Whether two block members are laid out the same way, whatever their names.
+/// The block structure can't tell `[[f32, ..4], ..2]` as a `mat2x4` from an
+/// array of two `vec4`, so either matches the field.
+fn same_layout(a: &shade::BlockMember, b: &shade::BlockMember) -> bool {
+    let (a, b) = (as_columns(a), as_columns(b));
+    a.offset == b.offset && a.base_type == b.base_type && a.container == b.container &&
+        a.count == b.count && a.array_stride == b.array_stride &&
+        a.matrix_stride == b.matrix_stride
+}
+
+/// Check that the structure of a uniform buffer is laid out as std140
+/// requires, and that it matches the size and members of the program block.
+pub fn check_block<T: UniformBlock>(_: Option<&BufferHandle<T>>, var: &shade::BlockVar)
+                                    -> Result<(), ParameterError> {
+    if ::std::mem::size_of::<T>() < var.size {
+        return Err(BlockSizeMismatch(var.name.clone()))
+    }
+    let members = UniformBlock::get_members(None::<&T>);
+    for (m, e) in members.iter().zip(std140_layout(members.as_slice()).iter()) {
+        if m != e {
+            return Err(BlockPaddingMismatch(var.name.clone(), m.name.clone()))
+        }
+    }
+    for r in var.members.iter() {
+        // members of blocks with an instance name are prefixed by the block name
+        let name = if r.name.as_slice().starts_with(var.name.as_slice()) &&
+                r.name.as_slice().slice_from(var.name.len()).starts_with(".") {
+            r.name.as_slice().slice_from(var.name.len() + 1)
+        } else {
+            r.name.as_slice()
+        };
+        match members.iter().find(|m| m.name.as_slice() == name) {
+            Some(m) if same_layout(m, r) => (),
+            _ => return Err(BlockLayoutMismatch(var.name.clone(), r.name.clone())),
+        }
+    }
+    Ok(())
+}
+
 /// Variable index of a texture.
 pub type VarTexture = u8;
 
+/// Variable index of the first texture of an array, and the number of textures.
+pub type VarTextureArray = (VarTexture, VarTexture);
+
+/// Variable index of an image.
+pub type VarImage = u8;
+
+/// Variable index of the first image of an array, and the number of images.
+pub type VarImageArray = (VarImage, VarImage);
+
+/// Variable index of a storage block.
+pub type VarStorageBlock = u8;
+
+/// Variable index of the first storage block of an array, and the number of
+/// storage blocks.
+pub type VarStorageBlockArray = (VarStorageBlock, VarStorageBlock);
+
 /// A texture parameter: consists of a texture handle with an optional sampler.
+/// Buffer textures, which back `samplerBuffer` uniforms, take no sampler.
 pub type TextureParam = (TextureHandle, Option<SamplerHandle>);
 
-/// A borrowed mutable storage for shader parameter values.
+/// An image parameter: a single level of a texture that the shaders can load
+/// from and store to directly.
+#[deriving(Clone, PartialEq, Show)]
+pub struct ImageParam {
+    /// The texture to access.
+    pub texture: TextureHandle,
+    /// The mipmap level to access.
+    pub level: Level,
+    /// The layer to access, or all of them (for array, cube and 3D textures)
+    /// if `None`.
+    pub layer: Option<Layer>,
+    /// The accesses allowed to the shaders.
+    pub access: tex::ImageAccess,
+    /// The format the shaders interpret the texels as. It has to have the
+    /// same size as the texture format.
+    pub format: tex::Format,
+}
+
+impl ImageParam {
+    /// Access all layers of the top level of the texture in its own format.
+    pub fn new(texture: TextureHandle, access: tex::ImageAccess) -> ImageParam {
+        ImageParam {
+            texture: texture,
+            level: 0,
+            layer: None,
+            access: access,
+            format: texture.get_info().format,
+        }
+    }
+}
+
+/// A shader storage buffer parameter.
+pub type StorageBufferParam = RawBufferHandle;
+
+/// A borrowed mutable storage for shader parameter values, indexed by the
+/// program variables. `None` values are not bound, leaving the variables as
+/// they were.
 // Not sure if it's the best data structure to represent it.
 pub struct ParamValues<'a> {
     /// uniform values to be provided
-    pub uniforms: &'a mut Vec<shade::UniformValue>,
+    pub uniforms: &'a mut Vec<Option<shade::UniformValue>>,
     /// uniform buffers to be provided
-    pub blocks  : &'a mut Vec<RawBufferHandle>,
+    pub blocks  : &'a mut Vec<Option<RawBufferHandle>>,
     /// textures to be provided
-    pub textures: &'a mut Vec<TextureParam>,
+    pub textures: &'a mut Vec<Option<TextureParam>>,
+    /// images to be provided
+    pub images: &'a mut Vec<Option<ImageParam>>,
+    /// storage buffers to be provided
+    pub storage_blocks: &'a mut Vec<Option<StorageBufferParam>>,
+}
+
+/// Store a value at the given index of a `ParamValues` vector, growing it as
+/// needed.
+pub fn set_value<T: Clone>(values: &mut Vec<Option<T>>, index: uint, value: Option<T>) {
+    if values.len() <= index {
+        let n = index + 1 - values.len();
+        values.grow(n, None);
+    }
+    *values.get_mut(index).unwrap() = value;
 }
 
 /// An error type on either the parameter storage or the program side
@@ -85,6 +539,39 @@ pub enum ParameterError {
     MissingBlock(String),
     /// Shader requested a texture that the parameters do not have
     MissingTexture(String),
+    /// Shader requested an image that the parameters do not have
+    MissingImage(String),
+    /// Shader requested a storage block that the parameters do not have
+    MissingStorageBlock(String),
+    /// The member of the block structure is not laid out as std140 requires
+    BlockPaddingMismatch(String, String),
+    /// The member of the shader block does not match the block structure
+    BlockLayoutMismatch(String, String),
+    /// The shader block is larger than the block structure
+    BlockSizeMismatch(String),
+    /// The shader array, vector or matrix has more elements, components or
+    /// columns than the parameter array
+    ArraySizeMismatch(String),
+}
+
+impl ParameterError {
+    /// Report the full name of the variable in error, as a nested structure
+    /// only sees the names stripped of its `prefix`.
+    fn with_prefix(self, prefix: &str) -> ParameterError {
+        let full = |name: String| format!("{}{}", prefix, name);
+        match self {
+            ParameterGeneralMismatch => ParameterGeneralMismatch,
+            MissingUniform(name) => MissingUniform(full(name)),
+            MissingBlock(name) => MissingBlock(full(name)),
+            MissingTexture(name) => MissingTexture(full(name)),
+            MissingImage(name) => MissingImage(full(name)),
+            MissingStorageBlock(name) => MissingStorageBlock(full(name)),
+            BlockPaddingMismatch(name, member) => BlockPaddingMismatch(full(name), member),
+            BlockLayoutMismatch(name, member) => BlockLayoutMismatch(full(name), member),
+            BlockSizeMismatch(name) => BlockSizeMismatch(full(name)),
+            ArraySizeMismatch(name) => ArraySizeMismatch(full(name)),
+        }
+    }
 }
 
 /// Abstracts the shader parameter structure, generated by the `shader_param` attribute
@@ -109,6 +596,14 @@ impl ShaderParam<()> for () {
             Some(t) => return Err(MissingTexture(t.name.clone())),
             None => (),
         }
+        match info.images.as_slice().head() {
+            Some(i) => return Err(MissingImage(i.name.clone())),
+            None => (),
+        }
+        match info.storage_blocks.as_slice().head() {
+            Some(b) => return Err(MissingStorageBlock(b.name.clone())),
+            None => (),
+        }
         Ok(())
     }
 
@@ -117,6 +612,127 @@ impl ShaderParam<()> for () {
     }
 }
 
+/// The link of a parameter structure nested in another one, along with the
+/// indices of its variables in the program.
+#[deriving(Clone, Show)]
+pub struct NestedLink<L> {
+    link: L,
+    uniforms: Vec<uint>,
+    blocks: Vec<uint>,
+    textures: Vec<uint>,
+    images: Vec<uint>,
+    storage_blocks: Vec<uint>,
+}
+
+/// Create the link of a nested parameter structure, against the variables
+/// whose names start with `prefix`, such as `material.` for the members of
+/// a `material` structure in GLSL.
+pub fn link_nested<L, T: ShaderParam<L>>(_: Option<&T>, info: &shade::ProgramInfo, prefix: &str)
+                                         -> Result<NestedLink<L>, ParameterError> {
+    macro_rules! select(
+        ($vars:expr, $prefix:expr) => ({
+            let mut vars = Vec::new();
+            let mut ids = Vec::new();
+            for (i, var) in $vars.iter().enumerate() {
+                if var.name.as_slice().starts_with($prefix) {
+                    let mut var = var.clone();
+                    var.name = var.name.as_slice().slice_from($prefix.len()).to_string();
+                    vars.push(var);
+                    ids.push(i);
+                }
+            }
+            (vars, ids)
+        })
+    )
+    let (uniforms, uniform_ids) = select!(info.uniforms, prefix);
+    let (blocks, block_ids) = select!(info.blocks, prefix);
+    let (textures, texture_ids) = select!(info.textures, prefix);
+    let (images, image_ids) = select!(info.images, prefix);
+    let (storage_blocks, storage_ids) = select!(info.storage_blocks, prefix);
+    let nested_info = shade::ProgramInfo {
+        attributes: Vec::new(),
+        uniforms: uniforms,
+        blocks: blocks,
+        textures: textures,
+        images: images,
+        storage_blocks: storage_blocks,
+    };
+    let link = match ShaderParam::create_link(None::<&T>, &nested_info) {
+        Ok(link) => link,
+        Err(e) => return Err(e.with_prefix(prefix)),
+    };
+    Ok(NestedLink {
+        link: link,
+        uniforms: uniform_ids,
+        blocks: block_ids,
+        textures: texture_ids,
+        images: image_ids,
+        storage_blocks: storage_ids,
+    })
+}
+
+/// The links of an array of nested parameter structures, one per element.
+pub type NestedLinkArray<L> = Vec<NestedLink<L>>;
+
+/// Create the links of an array of `count` nested parameter structures,
+/// against the variables prefixed by `name[i].` for each element `i`.
+pub fn link_nested_array<L, T: ShaderParam<L>>(_: Option<&T>, info: &shade::ProgramInfo,
+                                               name: &str, count: uint)
+                                               -> Result<NestedLinkArray<L>, ParameterError> {
+    let mut links = Vec::with_capacity(count);
+    for i in range(0, count) {
+        let prefix = format!("{}[{}].", name, i);
+        links.push(try!(link_nested(None::<&T>, info, prefix.as_slice())));
+    }
+    Ok(links)
+}
+
+/// Get the parameter values of a nested structure, using its link.
+pub fn fill_nested<L, T: ShaderParam<L>>(value: &T, link: &NestedLink<L>, params: ParamValues) {
+    let mut uniforms = Vec::new();
+    let mut blocks = Vec::new();
+    let mut textures = Vec::new();
+    let mut images = Vec::new();
+    let mut storage_blocks = Vec::new();
+    value.fill_params(&link.link, ParamValues {
+        uniforms: &mut uniforms,
+        blocks: &mut blocks,
+        textures: &mut textures,
+        images: &mut images,
+        storage_blocks: &mut storage_blocks,
+    });
+    for (&id, v) in link.uniforms.iter().zip(uniforms.into_iter()) {
+        set_value(params.uniforms, id, v);
+    }
+    for (&id, v) in link.blocks.iter().zip(blocks.into_iter()) {
+        set_value(params.blocks, id, v);
+    }
+    for (&id, v) in link.textures.iter().zip(textures.into_iter()) {
+        set_value(params.textures, id, v);
+    }
+    for (&id, v) in link.images.iter().zip(images.into_iter()) {
+        set_value(params.images, id, v);
+    }
+    for (&id, v) in link.storage_blocks.iter().zip(storage_blocks.into_iter()) {
+        set_value(params.storage_blocks, id, v);
+    }
+}
+
+/// Get the parameter values of an array of nested structures, using the
+/// links of its elements.
+pub fn fill_nested_array<L, T: ShaderParam<L>>(values: &[T], links: &[NestedLink<L>],
+                         params: ParamValues) {
+    for (value, link) in values.iter().zip(links.iter()) {
+        fill_nested(value, link, ParamValues {
+            uniforms: &mut *params.uniforms,
+            blocks: &mut *params.blocks,
+            textures: &mut *params.textures,
+            images: &mut *params.images,
+            storage_blocks: &mut *params.storage_blocks,
+        });
+    }
+}
+
 /// A named cell containing arbitrary value
 pub struct NamedCell<T> {
     /// Name
@@ -133,46 +749,373 @@ pub struct ParamDictionary {
     pub blocks: Vec<NamedCell<RawBufferHandle>>,
     /// Texture dictionary
     pub textures: Vec<NamedCell<TextureParam>>,
+    /// Image dictionary
+    pub images: Vec<NamedCell<ImageParam>>,
+    /// Storage block dictionary
+    pub storage_blocks: Vec<NamedCell<StorageBufferParam>>,
 }
 
-/// An associated link structure for `ParamDictionary` that redirects program
-/// input to the relevant dictionary cell.
+/// A program input read from the dictionary cell of the same name.
+struct DictionaryVar {
+    name: String,
+    /// Whether the missing cell has been reported already
+    reported: Cell<bool>,
+}
+
+/// An associated link structure for `ParamDictionary` and `ParamStack`, that
+/// redirects program input to the relevant dictionary cells. The cells are
+/// looked up by name on every fill, so the same link can be filled from
+/// dictionaries and stacks with different cells.
 pub struct ParamDictionaryLink {
-    uniforms: Vec<uint>,
-    blocks: Vec<uint>,
-    textures: Vec<uint>,
+    uniforms: Vec<DictionaryVar>,
+    blocks: Vec<DictionaryVar>,
+    textures: Vec<DictionaryVar>,
+    images: Vec<DictionaryVar>,
+    storage_blocks: Vec<DictionaryVar>,
+}
+
+/// A stack of dictionaries, from the outer scope to the inner one, such as
+/// global, view, material and object parameters. A parameter found in an
+/// inner dictionary overrides the ones with the same name in the outer ones.
+pub struct ParamStack<'a> {
+    /// Dictionaries, from the outer to the inner scope
+    pub layers: &'a [&'a ParamDictionary],
+}
+
+/// Find the innermost cell with the given name, as `(layer, index)`.
+macro_rules! find_cell(
+    ($layers:expr, $field:ident, $name:expr) => (
+        $layers.iter().enumerate().rev().filter_map(|(layer, d)|
+            d.$field.iter().position(|c| c.name == $name).map(|i| (layer, i))
+        ).next()
+    )
+)
+
+/// Create a dictionary link, checking that the layers, if known, provide
+/// every parameter of the program. The cells missing on a fill are reported
+/// once and left unbound.
+fn link_layers(layers: Option<&[&ParamDictionary]>, info: &shade::ProgramInfo)
+               -> Result<ParamDictionaryLink, ParameterError> {
+    macro_rules! link(
+        ($field:ident, $error:ident) => ({
+            let mut vars = Vec::with_capacity(info.$field.len());
+            for var in info.$field.iter() {
+                match layers {
+                    Some(layers) if find_cell!(layers, $field, var.name).is_none() =>
+                        return Err($error(var.name.clone())),
+                    _ => (),
+                }
+                vars.push(DictionaryVar {
+                    name: var.name.clone(),
+                    reported: Cell::new(false),
+                });
+            }
+            vars
+        })
+    )
+    Ok(ParamDictionaryLink {
+        uniforms: link!(uniforms, MissingUniform),
+        blocks: link!(blocks, MissingBlock),
+        textures: link!(textures, MissingTexture),
+        images: link!(images, MissingImage),
+        storage_blocks: link!(storage_blocks, MissingStorageBlock),
+    })
+}
+
+/// Get the parameter values from the linked cells of the layers.
+fn fill_layers(layers: &[&ParamDictionary], link: &ParamDictionaryLink, params: ParamValues) {
+    macro_rules! fill(
+        ($field:ident) => (
+            for var in link.$field.iter() {
+                let value = match find_cell!(layers, $field, var.name) {
+                    Some((layer, index)) => layers.get(layer)
+                        .and_then(|d| d.$field.as_slice().get(index))
+                        .map(|c| c.value.get()),
+                    None => {
+                        if !var.reported.get() {
+                            error!("Parameter {} is not found in the dictionaries", var.name);
+                            var.reported.set(true);
+                        }
+                        None
+                    },
+                };
+                params.$field.push(value);
+            }
+        )
+    )
+    fill!(uniforms);
+    fill!(blocks);
+    fill!(textures);
+    fill!(images);
+    fill!(storage_blocks);
 }
 
 impl ShaderParam<ParamDictionaryLink> for ParamDictionary {
     fn create_link(this: Option<&ParamDictionary>, info: &shade::ProgramInfo)
                    -> Result<ParamDictionaryLink, ParameterError> {
-        let this = match this {
-            Some(d) => d,
-            None => return Err(ParameterGeneralMismatch),
-        };
-        //TODO: proper error checks
-        Ok(ParamDictionaryLink {
-            uniforms: info.uniforms.iter().map(|var|
-                this.uniforms.iter().position(|c| c.name == var.name).unwrap()
-            ).collect(),
-            blocks: info.blocks.iter().map(|var|
-                this.blocks  .iter().position(|c| c.name == var.name).unwrap()
-            ).collect(),
-            textures: info.textures.iter().map(|var|
-                this.textures.iter().position(|c| c.name == var.name).unwrap()
-            ).collect(),
-        })
+        match this {
+            Some(d) => link_layers(Some([d].as_slice()), info),
+            None => link_layers(None, info),
+        }
     }
 
     fn fill_params(&self, link: &ParamDictionaryLink, params: ParamValues) {
-        for &id in link.uniforms.iter() {
-            params.uniforms.push(self.uniforms[id].value.get());
+        fill_layers([self], link, params)
+    }
+}
+
+impl<'a> ShaderParam<ParamDictionaryLink> for ParamStack<'a> {
+    fn create_link(this: Option<&ParamStack<'a>>, info: &shade::ProgramInfo)
+                   -> Result<ParamDictionaryLink, ParameterError> {
+        link_layers(this.map(|s| s.layers), info)
+    }
+
+    fn fill_params(&self, link: &ParamDictionaryLink, params: ParamValues) {
+        fill_layers(self.layers, link, params)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+    use device::BufferHandle;
+    use device::shade;
+    use super::{std140_layout, check_block, UniformBlock, BlockLayoutMismatch};
+    use super::{describe_member, PaddedVec3};
+    use super::{ShaderParam, ParamValues, ParamDictionary, ParamStack, NamedCell, MissingUniform};
+    use super::{is_array_element, link_uniform_array, compose_uniform, fill_uniform_array};
+    use super::{UniformElements, UniformComposite, ArraySizeMismatch};
+
+    fn member(name: &str, base: shade::BaseType, container: shade::ContainerType,
+              count: uint, array_stride: uint) -> shade::BlockMember {
+        shade::BlockMember {
+            name: name.to_string(),
+            offset: 0,
+            base_type: base,
+            container: container,
+            count: count,
+            array_stride: array_stride,
+            matrix_stride: 0,
         }
-        for &id in link.blocks.iter() {
-            params.blocks.push(self.blocks[id].value.get());
+    }
+
+    struct Block;
+
+    impl UniformBlock for Block {
+        fn get_members(_: Option<&Block>) -> Vec<shade::BlockMember> {
+            vec![
+                shade::BlockMember { matrix_stride: 16, .. member("model",
+                    shade::BaseF32, shade::Matrix(shade::ColumnMajor, 4, 4), 1, 0) },
+                shade::BlockMember { offset: 64, .. member("time",
+                    shade::BaseF32, shade::Single, 1, 0) },
+            ]
         }
-        for &id in link.textures.iter() {
-            params.textures.push(self.textures[id].value.get());
+    }
+
+    #[test]
+    fn test_std140_layout() {
+        let layout = std140_layout(&[
+            member("a", shade::BaseF32, shade::Single, 1, 0),
+            member("b", shade::BaseF32, shade::Vector(3), 1, 0),
+            member("c", shade::BaseF32, shade::Single, 1, 0),
+            member("d", shade::BaseF32, shade::Vector(2), 3, 1),
+            member("e", shade::BaseF32, shade::Matrix(shade::ColumnMajor, 3, 3), 1, 0),
+            member("f", shade::BaseF64, shade::Vector(3), 1, 0),
+        ]);
+        assert_eq!(layout.iter().map(|m| (m.offset, m.array_stride, m.matrix_stride))
+                         .collect::<Vec<_>>(),
+                   vec![(0, 0, 0), (16, 0, 0), (28, 0, 0), (32, 16, 0), (80, 0, 16), (128, 0, 0)]);
+    }
+
+    #[test]
+    fn test_describe_member() {
+        let mat3 = describe_member("m", 0, None::<&[PaddedVec3<f32>, ..3]>, None);
+        assert_eq!((mat3.container, mat3.matrix_stride),
+                   (shade::Matrix(shade::ColumnMajor, 3, 3), 16));
+        let dmat3x2 = describe_member("d", 0, None::<&[[f64, ..2], ..3]>, Some(2));
+        assert_eq!((dmat3x2.container, dmat3x2.count, dmat3x2.array_stride, dmat3x2.matrix_stride),
+                   (shade::Matrix(shade::ColumnMajor, 3, 2), 2, 48, 16));
+        let vec3 = describe_member("v", 0, None::<&PaddedVec3<f32>>, Some(4));
+        assert_eq!((vec3.array_stride, vec3.matrix_stride), (16, 0));
+    }
+
+    #[test]
+    fn test_check_block() {
+        let mut var = shade::BlockVar {
+            name: "Locals".to_string(),
+            size: 80,
+            usage: 1,
+            members: UniformBlock::get_members(None::<&Block>),
+        };
+        assert_eq!(check_block(None::<&BufferHandle<Block>>, &var), Ok(()));
+        // the matrix is declared as an array of its columns
+        *var.members.get_mut(0).unwrap() =
+            member("model", shade::BaseF32, shade::Vector(4), 4, 16);
+        assert_eq!(check_block(None::<&BufferHandle<Block>>, &var), Ok(()));
+        var.members.get_mut(0).unwrap().count = 3;
+        assert_eq!(check_block(None::<&BufferHandle<Block>>, &var),
+                   Err(BlockLayoutMismatch("Locals".to_string(), "model".to_string())));
+        var.members.get_mut(0).unwrap().count = 4;
+        var.members.get_mut(1).unwrap().offset = 68;
+        assert_eq!(check_block(None::<&BufferHandle<Block>>, &var),
+                   Err(BlockLayoutMismatch("Locals".to_string(), "time".to_string())));
+    }
+
+    fn dictionary(uniforms: &[(&str, f32)]) -> ParamDictionary {
+        ParamDictionary {
+            uniforms: uniforms.iter().map(|&(name, value)| NamedCell {
+                name: name.to_string(),
+                value: Cell::new(shade::ValueF32(value)),
+            }).collect(),
+            blocks: Vec::new(),
+            textures: Vec::new(),
+            images: Vec::new(),
+            storage_blocks: Vec::new(),
+        }
+    }
+
+    fn program(uniforms: &[&str]) -> shade::ProgramInfo {
+        shade::ProgramInfo {
+            attributes: Vec::new(),
+            uniforms: uniforms.iter().enumerate().map(|(i, name)| shade::UniformVar {
+                name: name.to_string(),
+                location: i as shade::Location,
+                count: 1,
+                base_type: shade::BaseF32,
+                container: shade::Single,
+            }).collect(),
+            blocks: Vec::new(),
+            textures: Vec::new(),
+            images: Vec::new(),
+            storage_blocks: Vec::new(),
+        }
+    }
+
+    fn fill_uniforms<L, T: ShaderParam<L>>(value: &T, link: &L) -> Vec<Option<shade::UniformValue>> {
+        let (mut uniforms, mut blocks, mut textures, mut images, mut storage_blocks) =
+            (Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        value.fill_params(link, ParamValues {
+            uniforms: &mut uniforms,
+            blocks: &mut blocks,
+            textures: &mut textures,
+            images: &mut images,
+            storage_blocks: &mut storage_blocks,
+        });
+        uniforms
+    }
+
+    #[test]
+    fn test_param_stack() {
+        let global = dictionary(&[("time", 1.0), ("scale", 2.0)]);
+        let object = dictionary(&[("scale", 3.0)]);
+        let layers = [&global, &object];
+        let stack = ParamStack { layers: layers.as_slice() };
+        let info = program(&["scale", "time"]);
+        let eager = ShaderParam::create_link(Some(&stack), &info).unwrap();
+        let lazy = ShaderParam::create_link(None::<&ParamStack>, &info).unwrap();
+        for link in [eager, lazy].iter() {
+            let uniforms = fill_uniforms(&stack, link);
+            match uniforms.as_slice() {
+                [Some(shade::ValueF32(scale)), Some(shade::ValueF32(time))] =>
+                    assert_eq!((scale, time), (3.0, 1.0)),
+                _ => panic!("Unexpected uniforms: {}", uniforms),
+            }
+        }
+        let missing = program(&["time", "offset"]);
+        assert_eq!(ShaderParam::create_link(Some(&object), &missing).err(),
+                   Some(MissingUniform("time".to_string())));
+        let link = ShaderParam::create_link(None::<&ParamDictionary>, &missing).unwrap();
+        let uniforms = fill_uniforms(&global, &link);
+        match uniforms.as_slice() {
+            [Some(shade::ValueF32(time)), None] => assert_eq!(time, 1.0),
+            _ => panic!("Unexpected uniforms: {}", uniforms),
         }
     }
+
+    #[test]
+    fn test_param_stack_shared_link() {
+        let global = dictionary(&[("time", 1.0), ("scale", 2.0)]);
+        let other = dictionary(&[("scale", 4.0), ("time", 5.0)]);
+        let object = dictionary(&[("scale", 3.0)]);
+        let first = [&global];
+        let second = [&other, &object];
+        let info = program(&["scale", "time"]);
+        let stack = ParamStack { layers: first.as_slice() };
+        let link = ShaderParam::create_link(Some(&stack), &info).unwrap();
+        // the cells are in another order, and one is overridden
+        let uniforms = fill_uniforms(&ParamStack { layers: second.as_slice() }, &link);
+        match uniforms.as_slice() {
+            [Some(shade::ValueF32(scale)), Some(shade::ValueF32(time))] =>
+                assert_eq!((scale, time), (3.0, 5.0)),
+            _ => panic!("Unexpected uniforms: {}", uniforms),
+        }
+        let uniforms = fill_uniforms(&stack, &link);
+        match uniforms.as_slice() {
+            [Some(shade::ValueF32(scale)), Some(shade::ValueF32(time))] =>
+                assert_eq!((scale, time), (2.0, 1.0)),
+            _ => panic!("Unexpected uniforms: {}", uniforms),
+        }
+    }
+
+    #[test]
+    fn test_is_array_element() {
+        assert!(is_array_element("lights[0]", "lights"));
+        assert!(is_array_element("lights[12]", "lights"));
+        assert!(!is_array_element("lights", "lights"));
+        assert!(!is_array_element("lights[]", "lights"));
+        assert!(!is_array_element("lights[0].colour", "lights"));
+        assert!(!is_array_element("lightsx[0]", "lights"));
+        assert!(!is_array_element("lights[i]", "lights"));
+    }
+
+    #[test]
+    fn test_link_uniform_array() {
+        let info = program(&["weights[0]", "weights[1]", "weights[2]", "weights"]);
+        let vars = info.uniforms.as_slice();
+        let link = link_uniform_array(None, &vars[0], 0, 2);
+        assert_eq!(link, Ok(UniformElements(0, 1)));
+        let link = link_uniform_array(link.ok(), &vars[1], 1, 2);
+        assert_eq!(link, Ok(UniformElements(0, 2)));
+        assert_eq!(link_uniform_array(link.ok(), &vars[2], 2, 2),
+                   Err(ArraySizeMismatch("weights[2]".to_string())));
+        // a scalar can't be made of the elements
+        assert_eq!(link_uniform_array(None, &vars[3], 3, 2),
+                   Err(ArraySizeMismatch("weights".to_string())));
+        let mut vector = vars[3].clone();
+        vector.container = shade::Vector(2);
+        assert_eq!(link_uniform_array(None, &vector, 3, 2), Ok(UniformComposite(3)));
+        assert_eq!(link_uniform_array(Some(UniformComposite(3)), &vars[0], 0, 2),
+                   Err(ArraySizeMismatch("weights[0]".to_string())));
+        let mut matrix = vars[3].clone();
+        matrix.container = shade::Matrix(shade::ColumnMajor, 4, 2);
+        assert_eq!(link_uniform_array(None, &matrix, 3, 4), Ok(UniformComposite(3)));
+        assert_eq!(link_uniform_array(None, &matrix, 3, 2),
+                   Err(ArraySizeMismatch("weights".to_string())));
+    }
+
+    #[test]
+    fn test_compose_uniform() {
+        assert_eq!(compose_uniform([shade::ValueU32(1), shade::ValueU32(2)]),
+                   Some(shade::ValueU32Vector2([1, 2])));
+        assert_eq!(compose_uniform([shade::ValueF64Vector2([1.0, 2.0]),
+                                    shade::ValueF64Vector2([3.0, 4.0]),
+                                    shade::ValueF64Vector2([5.0, 6.0])]),
+                   Some(shade::ValueF64Matrix3x2([[1.0, 2.0], [3.0, 4.0], [5.0, 6.0]])));
+        assert_eq!(compose_uniform([shade::ValueF32(1.0)]), None);
+        assert_eq!(compose_uniform([shade::ValueF32(1.0), shade::ValueI32(2)]), None);
+        assert_eq!(compose_uniform([shade::ValueF32Matrix2([[1.0, ..2], ..2]),
+                                    shade::ValueF32Matrix2([[2.0, ..2], ..2])]), None);
+    }
+
+    #[test]
+    fn test_fill_uniform_array() {
+        let values = [[1.0f32, ..4], [2.0, ..4], [3.0, ..4]];
+        let mut uniforms = Vec::new();
+        fill_uniform_array(values.as_slice(), UniformElements(1, 2), &mut uniforms);
+        assert_eq!(uniforms, vec![None, Some(shade::ValueF32Vector4([1.0, ..4])),
+                                  Some(shade::ValueF32Vector4([2.0, ..4]))]);
+        let mut uniforms = Vec::new();
+        fill_uniform_array(values.as_slice(), UniformComposite(0), &mut uniforms);
+        assert_eq!(uniforms, vec![Some(shade::ValueF32Matrix3x4([[1.0, ..4], [2.0, ..4], [3.0, ..4]]))]);
+    }
 }
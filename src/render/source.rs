@@ -0,0 +1,91 @@
+// Copyright 2014 The Gfx-rs Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Shader source produced by the preprocessor, for all the targets of a
+//! `ShaderSource` at once.
+
+use device::shade::ShaderSource;
+use preprocess::{FileSystem, PreprocessError, Preprocessor};
+
+/// Shader code produced by the preprocessor, owning one buffer per target.
+/// The SPIR-V binary is passed through untouched.
+#[deriving(Clone, PartialEq, Show)]
+pub struct ProcessedSource {
+    glsl_100_es: Option<Vec<u8>>,
+    glsl_300_es: Option<Vec<u8>>,
+    glsl_120: Option<Vec<u8>>,
+    glsl_130: Option<Vec<u8>>,
+    glsl_140: Option<Vec<u8>>,
+    glsl_150: Option<Vec<u8>>,
+    glsl_330: Option<Vec<u8>>,
+    glsl_400: Option<Vec<u8>>,
+    glsl_410: Option<Vec<u8>>,
+    glsl_420: Option<Vec<u8>>,
+    glsl_430: Option<Vec<u8>>,
+    glsl_440: Option<Vec<u8>>,
+    glsl_450: Option<Vec<u8>>,
+    spirv: Option<Vec<u8>>,
+}
+
+impl ProcessedSource {
+    /// Borrow the processed code, to be passed to `create_shader`.
+    pub fn as_source<'a>(&'a self) -> ShaderSource<'a> {
+        fn get<'a>(v: &'a Option<Vec<u8>>) -> Option<&'a [u8]> {
+            v.as_ref().map(|v| v.as_slice())
+        }
+        ShaderSource {
+            glsl_100_es: get(&self.glsl_100_es),
+            glsl_300_es: get(&self.glsl_300_es),
+            glsl_120: get(&self.glsl_120),
+            glsl_130: get(&self.glsl_130),
+            glsl_140: get(&self.glsl_140),
+            glsl_150: get(&self.glsl_150),
+            glsl_330: get(&self.glsl_330),
+            glsl_400: get(&self.glsl_400),
+            glsl_410: get(&self.glsl_410),
+            glsl_420: get(&self.glsl_420),
+            glsl_430: get(&self.glsl_430),
+            glsl_440: get(&self.glsl_440),
+            glsl_450: get(&self.glsl_450),
+            spirv: get(&self.spirv),
+        }
+    }
+}
+
+impl<F: FileSystem> Preprocessor<F> {
+    /// Process all the GLSL targets of the given source.
+    pub fn process_source(&self, src: &ShaderSource)
+                          -> Result<ProcessedSource, PreprocessError> {
+        let process = |s: Option<&[u8]>| match s {
+            Some(s) => self.process(s).map(|s| Some(s)),
+            None => Ok(None),
+        };
+        Ok(ProcessedSource {
+            glsl_100_es: try!(process(src.glsl_100_es)),
+            glsl_300_es: try!(process(src.glsl_300_es)),
+            glsl_120: try!(process(src.glsl_120)),
+            glsl_130: try!(process(src.glsl_130)),
+            glsl_140: try!(process(src.glsl_140)),
+            glsl_150: try!(process(src.glsl_150)),
+            glsl_330: try!(process(src.glsl_330)),
+            glsl_400: try!(process(src.glsl_400)),
+            glsl_410: try!(process(src.glsl_410)),
+            glsl_420: try!(process(src.glsl_420)),
+            glsl_430: try!(process(src.glsl_430)),
+            glsl_440: try!(process(src.glsl_440)),
+            glsl_450: try!(process(src.glsl_450)),
+            spirv: src.spirv.map(|s| s.to_vec()),
+        })
+    }
+}
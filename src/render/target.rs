@@ -22,12 +22,30 @@ pub enum Plane {
     /// Render to a `Surface` (corresponds to a renderbuffer in GL).
     PlaneSurface(device::SurfaceHandle),
     /// Render to a texture at a specific mipmap level
-    /// If `Layer` is set, it is selecting a single 2D slice of a given 3D texture
+    /// If `Layer` is set, it is selecting a single 2D slice of a given 3D texture,
+    /// or a face of a cube texture (see `Plane::cube_face`)
     PlaneTexture(device::TextureHandle, device::target::Level,
                  Option<device::target::Layer>),
 }
 
 impl Plane {
+    /// Render to a face of a cube texture, or of one of the cubes of a cube
+    /// array, at a specific mipmap level. Returns `None` if the texture is
+    /// not a cube or does not have that many cubes.
+    pub fn cube_face(texture: device::TextureHandle, level: device::target::Level,
+                     cube: u16, face: device::tex::CubeFace) -> Option<Plane> {
+        let cubes = match texture.get_info().kind {
+            device::tex::TextureCube => 1,
+            device::tex::TextureCubeArray => texture.get_info().depth,
+            _ => 0,
+        };
+        if cube < cubes {
+            Some(PlaneTexture(texture, level, Some(face.to_layer(cube))))
+        } else {
+            None
+        }
+    }
+
     /// Get the surface info
     pub fn get_surface_info(&self) -> device::tex::SurfaceInfo {
         match *self {
@@ -50,6 +68,9 @@ pub struct Frame {
     pub depth: Option<Plane>,
     /// The stencil buffer for this frame.
     pub stencil: Option<Plane>,
+    /// Convert the linear color values written by the shader into the sRGB
+    /// color space, for the color planes that have an sRGB format.
+    pub srgb: bool,
 }
 
 impl Frame {
@@ -62,6 +83,7 @@ impl Frame {
             colors: Vec::new(),
             depth: None,
             stencil: None,
+            srgb: false,
         }
     }
 
@@ -73,3 +95,37 @@ impl Frame {
         self.stencil.is_none()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use device;
+    use device::tex;
+    use super::{Plane, PlaneTexture};
+
+    #[test]
+    fn test_cube_face() {
+        let info = tex::TextureInfo {
+            width: 32,
+            height: 32,
+            depth: 2,
+            levels: 1,
+            kind: tex::TextureCubeArray,
+            format: tex::RGBA8,
+            swizzle: tex::IDENTITY_SWIZZLE,
+        };
+        let cubes = device::make_fake_texture(1, info);
+        assert_eq!(Plane::cube_face(cubes, 0, 1, tex::NegX),
+                   Some(PlaneTexture(cubes, 0, Some(7))));
+        assert_eq!(Plane::cube_face(cubes, 0, 2, tex::PosX), None);
+
+        let cube = device::make_fake_texture(2,
+            tex::TextureInfo { kind: tex::TextureCube, ..info });
+        assert_eq!(Plane::cube_face(cube, 0, 0, tex::NegZ),
+                   Some(PlaneTexture(cube, 0, Some(5))));
+        assert_eq!(Plane::cube_face(cube, 0, 1, tex::NegZ), None);
+
+        let flat = device::make_fake_texture(3,
+            tex::TextureInfo { kind: tex::Texture2D, ..info });
+        assert_eq!(Plane::cube_face(flat, 0, 0, tex::PosX), None);
+    }
+}
@@ -0,0 +1,1155 @@
+// Copyright 2014 The Gfx-rs Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Texture container and image loading. Parses DDS and KTX files, as well as
+//! PNG and TGA images, into a `TextureInfo` and the list of images that fill
+//! all of its levels, layers and faces.
+
+use std::cmp::max;
+use inflate;
+
+use device::attrib::{F16, F32, IntNormalized};
+use device::tex;
+
+/// An error occurring while parsing a texture container.
+#[deriving(Clone, PartialEq, Show)]
+pub enum LoadError {
+    /// The data does not start with a known container signature.
+    UnknownContainer,
+    /// The header contains invalid or inconsistent values.
+    InvalidHeader,
+    /// The texel format stored in the container is not supported.
+    UnsupportedFormat,
+    /// The combination of dimensions, layers and faces is not supported.
+    UnsupportedLayout,
+    /// The data ends before all the described images.
+    UnexpectedEnd,
+    /// The encoded image data could not be decoded.
+    CorruptData,
+}
+
+/// A single image of a texture, ready to be uploaded with `update_texture`.
+#[deriving(Clone, PartialEq, Show)]
+pub struct Image {
+    /// The region of the texture covered by the data.
+    pub info: tex::ImageInfo,
+    /// Tightly packed texel data.
+    pub data: Vec<u8>,
+}
+
+/// A texture description together with all of its images.
+#[deriving(Clone, PartialEq, Show)]
+pub struct TextureData {
+    /// Texture description to be passed into `create_texture`.
+    pub info: tex::TextureInfo,
+    /// Images for each mipmap level, array layer and cube face.
+    pub images: Vec<Image>,
+}
+
+impl TextureData {
+    /// Extend the texture description to the full mipmap chain. The levels
+    /// not covered by `images` are generated by the device after the upload.
+    pub fn with_mipmaps(mut self) -> TextureData {
+        self.info.levels = self.info.get_max_levels();
+        self
+    }
+
+    /// Get the number of mipmap levels that have their data provided.
+    pub fn get_provided_levels(&self) -> u8 {
+        self.images.iter().map(|im| im.info.mipmap + 1).max().unwrap_or(0)
+    }
+}
+
+/// Create the description of a single 2D image with uncompressed data.
+fn single_image(width: u16, height: u16, format: tex::Format, data: Vec<u8>) -> TextureData {
+    let info = tex::TextureInfo {
+        width: width,
+        height: height,
+        depth: 1,
+        levels: 1,
+        kind: tex::Texture2D,
+        format: format,
+        swizzle: tex::IDENTITY_SWIZZLE,
+    };
+    TextureData {
+        info: info,
+        images: vec![Image {
+            info: info.to_image_info(),
+            data: data,
+        }],
+    }
+}
+
+/// Get the normalized 8-bit format with the given number of components.
+fn unorm8_format(channels: uint) -> tex::Format {
+    let components = match channels {
+        1 => tex::R,
+        2 => tex::RG,
+        3 => tex::RGB,
+        _ => tex::RGBA,
+    };
+    tex::Unsigned(components, 8, IntNormalized)
+}
+
+fn read_u32(data: &[u8], offset: uint, swap: bool) -> u32 {
+    let b = data.slice(offset, offset + 4);
+    if swap {
+        b[3] as u32 | b[2] as u32 << 8 | b[1] as u32 << 16 | b[0] as u32 << 24
+    } else {
+        b[0] as u32 | b[1] as u32 << 8 | b[2] as u32 << 16 | b[3] as u32 << 24
+    }
+}
+
+fn to_u16(value: u32) -> Result<u16, LoadError> {
+    if value > 0xFFFF {
+        Err(InvalidHeader)
+    } else {
+        Ok(value as u16)
+    }
+}
+
+fn to_levels(value: u32) -> Result<u8, LoadError> {
+    match value {
+        0 => Ok(1),
+        1 ... 0xFF => Ok(value as u8),
+        _ => Err(InvalidHeader),
+    }
+}
+
+/// Get the region of a single layer and face of the given mipmap level.
+fn layer_image(info: &tex::TextureInfo, level: u8, layer: u16,
+               face: Option<tex::CubeFace>) -> tex::ImageInfo {
+    let mut img = info.to_image_info_level(level);
+    img.face = face;
+    match info.kind {
+        tex::Texture1DArray => {
+            img.yoffset = layer;
+            img.height = 1;
+        },
+        tex::Texture2DArray | tex::TextureCubeArray => {
+            img.zoffset = layer;
+            img.depth = 1;
+        },
+        _ => (),
+    }
+    img
+}
+
+fn faces_of(info: &tex::TextureInfo) -> Vec<Option<tex::CubeFace>> {
+    match info.kind {
+        tex::TextureCube | tex::TextureCubeArray =>
+            tex::CUBE_FACES.iter().map(|&f| Some(f)).collect(),
+        _ => vec![None],
+    }
+}
+
+fn layers_of(info: &tex::TextureInfo) -> u16 {
+    match info.kind {
+        tex::Texture1DArray => info.height,
+        tex::Texture2DArray | tex::TextureCubeArray => info.depth,
+        _ => 1,
+    }
+}
+
+/// Parse a texture container or an image, detecting its type from the
+/// signature. TGA images have no signature and need to be loaded with
+/// `load_tga` directly.
+pub fn load(data: &[u8]) -> Result<TextureData, LoadError> {
+    if data.starts_with(KTX_MAGIC) {
+        load_ktx(data)
+    } else if data.starts_with(PNG_SIGNATURE) {
+        load_png(data)
+    } else if data.len() >= 4 && read_u32(data, 0, false) == DDS_MAGIC {
+        load_dds(data)
+    } else {
+        Err(UnknownContainer)
+    }
+}
+
+const DDS_MAGIC: u32 = 0x20534444; // "DDS "
+const DDS_HEADER_SIZE: u32 = 124;
+const DDS_DATA_OFFSET: uint = 128;
+const DDS_DX10_DATA_OFFSET: uint = 148;
+
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDSD_DEPTH: u32 = 0x800000;
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_LUMINANCE: u32 = 0x20000;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_VOLUME: u32 = 0x200000;
+
+const FOURCC_DXT1: u32 = 0x31545844;
+const FOURCC_DXT3: u32 = 0x33545844;
+const FOURCC_DXT5: u32 = 0x35545844;
+const FOURCC_ATI1: u32 = 0x31495441;
+const FOURCC_ATI2: u32 = 0x32495441;
+const FOURCC_BC4U: u32 = 0x55344342;
+const FOURCC_BC4S: u32 = 0x53344342;
+const FOURCC_BC5U: u32 = 0x55354342;
+const FOURCC_BC5S: u32 = 0x53354342;
+const FOURCC_DX10: u32 = 0x30315844;
+
+const D3D10_RESOURCE_DIMENSION_TEXTURE1D: u32 = 2;
+const D3D10_RESOURCE_DIMENSION_TEXTURE3D: u32 = 4;
+const D3D10_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+/// Map a DXGI format to a texel format, also returning whether the red
+/// and blue components need to be swapped, and whether the alpha component
+/// is unused and needs to be made opaque.
+fn dxgi_to_format(dxgi: u32) -> Result<(tex::Format, bool, bool), LoadError> {
+    Ok((match dxgi {
+        2  => tex::Float(tex::RGBA, F32),
+        6  => tex::Float(tex::RGB, F32),
+        10 => tex::Float(tex::RGBA, F16),
+        11 => tex::Unsigned(tex::RGBA, 16, IntNormalized),
+        16 => tex::Float(tex::RG, F32),
+        24 => tex::RGB10A2,
+        26 => tex::R11FG11FB10F,
+        28 => tex::RGBA8,
+        29 => tex::SRGB8_A8,
+        34 => tex::Float(tex::RG, F16),
+        41 => tex::Float(tex::R, F32),
+        49 => tex::Unsigned(tex::RG, 8, IntNormalized),
+        54 => tex::Float(tex::R, F16),
+        56 => tex::Unsigned(tex::R, 16, IntNormalized),
+        61 => tex::Unsigned(tex::R, 8, IntNormalized),
+        67 => tex::RGB9E5,
+        71 => tex::Compressed(tex::BC1_RGBA),
+        74 => tex::Compressed(tex::BC2_RGBA),
+        77 => tex::Compressed(tex::BC3_RGBA),
+        80 => tex::Compressed(tex::BC4_R),
+        81 => tex::Compressed(tex::BC4_R_SIGNED),
+        83 => tex::Compressed(tex::BC5_RG),
+        84 => tex::Compressed(tex::BC5_RG_SIGNED),
+        87 => return Ok((tex::RGBA8, true, false)),
+        88 => return Ok((tex::RGBA8, true, true)),
+        95 => tex::Compressed(tex::BC6H_RGB_UFLOAT),
+        96 => tex::Compressed(tex::BC6H_RGB_SFLOAT),
+        98 => tex::Compressed(tex::BC7_RGBA),
+        99 => tex::Compressed(tex::BC7_SRGBA),
+        _ => return Err(UnsupportedFormat),
+    }, false, false))
+}
+
+/// Map a legacy DDS pixel format to a texel format, also returning whether
+/// the red and blue components need to be swapped, and whether the alpha
+/// component is unused and needs to be made opaque.
+fn dds_pixel_format(data: &[u8]) -> Result<(tex::Format, bool, bool), LoadError> {
+    let flags = read_u32(data, 80, false);
+    if flags & DDPF_FOURCC != 0 {
+        let format = match read_u32(data, 84, false) {
+            FOURCC_DXT1 => tex::BC1_RGBA,
+            FOURCC_DXT3 => tex::BC2_RGBA,
+            FOURCC_DXT5 => tex::BC3_RGBA,
+            FOURCC_ATI1 | FOURCC_BC4U => tex::BC4_R,
+            FOURCC_BC4S => tex::BC4_R_SIGNED,
+            FOURCC_ATI2 | FOURCC_BC5U => tex::BC5_RG,
+            FOURCC_BC5S => tex::BC5_RG_SIGNED,
+            _ => return Err(UnsupportedFormat),
+        };
+        return Ok((tex::Compressed(format), false, false))
+    }
+    let bits = read_u32(data, 88, false);
+    let masks = (read_u32(data, 92, false), read_u32(data, 96, false), read_u32(data, 100, false));
+    if flags & DDPF_RGB != 0 {
+        // without an alpha mask, the fourth byte of a 32-bit texel is padding
+        let opaque = flags & DDPF_ALPHAPIXELS == 0;
+        let rgb = tex::Unsigned(tex::RGB, 8, IntNormalized);
+        match (bits, masks) {
+            (32, (0xFF, 0xFF00, 0xFF0000)) => Ok((tex::RGBA8, false, opaque)),
+            (32, (0xFF0000, 0xFF00, 0xFF)) => Ok((tex::RGBA8, true, opaque)),
+            (24, (0xFF, 0xFF00, 0xFF0000)) => Ok((rgb, false, false)),
+            (24, (0xFF0000, 0xFF00, 0xFF)) => Ok((rgb, true, false)),
+            _ => Err(UnsupportedFormat),
+        }
+    } else if flags & DDPF_LUMINANCE != 0 && bits == 8 {
+        Ok((tex::Unsigned(tex::R, 8, IntNormalized), false, false))
+    } else {
+        Err(UnsupportedFormat)
+    }
+}
+
+/// Swap the red and blue components of every texel.
+fn swap_red_blue(data: &mut [u8], format: tex::Format) {
+    let size = format.get_size().unwrap();
+    for texel in data.chunks_mut(size) {
+        texel.swap(0, 2);
+    }
+}
+
+/// Set the alpha component of every RGBA8 texel to one.
+fn set_opaque(data: &mut [u8]) {
+    for texel in data.chunks_mut(4) {
+        texel[3] = 0xFF;
+    }
+}
+
+/// Parse a DirectDraw Surface, including the DX10 header extension.
+pub fn load_dds(data: &[u8]) -> Result<TextureData, LoadError> {
+    if data.len() < DDS_DATA_OFFSET {
+        return Err(UnexpectedEnd)
+    }
+    if read_u32(data, 0, false) != DDS_MAGIC {
+        return Err(UnknownContainer)
+    }
+    if read_u32(data, 4, false) != DDS_HEADER_SIZE {
+        return Err(InvalidHeader)
+    }
+
+    let flags = read_u32(data, 8, false);
+    let height = try!(to_u16(read_u32(data, 12, false)));
+    let width = try!(to_u16(read_u32(data, 16, false)));
+    let depth = if flags & DDSD_DEPTH != 0 {
+        try!(to_u16(read_u32(data, 24, false)))
+    } else {
+        1
+    };
+    let levels = if flags & DDSD_MIPMAPCOUNT != 0 {
+        try!(to_levels(read_u32(data, 28, false)))
+    } else {
+        1
+    };
+    let caps2 = read_u32(data, 112, false);
+
+    let (format, swap, opaque, kind, layers, mut offset) =
+        if read_u32(data, 80, false) & DDPF_FOURCC != 0 &&
+            read_u32(data, 84, false) == FOURCC_DX10 {
+        if data.len() < DDS_DX10_DATA_OFFSET {
+            return Err(UnexpectedEnd)
+        }
+        let (format, swap, opaque) = try!(dxgi_to_format(read_u32(data, 128, false)));
+        let dimension = read_u32(data, 132, false);
+        let misc = read_u32(data, 136, false);
+        let layers = max(1, try!(to_u16(read_u32(data, 140, false))));
+        let kind = if misc & D3D10_RESOURCE_MISC_TEXTURECUBE != 0 {
+            if layers > 1 { tex::TextureCubeArray } else { tex::TextureCube }
+        } else if dimension == D3D10_RESOURCE_DIMENSION_TEXTURE3D {
+            tex::Texture3D
+        } else if dimension == D3D10_RESOURCE_DIMENSION_TEXTURE1D {
+            if layers > 1 { tex::Texture1DArray } else { tex::Texture1D }
+        } else {
+            if layers > 1 { tex::Texture2DArray } else { tex::Texture2D }
+        };
+        (format, swap, opaque, kind, layers, DDS_DX10_DATA_OFFSET)
+    } else {
+        let (format, swap, opaque) = try!(dds_pixel_format(data));
+        let kind = if caps2 & DDSCAPS2_CUBEMAP != 0 {
+            tex::TextureCube
+        } else if caps2 & DDSCAPS2_VOLUME != 0 {
+            tex::Texture3D
+        } else {
+            tex::Texture2D
+        };
+        (format, swap, opaque, kind, 1, DDS_DATA_OFFSET)
+    };
+
+    let info = tex::TextureInfo {
+        width: width,
+        height: match kind {
+            tex::Texture1DArray => layers,
+            _ => height,
+        },
+        depth: match kind {
+            tex::Texture2DArray | tex::TextureCubeArray => layers,
+            tex::Texture3D => depth,
+            _ => 1,
+        },
+        levels: levels,
+        kind: kind,
+        format: format,
+        swizzle: tex::IDENTITY_SWIZZLE,
+    };
+
+    // DDS stores the full mipmap chain of each layer and face contiguously
+    let mut images = Vec::new();
+    for layer in range(0, layers) {
+        for &face in faces_of(&info).iter() {
+            for level in range(0, levels) {
+                let img = layer_image(&info, level, layer, face);
+                let size = img.get_data_size();
+                if offset + size > data.len() {
+                    return Err(UnexpectedEnd)
+                }
+                let mut bytes = data.slice(offset, offset + size).to_vec();
+                if swap {
+                    swap_red_blue(bytes.as_mut_slice(), format);
+                }
+                if opaque {
+                    set_opaque(bytes.as_mut_slice());
+                }
+                offset += size;
+                images.push(Image {
+                    info: img,
+                    data: bytes,
+                });
+            }
+        }
+    }
+
+    Ok(TextureData {
+        info: info,
+        images: images,
+    })
+}
+
+static KTX_MAGIC: &'static [u8] = &[
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x31, 0x31, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A
+];
+const KTX_ENDIANNESS: u32 = 0x04030201;
+const KTX_ENDIANNESS_SWAPPED: u32 = 0x01020304;
+const KTX_HEADER_SIZE: uint = 64;
+
+/// Map a GL internal format, as stored in KTX, to a texel format.
+fn gl_internal_to_format(internal: u32) -> Result<tex::Format, LoadError> {
+    // ASTC formats come in two runs of 14 consecutive values
+    static ASTC_BLOCKS: [(u8, u8), ..14] = [
+        (4, 4), (5, 4), (5, 5), (6, 5), (6, 6), (8, 5), (8, 6),
+        (8, 8), (10, 5), (10, 6), (10, 8), (10, 10), (12, 10), (12, 12),
+    ];
+    Ok(match internal {
+        0x8229 => tex::Unsigned(tex::R, 8, IntNormalized),
+        0x822B => tex::Unsigned(tex::RG, 8, IntNormalized),
+        0x8051 => tex::Unsigned(tex::RGB, 8, IntNormalized),
+        0x8058 => tex::RGBA8,
+        0x822A => tex::Unsigned(tex::R, 16, IntNormalized),
+        0x822C => tex::Unsigned(tex::RG, 16, IntNormalized),
+        0x805B => tex::Unsigned(tex::RGBA, 16, IntNormalized),
+        0x822D => tex::Float(tex::R, F16),
+        0x822F => tex::Float(tex::RG, F16),
+        0x881B => tex::Float(tex::RGB, F16),
+        0x881A => tex::Float(tex::RGBA, F16),
+        0x822E => tex::Float(tex::R, F32),
+        0x8230 => tex::Float(tex::RG, F32),
+        0x8815 => tex::Float(tex::RGB, F32),
+        0x8814 => tex::Float(tex::RGBA, F32),
+        0x8059 => tex::RGB10A2,
+        0x8C3A => tex::R11FG11FB10F,
+        0x8C3D => tex::RGB9E5,
+        0x8C41 => tex::SRGB8,
+        0x8C43 => tex::SRGB8_A8,
+        0x9274 => tex::Compressed(tex::ETC2_RGB),
+        0x9275 => tex::Compressed(tex::ETC2_SRGB),
+        0x9278 => tex::Compressed(tex::ETC2_EAC_RGBA8),
+        0x83F0 => tex::Compressed(tex::BC1_RGB),
+        0x83F1 => tex::Compressed(tex::BC1_RGBA),
+        0x83F2 => tex::Compressed(tex::BC2_RGBA),
+        0x83F3 => tex::Compressed(tex::BC3_RGBA),
+        0x8DBB => tex::Compressed(tex::BC4_R),
+        0x8DBC => tex::Compressed(tex::BC4_R_SIGNED),
+        0x8DBD => tex::Compressed(tex::BC5_RG),
+        0x8DBE => tex::Compressed(tex::BC5_RG_SIGNED),
+        0x8E8C => tex::Compressed(tex::BC7_RGBA),
+        0x8E8D => tex::Compressed(tex::BC7_SRGBA),
+        0x8E8E => tex::Compressed(tex::BC6H_RGB_SFLOAT),
+        0x8E8F => tex::Compressed(tex::BC6H_RGB_UFLOAT),
+        0x93B0 ... 0x93BD => {
+            let (w, h) = ASTC_BLOCKS[(internal - 0x93B0) as uint];
+            tex::Compressed(tex::ASTC_RGBA(w, h))
+        },
+        0x93D0 ... 0x93DD => {
+            let (w, h) = ASTC_BLOCKS[(internal - 0x93D0) as uint];
+            tex::Compressed(tex::ASTC_SRGBA(w, h))
+        },
+        _ => return Err(UnsupportedFormat),
+    })
+}
+
+/// Copy an uncompressed KTX image, dropping the 4-byte row alignment and
+/// converting the components to the native byte order.
+fn unpack_ktx_rows(src: &[u8], img: &tex::ImageInfo, type_size: uint, swap: bool) -> Vec<u8> {
+    let row = img.width as uint * img.format.get_size().unwrap();
+    let stride = (row + 3) & !3;
+    let rows = img.height as uint * img.depth as uint;
+    let mut data = Vec::with_capacity(row * rows);
+    for i in range(0, rows) {
+        data.push_all(src.slice(i * stride, i * stride + row));
+    }
+    if swap && type_size > 1 {
+        for value in data.as_mut_slice().chunks_mut(type_size) {
+            value.reverse();
+        }
+    }
+    data
+}
+
+/// Parse a Khronos texture container (KTX 1.1).
+pub fn load_ktx(data: &[u8]) -> Result<TextureData, LoadError> {
+    if !data.starts_with(KTX_MAGIC) {
+        return Err(UnknownContainer)
+    }
+    if data.len() < KTX_HEADER_SIZE {
+        return Err(UnexpectedEnd)
+    }
+    let swap = match read_u32(data, 12, false) {
+        KTX_ENDIANNESS => false,
+        KTX_ENDIANNESS_SWAPPED => true,
+        _ => return Err(InvalidHeader),
+    };
+    let field = |i: uint| read_u32(data, 16 + 4 * i, swap);
+
+    let type_size = field(1) as uint;
+    let format = try!(gl_internal_to_format(field(3)));
+    let width = try!(to_u16(field(5)));
+    let height = try!(to_u16(field(6)));
+    let depth = try!(to_u16(field(7)));
+    let layers = try!(to_u16(field(8)));
+    let faces = field(9);
+    let levels = try!(to_levels(field(10)));
+    let key_value_size = field(11) as uint;
+
+    if width == 0 || (depth > 0 && height == 0) {
+        return Err(InvalidHeader)
+    }
+    let kind = match (faces, layers, height, depth) {
+        (6, 0, _, 0) => tex::TextureCube,
+        (6, _, _, 0) => tex::TextureCubeArray,
+        (1, 0, 0, 0) => tex::Texture1D,
+        (1, 0, _, 0) => tex::Texture2D,
+        (1, 0, _, _) => tex::Texture3D,
+        (1, _, 0, 0) => tex::Texture1DArray,
+        (1, _, _, 0) => tex::Texture2DArray,
+        (1, _, _, _) | (6, _, _, _) => return Err(UnsupportedLayout),
+        _ => return Err(InvalidHeader),
+    };
+
+    let info = tex::TextureInfo {
+        width: width,
+        height: match kind {
+            tex::Texture1DArray => layers,
+            _ => max(height, 1),
+        },
+        depth: match kind {
+            tex::Texture2DArray | tex::TextureCubeArray => layers,
+            _ => max(depth, 1),
+        },
+        levels: levels,
+        kind: kind,
+        format: format,
+        swizzle: tex::IDENTITY_SWIZZLE,
+    };
+
+    // KTX stores all layers and faces of a mipmap level contiguously,
+    // each level prefixed with its size
+    let mut offset = KTX_HEADER_SIZE + key_value_size;
+    let mut images = Vec::new();
+    for level in range(0, levels) {
+        if offset + 4 > data.len() {
+            return Err(UnexpectedEnd)
+        }
+        offset += 4;
+        for layer in range(0, layers_of(&info)) {
+            for &face in faces_of(&info).iter() {
+                let img = layer_image(&info, level, layer, face);
+                let size = match format.get_size() {
+                    Some(texel) => {
+                        let row = (img.width as uint * texel + 3) & !3;
+                        row * img.height as uint * img.depth as uint
+                    },
+                    None => img.get_data_size(),
+                };
+                if offset + size > data.len() {
+                    return Err(UnexpectedEnd)
+                }
+                let src = data.slice(offset, offset + size);
+                let bytes = if format.is_compressed() {
+                    src.to_vec()
+                } else {
+                    unpack_ktx_rows(src, &img, type_size, swap)
+                };
+                // cube and mip padding both align to 4 bytes
+                offset = (offset + size + 3) & !3;
+                images.push(Image {
+                    info: img,
+                    data: bytes,
+                });
+            }
+        }
+    }
+
+    Ok(TextureData {
+        info: info,
+        images: images,
+    })
+}
+
+static PNG_SIGNATURE: &'static [u8] = &[137, 80, 78, 71, 13, 10, 26, 10];
+
+const PNG_IHDR: u32 = 0x49484452;
+const PNG_PLTE: u32 = 0x504C5445;
+const PNG_TRNS: u32 = 0x74524E53;
+const PNG_IDAT: u32 = 0x49444154;
+const PNG_IEND: u32 = 0x49454E44;
+
+const PNG_GRAY: u8 = 0;
+const PNG_RGB: u8 = 2;
+const PNG_PALETTE: u8 = 3;
+const PNG_GRAY_ALPHA: u8 = 4;
+const PNG_RGBA: u8 = 6;
+
+/// Offsets and steps of the Adam7 interlacing passes: (x0, y0, dx, dy).
+static ADAM7: [(uint, uint, uint, uint), ..7] = [
+    (0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4),
+    (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2),
+];
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc { a } else if pb <= pc { b } else { c }
+}
+
+/// Reverse the filtering of a single scanline, given the previous one.
+fn unfilter(filter: u8, row: &mut [u8], prev: &[u8], bpp: uint) -> Result<(), LoadError> {
+    for i in range(0, row.len()) {
+        let left = if i >= bpp { row[i - bpp] } else { 0 };
+        let upper_left = if i >= bpp { prev[i - bpp] } else { 0 };
+        row[i] += match filter {
+            0 => 0,
+            1 => left,
+            2 => prev[i],
+            3 => ((left as u16 + prev[i] as u16) / 2) as u8,
+            4 => paeth(left, prev[i], upper_left),
+            _ => return Err(CorruptData),
+        };
+    }
+    Ok(())
+}
+
+/// Convert 16-bit big endian samples into the native byte order.
+fn native_u16(data: &mut [u8]) {
+    if cfg!(target_endian = "little") {
+        for sample in data.chunks_mut(2) {
+            sample.swap(0, 1);
+        }
+    }
+}
+
+/// Decode a PNG image, including interlaced and paletted ones.
+pub fn load_png(data: &[u8]) -> Result<TextureData, LoadError> {
+    if !data.starts_with(PNG_SIGNATURE) {
+        return Err(UnknownContainer)
+    }
+
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut transparency: &[u8] = &[];
+    let mut compressed = Vec::new();
+    let mut offset = PNG_SIGNATURE.len();
+    loop {
+        if offset + 8 > data.len() {
+            return Err(UnexpectedEnd)
+        }
+        let length = read_u32(data, offset, true) as uint;
+        let kind = read_u32(data, offset + 4, true);
+        let start = offset + 8;
+        // skip the CRC at the end of the chunk
+        if start + length + 4 > data.len() {
+            return Err(UnexpectedEnd)
+        }
+        let chunk = data.slice(start, start + length);
+        offset = start + length + 4;
+        match kind {
+            PNG_IHDR if length == 13 => header = Some(chunk),
+            PNG_IHDR => return Err(InvalidHeader),
+            PNG_PLTE => palette = chunk,
+            PNG_TRNS => transparency = chunk,
+            PNG_IDAT => compressed.push_all(chunk),
+            PNG_IEND => break,
+            _ => (),
+        }
+    }
+
+    let header = match header {
+        Some(h) => h,
+        None => return Err(InvalidHeader),
+    };
+    let width = try!(to_u16(read_u32(header, 0, true)));
+    let height = try!(to_u16(read_u32(header, 4, true)));
+    let depth = header[8] as uint;
+    let color = header[9];
+    let interlaced = match header[12] {
+        0 => false,
+        1 => true,
+        _ => return Err(InvalidHeader),
+    };
+    let mut channels = match (color, depth) {
+        (PNG_GRAY, 1) | (PNG_GRAY, 2) | (PNG_GRAY, 4) |
+        (PNG_GRAY, 8) | (PNG_GRAY, 16) => 1u,
+        (PNG_PALETTE, 1) | (PNG_PALETTE, 2) | (PNG_PALETTE, 4) | (PNG_PALETTE, 8) => 1,
+        (PNG_GRAY_ALPHA, 8) | (PNG_GRAY_ALPHA, 16) => 2,
+        (PNG_RGB, 8) | (PNG_RGB, 16) => 3,
+        (PNG_RGBA, 8) | (PNG_RGBA, 16) => 4,
+        _ => return Err(UnsupportedFormat),
+    };
+    if width == 0 || height == 0 || header[10] != 0 || header[11] != 0 {
+        return Err(InvalidHeader)
+    }
+
+    let raw = match inflate::inflate_zlib(compressed.as_slice()) {
+        Some(raw) => raw,
+        None => return Err(CorruptData),
+    };
+    let raw = raw.as_slice();
+
+    let (w, h) = (width as uint, height as uint);
+    let bits = channels * depth;
+    let filter_bpp = max(1, bits / 8);
+    let sample_bytes = if depth == 16 { 2 } else { 1 };
+    let pixel_bytes = channels * sample_bytes;
+    let max_sample = (1u << depth) - 1;
+    let mut pixels = Vec::from_elem(w * h * pixel_bytes, 0u8);
+
+    let whole = [(0u, 0u, 1u, 1u)];
+    let passes = if interlaced { ADAM7.as_slice() } else { whole.as_slice() };
+    let mut pos = 0u;
+    for &(x0, y0, dx, dy) in passes.iter() {
+        if x0 >= w || y0 >= h {
+            continue
+        }
+        let pass_w = (w - x0 + dx - 1) / dx;
+        let pass_h = (h - y0 + dy - 1) / dy;
+        let stride = (pass_w * bits + 7) / 8;
+        if pos + pass_h * (stride + 1) > raw.len() {
+            return Err(CorruptData)
+        }
+        let mut prev = Vec::from_elem(stride, 0u8);
+        for y in range(0, pass_h) {
+            let filter = raw[pos];
+            let mut row = raw.slice(pos + 1, pos + 1 + stride).to_vec();
+            pos += stride + 1;
+            try!(unfilter(filter, row.as_mut_slice(), prev.as_slice(), filter_bpp));
+            for x in range(0, pass_w) {
+                let dst = ((y0 + y * dy) * w + x0 + x * dx) * pixel_bytes;
+                if depth >= 8 {
+                    let src = row.slice(x * pixel_bytes, (x + 1) * pixel_bytes);
+                    for (d, s) in pixels.slice_mut(dst, dst + pixel_bytes).iter_mut().zip(src.iter()) {
+                        *d = *s;
+                    }
+                } else {
+                    let bit = x * depth;
+                    let shift = 8 - depth - bit % 8;
+                    let value = (row[bit / 8] as uint >> shift) & max_sample;
+                    pixels[dst] = if color == PNG_GRAY {
+                        (value * 0xFF / max_sample) as u8
+                    } else {
+                        value as u8
+                    };
+                }
+            }
+            prev = row;
+        }
+    }
+
+    // gray and RGB images may have a single transparent color
+    if (color == PNG_GRAY || color == PNG_RGB) && !transparency.is_empty() {
+        if transparency.len() < 2 * channels {
+            return Err(CorruptData)
+        }
+        let key: Vec<u8> = if depth == 16 {
+            transparency.slice_to(2 * channels).to_vec()
+        } else {
+            range(0, channels).map(|c| {
+                let value = (transparency[2 * c] as uint << 8 | transparency[2 * c + 1] as uint) &
+                    max_sample;
+                (value * 0xFF / max_sample) as u8
+            }).collect()
+        };
+        let mut keyed = Vec::with_capacity(w * h * (pixel_bytes + sample_bytes));
+        for pixel in pixels.chunks(pixel_bytes) {
+            keyed.push_all(pixel);
+            let alpha = if pixel == key.as_slice() { 0 } else { 0xFF };
+            keyed.grow(sample_bytes, alpha);
+        }
+        pixels = keyed;
+        channels += 1;
+    }
+
+    if depth == 16 {
+        native_u16(pixels.as_mut_slice());
+    }
+
+    if color == PNG_PALETTE {
+        let entries = palette.len() / 3;
+        let out_channels = if transparency.is_empty() { 3 } else { 4 };
+        let mut expanded = Vec::with_capacity(w * h * out_channels);
+        for &index in pixels.iter() {
+            let i = index as uint;
+            if i >= entries {
+                return Err(CorruptData)
+            }
+            expanded.push_all(palette.slice(i * 3, i * 3 + 3));
+            if out_channels == 4 {
+                expanded.push(if i < transparency.len() { transparency[i] } else { 0xFF });
+            }
+        }
+        return Ok(single_image(width, height, unorm8_format(out_channels), expanded))
+    }
+
+    let format = if depth == 16 {
+        let components = match channels {
+            1 => tex::R,
+            2 => tex::RG,
+            3 => tex::RGB,
+            _ => tex::RGBA,
+        };
+        tex::Unsigned(components, 16, IntNormalized)
+    } else {
+        unorm8_format(channels)
+    };
+    Ok(single_image(width, height, format, pixels))
+}
+
+const TGA_HEADER_SIZE: uint = 18;
+const TGA_COLOR_MAPPED: u8 = 1;
+const TGA_TRUE_COLOR: u8 = 2;
+const TGA_GRAY: u8 = 3;
+const TGA_RLE: u8 = 8;
+const TGA_RIGHT_TO_LEFT: u8 = 0x10;
+const TGA_TOP_TO_BOTTOM: u8 = 0x20;
+
+fn read_u16_le(data: &[u8], offset: uint) -> u16 {
+    data[offset] as u16 | data[offset + 1] as u16 << 8
+}
+
+/// Get the number of output components of a TGA color value.
+fn tga_channels(bits: u8, gray: bool, alpha_bits: u8) -> Result<uint, LoadError> {
+    match (gray, bits) {
+        (true, 8) => Ok(1),
+        (true, 16) => Ok(2),
+        (false, 15) => Ok(3),
+        (false, 16) => Ok(if alpha_bits > 0 { 4 } else { 3 }),
+        (false, 24) => Ok(3),
+        (false, 32) => Ok(4),
+        _ => Err(UnsupportedFormat),
+    }
+}
+
+/// Convert a single TGA color value into its R, RG, RGB or RGBA components.
+fn tga_color(src: &[u8], gray: bool, channels: uint, out: &mut [u8]) {
+    if gray {
+        for i in range(0, channels) {
+            out[i] = src[i];
+        }
+    } else if src.len() == 2 {
+        let value = read_u16_le(src, 0);
+        let expand = |v: u16| -> u8 {
+            let v = (v & 0x1F) as u8;
+            (v << 3) | (v >> 2)
+        };
+        out[0] = expand(value >> 10);
+        out[1] = expand(value >> 5);
+        out[2] = expand(value);
+        if channels == 4 {
+            out[3] = if value & 0x8000 != 0 { 0xFF } else { 0 };
+        }
+    } else {
+        out[0] = src[2];
+        out[1] = src[1];
+        out[2] = src[0];
+        if channels == 4 {
+            out[3] = src[3];
+        }
+    }
+}
+
+/// Decode a TGA image, including color-mapped and run-length encoded ones.
+pub fn load_tga(data: &[u8]) -> Result<TextureData, LoadError> {
+    if data.len() < TGA_HEADER_SIZE {
+        return Err(UnexpectedEnd)
+    }
+    let id_length = data[0] as uint;
+    let has_map = data[1] == 1;
+    let image_type = data[2];
+    let map_first = read_u16_le(data, 3) as uint;
+    let map_length = read_u16_le(data, 5) as uint;
+    let map_bits = data[7];
+    let width = read_u16_le(data, 12);
+    let height = read_u16_le(data, 14);
+    let bits = data[16];
+    let descriptor = data[17];
+    let alpha_bits = descriptor & 0xF;
+
+    let base_type = image_type & !TGA_RLE;
+    let gray = base_type == TGA_GRAY;
+    let channels = match base_type {
+        TGA_COLOR_MAPPED if has_map && (bits == 8 || bits == 16) =>
+            try!(tga_channels(map_bits, false, alpha_bits)),
+        TGA_TRUE_COLOR | TGA_GRAY => try!(tga_channels(bits, gray, alpha_bits)),
+        _ => return Err(UnsupportedFormat),
+    };
+    if width == 0 || height == 0 {
+        return Err(InvalidHeader)
+    }
+
+    let mut offset = TGA_HEADER_SIZE + id_length;
+    let mut map = Vec::new();
+    if has_map {
+        let entry_bytes = (map_bits as uint + 7) / 8;
+        let size = map_length * entry_bytes;
+        if offset + size > data.len() {
+            return Err(UnexpectedEnd)
+        }
+        // only color-mapped images use the map, the others just skip it
+        if base_type == TGA_COLOR_MAPPED {
+            // 15, 16, 24 or 32 bits, as checked by `tga_channels`
+            let expected = match map_bits { 15 | 16 => 2, _ => channels };
+            if entry_bytes != expected {
+                return Err(UnsupportedFormat)
+            }
+            map = Vec::from_elem(map_length * channels, 0u8);
+            for (src, dst) in data.slice(offset, offset + size).chunks(entry_bytes)
+                                  .zip(map.as_mut_slice().chunks_mut(channels)) {
+                tga_color(src, false, channels, dst);
+            }
+        }
+        offset += size;
+    }
+
+    // unpack the raw pixel values, expanding run-length packets
+    let (w, h) = (width as uint, height as uint);
+    let pixel_bytes = (bits as uint + 7) / 8;
+    let total = w * h * pixel_bytes;
+    let mut raw = Vec::with_capacity(total);
+    if image_type & TGA_RLE != 0 {
+        while raw.len() < total {
+            if offset >= data.len() {
+                return Err(UnexpectedEnd)
+            }
+            let packet = data[offset];
+            let count = (packet & 0x7F) as uint + 1;
+            offset += 1;
+            let size = if packet & 0x80 != 0 { pixel_bytes } else { count * pixel_bytes };
+            if offset + size > data.len() || raw.len() + count * pixel_bytes > total {
+                return Err(CorruptData)
+            }
+            let src = data.slice(offset, offset + size);
+            if packet & 0x80 != 0 {
+                for _ in range(0, count) {
+                    raw.push_all(src);
+                }
+            } else {
+                raw.push_all(src);
+            }
+            offset += size;
+        }
+    } else {
+        if offset + total > data.len() {
+            return Err(UnexpectedEnd)
+        }
+        raw.push_all(data.slice(offset, offset + total));
+    }
+
+    // convert into rows stored from top to bottom, left to right
+    let mut pixels = Vec::from_elem(w * h * channels, 0u8);
+    for (i, src) in raw.as_slice().chunks(pixel_bytes).enumerate() {
+        let (mut x, mut y) = (i % w, i / w);
+        if descriptor & TGA_RIGHT_TO_LEFT != 0 {
+            x = w - 1 - x;
+        }
+        if descriptor & TGA_TOP_TO_BOTTOM == 0 {
+            y = h - 1 - y;
+        }
+        let dst = (y * w + x) * channels;
+        let out = pixels.slice_mut(dst, dst + channels);
+        if base_type == TGA_COLOR_MAPPED {
+            let index = if pixel_bytes == 2 { read_u16_le(src, 0) as uint } else { src[0] as uint };
+            if index < map_first || index - map_first >= map_length {
+                return Err(CorruptData)
+            }
+            let entry = (index - map_first) * channels;
+            for (d, s) in out.iter_mut().zip(map.slice(entry, entry + channels).iter()) {
+                *d = *s;
+            }
+        } else {
+            tga_color(src, gray, channels, out);
+        }
+    }
+
+    Ok(single_image(width, height, unorm8_format(channels), pixels))
+}
+
+#[cfg(test)]
+mod test {
+    use device::attrib::IntNormalized;
+    use device::tex;
+    use super::{load, load_tga, UnexpectedEnd, CorruptData, KTX_MAGIC, PNG_SIGNATURE};
+
+    fn push_u32(v: &mut Vec<u8>, x: u32) {
+        for i in range(0u, 4) {
+            v.push((x >> (i * 8)) as u8);
+        }
+    }
+
+    #[test]
+    fn test_dds_mip_chain() {
+        let mut v = Vec::new();
+        push_u32(&mut v, 0x20534444);
+        push_u32(&mut v, 124);
+        push_u32(&mut v, 0x20000); // mipmap count
+        push_u32(&mut v, 4);       // height
+        push_u32(&mut v, 4);       // width
+        push_u32(&mut v, 0);
+        push_u32(&mut v, 0);
+        push_u32(&mut v, 3);       // levels
+        for _ in range(0u, 11) { push_u32(&mut v, 0); }
+        push_u32(&mut v, 32);
+        push_u32(&mut v, 0x40 | 0x1); // RGB with alpha
+        push_u32(&mut v, 0);
+        push_u32(&mut v, 32);
+        push_u32(&mut v, 0xFF0000);
+        push_u32(&mut v, 0xFF00);
+        push_u32(&mut v, 0xFF);
+        push_u32(&mut v, 0xFF000000);
+        for _ in range(0u, 5) { push_u32(&mut v, 0); }
+        assert_eq!(v.len(), 128);
+        for i in range(0u, 16 + 4 + 1) {
+            v.push_all(&[i as u8, 1, 2, 3]);
+        }
+
+        let data = load(v.as_slice()).unwrap();
+        assert_eq!(data.info.kind, tex::Texture2D);
+        assert_eq!(data.info.format, tex::RGBA8);
+        assert_eq!(data.info.levels, 3);
+        assert_eq!(data.images.len(), 3);
+        let sizes: Vec<(u16, uint)> = data.images.iter()
+            .map(|im| (im.info.width, im.data.len())).collect();
+        assert_eq!(sizes, vec![(4, 64), (2, 16), (1, 4)]);
+        // BGRA is swizzled into RGBA
+        assert_eq!(data.images[2].data, vec![2, 1, 20, 3]);
+
+        v.pop();
+        assert_eq!(load(v.as_slice()), Err(UnexpectedEnd));
+
+        // without DDPF_ALPHAPIXELS the alpha byte is padding
+        v.push(3);
+        *v.get_mut(80).unwrap() = 0x40;
+        let data = load(v.as_slice()).unwrap();
+        assert_eq!(data.info.format, tex::RGBA8);
+        assert_eq!(data.images[2].data, vec![2, 1, 20, 0xFF]);
+    }
+
+    #[test]
+    fn test_ktx_row_padding() {
+        let mut v = KTX_MAGIC.to_vec();
+        for &x in [0x04030201, 0x1401, 1, 0x1907, 0x8051, 0x1907,
+                   3, 2, 0, 0, 1, 1, 0].iter() {
+            push_u32(&mut v, x);
+        }
+        push_u32(&mut v, 24);
+        for row in range(0u8, 2) {
+            v.push_all(&[row, row, row, 1, 2, 3, 4, 5, 6, 0xFF, 0xFF, 0xFF]);
+        }
+
+        let data = load(v.as_slice()).unwrap();
+        assert_eq!(data.info.kind, tex::Texture2D);
+        assert_eq!(data.info.format, tex::Unsigned(tex::RGB, 8, IntNormalized));
+        assert_eq!(data.images.len(), 1);
+        assert_eq!(data.images[0].data, vec![0, 0, 0, 1, 2, 3, 4, 5, 6,
+                                             1, 1, 1, 1, 2, 3, 4, 5, 6]);
+    }
+
+    fn push_u32_be(v: &mut Vec<u8>, x: u32) {
+        for i in range(0u, 4).rev() {
+            v.push((x >> (i * 8)) as u8);
+        }
+    }
+
+    /// Make a PNG image from unfiltered scanlines, stored without compression.
+    fn make_png(width: u32, height: u32, depth: u8, color: u8,
+                transparency: &[u8], raw: &[u8]) -> Vec<u8> {
+        let mut zlib = vec![0x78, 0x01, 0x01, raw.len() as u8, 0, !(raw.len() as u8), 0xFF];
+        zlib.push_all(raw);
+        let (mut a, mut b) = (1u32, 0u32);
+        for &x in raw.iter() {
+            a = (a + x as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        push_u32_be(&mut zlib, (b << 16) | a);
+
+        let mut v = PNG_SIGNATURE.to_vec();
+        push_u32_be(&mut v, 13);
+        push_u32_be(&mut v, 0x49484452);
+        push_u32_be(&mut v, width);
+        push_u32_be(&mut v, height);
+        v.push_all(&[depth, color, 0, 0, 0]);
+        push_u32_be(&mut v, 0);
+        if !transparency.is_empty() {
+            push_u32_be(&mut v, transparency.len() as u32);
+            push_u32_be(&mut v, 0x74524E53);
+            v.push_all(transparency);
+            push_u32_be(&mut v, 0);
+        }
+        push_u32_be(&mut v, zlib.len() as u32);
+        push_u32_be(&mut v, 0x49444154);
+        v.push_all(zlib.as_slice());
+        push_u32_be(&mut v, 0);
+        push_u32_be(&mut v, 0);
+        push_u32_be(&mut v, 0x49454E44);
+        push_u32_be(&mut v, 0);
+        v
+    }
+
+    #[test]
+    fn test_png_filters() {
+        // scanlines using the Sub and Up filters
+        let raw = [1u8, 10, 20, 30, 5, 5, 5, 2, 2, 2, 2, 2, 2, 2];
+        let data = load(make_png(2, 2, 8, 2, &[], &raw).as_slice()).unwrap();
+        assert_eq!(data.info.format, tex::Unsigned(tex::RGB, 8, IntNormalized));
+        assert_eq!(data.images[0].data, vec![10, 20, 30, 15, 25, 35,
+                                             12, 22, 32, 17, 27, 37]);
+    }
+
+    #[test]
+    fn test_png_transparency() {
+        // 2-bit gray samples 0, 1 and 3, with 1 being transparent
+        let data = load(make_png(3, 1, 2, 0, &[0, 1], &[0, 0x1C]).as_slice()).unwrap();
+        assert_eq!(data.info.format, tex::Unsigned(tex::RG, 8, IntNormalized));
+        assert_eq!(data.images[0].data, vec![0, 0xFF, 85, 0, 0xFF, 0xFF]);
+
+        let raw = [0u8, 1, 2, 3, 4, 5, 6];
+        let data = load(make_png(2, 1, 8, 2, &[0, 4, 0, 5, 0, 6], &raw).as_slice()).unwrap();
+        assert_eq!(data.info.format, tex::Unsigned(tex::RGBA, 8, IntNormalized));
+        assert_eq!(data.images[0].data, vec![1, 2, 3, 0xFF, 4, 5, 6, 0]);
+        assert_eq!(load(make_png(2, 1, 8, 2, &[0, 4], &raw).as_slice()), Err(CorruptData));
+    }
+
+    #[test]
+    fn test_tga_rle_bottom_up() {
+        let mut v = vec![0u8, 0, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 2, 0, 24, 0];
+        v.push_all(&[0x81, 1, 2, 3, 0x01, 4, 5, 6, 7, 8, 9]);
+
+        let data = load_tga(v.as_slice()).unwrap();
+        assert_eq!(data.info.format, tex::Unsigned(tex::RGB, 8, IntNormalized));
+        assert_eq!(data.images[0].data, vec![6, 5, 4, 9, 8, 7, 3, 2, 1, 3, 2, 1]);
+    }
+
+    #[test]
+    fn test_tga_unused_map() {
+        // true-color images with a map of 0 and 8 bit entries
+        for &map_bits in [0u8, 8].iter() {
+            let mut v = vec![0u8, 1, 2, 0, 0, 2, 0, map_bits, 0, 0, 0, 0, 1, 0, 1, 0, 32, 0];
+            v.grow(2 * (map_bits as uint / 8), 0xFF);
+            v.push_all(&[1, 2, 3, 4]);
+            let data = load_tga(v.as_slice()).unwrap();
+            assert_eq!(data.images[0].data, vec![3, 2, 1, 4]);
+        }
+        // a color-mapped image with an invalid map
+        let v = vec![0u8, 1, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 8, 0, 0];
+        assert_eq!(load_tga(v.as_slice()).err(), Some(super::UnsupportedFormat));
+    }
+}
@@ -0,0 +1,179 @@
+// Copyright 2014 The Gfx-rs Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Shader hot reloading. A `ProgramWatcher` links a program from shader files
+//! and links it again whenever they are modified. The new program is then
+//! swapped into the batches with `OwnedBatch::set_program`, or by linking
+//! the ref batches with `RefBatch::link_program` and, if they all succeed,
+//! calling `Context::replace_program` and `RefBatch::set_links`. The replaced
+//! program is returned either way, and is left to the caller to delete.
+
+use std::default::Default;
+use std::io::{File, IoError};
+use std::io::fs;
+use std::str;
+
+use device;
+use device::draw::CommandBuffer;
+use device::shade::ShaderSource;
+use {DeviceHelper, ProgramError};
+
+/// An error occurring while loading shader files.
+#[deriving(Clone, PartialEq, Show)]
+pub enum WatchError {
+    /// The file could not be read.
+    ErrorIo(IoError),
+    /// The file has no supported `#version` directive.
+    ErrorVersion(String),
+    /// The shaders failed to compile or link.
+    ErrorProgram(ProgramError),
+}
+
+/// Put the GLSL code in the slot of `ShaderSource` matching its `#version`
+/// directive. Returns `None` if it is missing or not supported.
+pub fn source_from_glsl<'a>(code: &'a [u8]) -> Option<ShaderSource<'a>> {
+    let text = match str::from_utf8(code) {
+        Some(text) => text,
+        None => return None,
+    };
+    let line = match text.lines_any().map(|l| l.trim()).find(|l| l.starts_with("#version")) {
+        Some(line) => line,
+        None => return None,
+    };
+    let mut words = line.slice_from("#version".len()).words();
+    let number: Option<uint> = words.next().and_then(from_str);
+    let es = words.next() == Some("es");
+    let mut src: ShaderSource = Default::default();
+    match (number, es) {
+        (Some(100), _)     => src.glsl_100_es = Some(code),
+        (Some(300), true)  => src.glsl_300_es = Some(code),
+        (Some(120), false) => src.glsl_120 = Some(code),
+        (Some(130), false) => src.glsl_130 = Some(code),
+        (Some(140), false) => src.glsl_140 = Some(code),
+        (Some(150), false) => src.glsl_150 = Some(code),
+        (Some(330), false) => src.glsl_330 = Some(code),
+        (Some(400), false) => src.glsl_400 = Some(code),
+        (Some(410), false) => src.glsl_410 = Some(code),
+        (Some(420), false) => src.glsl_420 = Some(code),
+        (Some(430), false) => src.glsl_430 = Some(code),
+        (Some(440), false) => src.glsl_440 = Some(code),
+        (Some(450), false) => src.glsl_450 = Some(code),
+        _ => return None,
+    }
+    Some(src)
+}
+
+/// A shader file along with its last seen modification time.
+struct WatchedFile {
+    path: Path,
+    modified: u64,
+    code: Vec<u8>,
+}
+
+impl WatchedFile {
+    fn load(path: Path) -> Result<WatchedFile, WatchError> {
+        let mut file = WatchedFile {
+            path: path,
+            modified: 0,
+            code: Vec::new(),
+        };
+        try!(file.reload());
+        Ok(file)
+    }
+
+    fn get_modified(&self) -> Result<u64, WatchError> {
+        fs::stat(&self.path).map(|s| s.modified).map_err(|e| ErrorIo(e))
+    }
+
+    fn reload(&mut self) -> Result<(), WatchError> {
+        let modified = try!(self.get_modified());
+        self.code = try!(File::open(&self.path).read_to_end().map_err(|e| ErrorIo(e)));
+        self.modified = modified;
+        Ok(())
+    }
+
+    fn get_source(&self) -> Result<ShaderSource, WatchError> {
+        source_from_glsl(self.code.as_slice())
+            .ok_or(ErrorVersion(self.path.display().to_string()))
+    }
+}
+
+/// Links a program from a pair of shader files, and again when they change.
+pub struct ProgramWatcher {
+    vertex: WatchedFile,
+    fragment: WatchedFile,
+}
+
+impl ProgramWatcher {
+    /// Load the shader files and link the initial program.
+    pub fn new<C: CommandBuffer, D: device::Device<C>>(device: &mut D,
+               vs_path: Path, fs_path: Path)
+               -> Result<(ProgramWatcher, device::ProgramHandle), WatchError> {
+        let watcher = ProgramWatcher {
+            vertex: try!(WatchedFile::load(vs_path)),
+            fragment: try!(WatchedFile::load(fs_path)),
+        };
+        let program = try!(watcher.link(device));
+        Ok((watcher, program))
+    }
+
+    fn link<C: CommandBuffer, D: device::Device<C>>(&self, device: &mut D)
+            -> Result<device::ProgramHandle, WatchError> {
+        let vs_src = try!(self.vertex.get_source());
+        let fs_src = try!(self.fragment.get_source());
+        device.link_program(vs_src, fs_src).map_err(|e| ErrorProgram(e))
+    }
+
+    /// Check the files for modification, and link the program again if any
+    /// of them changed. Returns `None` if nothing changed. On error the
+    /// previous program is still valid, and the link is only attempted again
+    /// after the next modification.
+    pub fn poll<C: CommandBuffer, D: device::Device<C>>(&mut self, device: &mut D)
+                -> Option<Result<device::ProgramHandle, WatchError>> {
+        let mut changed = false;
+        for file in [&mut self.vertex, &mut self.fragment].iter_mut() {
+            match file.get_modified() {
+                Ok(modified) if modified == file.modified => (),
+                // the file may be in the middle of being saved
+                Err(_) => (),
+                Ok(_) => match file.reload() {
+                    Ok(()) => changed = true,
+                    Err(e) => return Some(Err(e)),
+                },
+            }
+        }
+        if changed {
+            Some(self.link(device))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::default::Default;
+    use device::shade::ShaderSource;
+    use super::source_from_glsl;
+
+    #[test]
+    fn test_source_from_glsl() {
+        let code = b"// lighting\n#version 150 core\nvoid main() {}";
+        assert_eq!(source_from_glsl(code), Some(ShaderSource { glsl_150: Some(code), ..Default::default() }));
+        let code = b"#version 300 es\nvoid main() {}";
+        assert_eq!(source_from_glsl(code), Some(ShaderSource { glsl_300_es: Some(code), ..Default::default() }));
+        assert_eq!(source_from_glsl(b"#version 310 es\n"), None);
+        assert_eq!(source_from_glsl(b"void main() {}"), None);
+    }
+}
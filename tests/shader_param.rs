@@ -17,8 +17,27 @@
 #[phase(plugin)]
 extern crate gfx_macros;
 
+use secret_lib::device::shade;
+use secret_lib::gfx::shade::{ShaderParam, ParamValues, MissingUniform, ArraySizeMismatch};
+use secret_lib::gfx::shade::{UniformElements, UniformComposite};
+
 mod secret_lib;
 
+const NUM_LIGHTS: uint = 4;
+const NUM_WEIGHTS: uint = 3;
+
+#[repr(C)]
+#[uniform_block]
+struct TestBlock {
+    color: [f32, ..4],
+}
+
+#[shader_param(MaterialBatch)]
+struct Material {
+    color: [f32, ..4],
+    maps: [secret_lib::gfx::shade::TextureParam, ..2],
+}
+
 // Test all features
 #[shader_param(TestBatch)]
 struct TestParam {
@@ -29,6 +48,18 @@ struct TestParam {
     e: f32,
     #[name = "a_f"]
     f: [f32, ..4],
+    g: [[f32, ..4], ..NUM_LIGHTS],
+    h: [f32, ..8],
+    i: [[[f64, ..3], ..3], ..2],
+    j: secret_lib::gfx::BufferHandle<TestBlock>,
+    k: Option<f32>,
+    l: [secret_lib::gfx::shade::TextureParam, ..NUM_LIGHTS],
+    #[nested]
+    m: Material,
+    #[nested]
+    #[name = "u_Back."]
+    n: Material,
+    o: [[f32, ..3], ..2],
 }
 
 // Test that there are no conflicts between the two reexport modules
@@ -38,9 +69,179 @@ struct TestParam2 {
     b: secret_lib::gfx::shade::TextureParam,
 }
 
+#[deriving(Clone, Show)]
+#[shader_param(LightBatch)]
+struct Light {
+    intensity: f32,
+    radius: Option<f32>,
+}
+
+// Test that the links of nested structures follow the deriving
+#[deriving(Clone, Show)]
+#[shader_param(SpotBatch)]
+struct Spot {
+    angle: f32,
+    #[nested]
+    light: Light,
+}
+
+#[shader_param(SceneBatch)]
+struct Scene {
+    exposure: Option<f32>,
+    weights: [f32, ..NUM_WEIGHTS],
+    #[nested]
+    sun: Light,
+    #[nested]
+    lights: [Light, ..NUM_LIGHTS],
+}
+
 #[test]
 fn test_shader_param() {
     // testing if the types are visible
     let _ref: |&TestRefBatch|;
     let _owned: |&TestOwnedBatch|;
+    let _spot: |&SpotBatch|;
+}
+
+fn program(uniforms: &[&str]) -> shade::ProgramInfo {
+    shade::ProgramInfo {
+        attributes: Vec::new(),
+        uniforms: uniforms.iter().enumerate().map(|(i, name)| shade::UniformVar {
+            name: name.to_string(),
+            location: i as shade::Location,
+            count: 1,
+            base_type: shade::BaseF32,
+            container: shade::Single,
+        }).collect(),
+        blocks: Vec::new(),
+        textures: Vec::new(),
+        images: Vec::new(),
+        storage_blocks: Vec::new(),
+    }
+}
+
+#[test]
+fn test_link_scene() {
+    let info = program(&["exposure", "weights[0]", "weights[1]", "sun.intensity",
+        "lights[0].intensity", "lights[1].intensity", "lights[1].radius"]);
+    let link = ShaderParam::create_link(None::<&Scene>, &info).unwrap();
+    let light = |intensity: f32, radius: Option<f32>| Light {
+        intensity: intensity,
+        radius: radius,
+    };
+    let scene = Scene {
+        exposure: None,
+        weights: [1.0, 2.0, 3.0],
+        sun: light(4.0, None),
+        lights: [light(5.0, Some(6.0)), light(7.0, Some(8.0)),
+                 light(9.0, None), light(10.0, None)],
+    };
+    let (mut uniforms, mut blocks, mut textures, mut images, mut storage_blocks) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    scene.fill_params(&link, ParamValues {
+        uniforms: &mut uniforms,
+        blocks: &mut blocks,
+        textures: &mut textures,
+        images: &mut images,
+        storage_blocks: &mut storage_blocks,
+    });
+    assert_eq!(uniforms, vec![
+        None,
+        Some(shade::ValueF32(1.0)),
+        Some(shade::ValueF32(2.0)),
+        Some(shade::ValueF32(4.0)),
+        Some(shade::ValueF32(5.0)),
+        Some(shade::ValueF32(7.0)),
+        Some(shade::ValueF32(8.0)),
+    ]);
+}
+
+#[test]
+fn test_link_scene_missing() {
+    let info = program(&["sun.intensity", "lights[0].colour"]);
+    match ShaderParam::create_link(None::<&Scene>, &info) {
+        Err(MissingUniform(ref name)) => assert_eq!(name.as_slice(), "lights[0].colour"),
+        _ => panic!("The unknown uniform is not reported"),
+    }
+}
+
+#[test]
+fn test_link_texture_array() {
+    let mut info = program(&["color"]);
+    info.uniforms.get_mut(0).unwrap().container = shade::Vector(4);
+    info.textures = ["maps[0]", "maps[1]"].iter().enumerate().map(|(i, name)| shade::SamplerVar {
+        name: name.to_string(),
+        location: i as shade::Location,
+        base_type: shade::BaseF32,
+        sampler_type: shade::Sampler2D(shade::NoArray, shade::NoShadow,
+                                       shade::NoMultiSample, shade::NoRect),
+    }).collect();
+    let link = ShaderParam::create_link(None::<&Material>, &info).unwrap();
+    assert_eq!(link.color, Some(UniformComposite(0)));
+    assert_eq!(link.maps, Some((0, 2)));
+}
+
+#[shader_param(ArraysBatch)]
+struct Arrays {
+    lights: [[f32, ..4], ..NUM_LIGHTS],
+    columns: [[f32, ..3], ..2],
+}
+
+fn fill_arrays(arrays: &Arrays, link: &_ArraysLink) -> Vec<Option<shade::UniformValue>> {
+    let (mut uniforms, mut blocks, mut textures, mut images, mut storage_blocks) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    arrays.fill_params(link, ParamValues {
+        uniforms: &mut uniforms,
+        blocks: &mut blocks,
+        textures: &mut textures,
+        images: &mut images,
+        storage_blocks: &mut storage_blocks,
+    });
+    uniforms
+}
+
+#[test]
+fn test_link_uniform_arrays() {
+    let arrays = Arrays {
+        lights: [[1.0, ..4], [2.0, ..4], [3.0, ..4], [4.0, ..4]],
+        columns: [[5.0, ..3], [6.0, ..3]],
+    };
+    // a constant length, and a literal one, both as shader arrays
+    let mut info = program(&["lights[0]", "lights[1]", "columns[0]", "columns[1]"]);
+    for var in info.uniforms.iter_mut() {
+        var.container = shade::Vector(if var.name.as_slice().starts_with("lights") {4} else {3});
+    }
+    let link = ShaderParam::create_link(None::<&Arrays>, &info).unwrap();
+    assert_eq!(link.lights, Some(UniformElements(0, 2)));
+    assert_eq!(link.columns, Some(UniformElements(2, 2)));
+    assert_eq!(fill_arrays(&arrays, &link), vec![
+        Some(shade::ValueF32Vector4([1.0, ..4])),
+        Some(shade::ValueF32Vector4([2.0, ..4])),
+        Some(shade::ValueF32Vector3([5.0, ..3])),
+        Some(shade::ValueF32Vector3([6.0, ..3])),
+    ]);
+    // the same fields as matrices
+    let mut info = program(&["columns"]);
+    info.uniforms.get_mut(0).unwrap().container = shade::Matrix(shade::ColumnMajor, 2, 3);
+    let link = ShaderParam::create_link(None::<&Arrays>, &info).unwrap();
+    assert_eq!(link.lights, None);
+    assert_eq!(link.columns, Some(UniformComposite(0)));
+    assert_eq!(fill_arrays(&arrays, &link), vec![
+        Some(shade::ValueF32Matrix2x3([[5.0, ..3], [6.0, ..3]])),
+    ]);
+}
+
+#[test]
+fn test_link_uniform_array_too_long() {
+    let info = program(&["lights[0]", "lights[1]", "lights[2]", "lights[3]", "lights[4]"]);
+    match ShaderParam::create_link(None::<&Arrays>, &info) {
+        Err(ArraySizeMismatch(ref name)) => assert_eq!(name.as_slice(), "lights[4]"),
+        _ => panic!("The array overflow is not reported"),
+    }
+    let mut info = program(&["columns"]);
+    info.uniforms.get_mut(0).unwrap().container = shade::Matrix(shade::ColumnMajor, 3, 3);
+    match ShaderParam::create_link(None::<&Arrays>, &info) {
+        Err(ArraySizeMismatch(ref name)) => assert_eq!(name.as_slice(), "columns"),
+        _ => panic!("The matrix size mismatch is not reported"),
+    }
 }
@@ -0,0 +1,3 @@
+vec3 light() {
+    return vec3(AMBIENT);
+}
@@ -76,3 +76,14 @@ GLSL_150: b"
 
 static _SRC4: ShaderSource<'static> = shaders! {
 };
+
+static _SRC5: ShaderSource<'static> = shaders! {
+GLSL_150: preprocess!("shaders", b"
+    #version 150 core
+    #include \"light.glsl\"
+    out vec4 o_Color;
+    void main() {
+        o_Color = vec4(light(), 1.0);
+    }
+", "AMBIENT 0.1")
+};
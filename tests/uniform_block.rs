@@ -0,0 +1,67 @@
+// Copyright 2014 The Gfx-rs Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![feature(phase)]
+
+#[phase(plugin)]
+extern crate gfx_macros;
+
+mod secret_lib;
+
+const NUM_LIGHTS: uint = 2;
+
+// Test all features
+#[repr(C)]
+#[uniform_block]
+struct Camera {
+    view: [[f32, ..4], ..4],
+    position: [f32, ..3],
+    near: f32,
+    #[name = "u_Far"]
+    far: f32,
+    _pad0: f32,
+    _pad1: f32,
+    _pad2: f32,
+    lights: [[f32, ..4], ..NUM_LIGHTS],
+    normal: [secret_lib::gfx::shade::PaddedVec3<f32>, ..3],
+    corners: [secret_lib::gfx::shade::PaddedVec3<f32>, ..NUM_LIGHTS],
+}
+
+// Test that there are no conflicts between the two reexport modules
+#[repr(C)]
+#[uniform_block]
+struct Light {
+    color: [f32, ..4],
+}
+
+#[test]
+fn test_uniform_block() {
+    use secret_lib::device::shade::{Vector, Matrix, ColumnMajor};
+    use secret_lib::gfx::shade;
+    use secret_lib::gfx::shade::UniformBlock;
+
+    let members = UniformBlock::get_members(None::<&Camera>);
+    assert_eq!(members.iter().map(|m| (m.name.as_slice(), m.offset, m.count))
+                      .collect::<Vec<_>>(),
+               vec![("view", 0, 1), ("position", 64, 1), ("near", 76, 1),
+                    ("u_Far", 80, 1), ("_pad0", 84, 1), ("_pad1", 88, 1),
+                    ("_pad2", 92, 1), ("lights", 96, 2), ("normal", 128, 1),
+                    ("corners", 176, 2)]);
+    assert_eq!(shade::std140_layout(members.as_slice()), members);
+    assert_eq!((members[8].container, members[8].matrix_stride), (Matrix(ColumnMajor, 3, 3), 16));
+    assert_eq!((members[9].container, members[9].array_stride), (Vector(3), 16));
+
+    let light = UniformBlock::get_members(None::<&Light>);
+    assert_eq!(light[0].container, Vector(4));
+}